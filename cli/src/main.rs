@@ -1,14 +1,19 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::engine::{
+    ArgValueCompleter, CompletionCandidate, PathCompleter, ValueCompleter,
+};
+use clap_complete::env::EnvCompleter;
+use clap_complete::CompleteEnv;
 use colored::*;
 use dialoguer::{theme::ColorfulTheme, Confirm, Input};
 use rand::Rng;
-use semver::Version;
+use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::env;
+use std::ffi::OsStr;
 use std::fs;
-use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::thread;
@@ -54,12 +59,34 @@ struct EnvInfo {
     binary_os: Option<String>,
     #[serde(default)]
     binary_arch: Option<String>,
+    /// SHA-256 of the release asset, verified against the release checksums at download
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    release_sha256: Option<String>,
+    /// Version range or channel the binary was resolved from (e.g. `^0.8`, `latest`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    binary_spec: Option<String>,
+    /// Binary was chosen explicitly with --binary rather than inherited from the global default.
+    /// Always written, so entries from older versions can be told apart by its absence.
+    #[serde(default)]
+    binary_pinned: bool,
+    /// SHA-256 of the executable at `binary` when it was pinned, checked before start/login
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    binary_sha256: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 struct GlobalConfig {
     #[serde(default)]
     default_binary: Option<String>, // path or version
+    /// Where syftbox releases come from (github:<api url>, mirror:<url>, file://<dir>)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    release_source: Option<String>,
+    /// How often a failed download is retried (default 3)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    download_retries: Option<u32>,
+    /// Seconds GitHub release metadata is reused before asking the API again (default 3600)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    release_cache_ttl: Option<u64>,
 }
 
 #[derive(Parser)]
@@ -83,12 +110,21 @@ enum Commands {
         /// Enable development mode defaults
         #[arg(long, default_value_t = false)]
         dev: bool,
-        /// Specify syftbox binary (path) or version (e.g. 0.8.5)
-        #[arg(long)]
+        /// Specify syftbox binary: path, version (0.8.5), range (^0.8, latest), release archive or git:<repo>@<ref>
+        #[arg(long, add = ArgValueCompleter::new(complete_binary_spec))]
         binary: Option<String>,
         /// Run in quiet mode, automatically accepting defaults
         #[arg(short, long, default_value_t = false)]
         quiet: bool,
+        /// Install a downloaded binary without checking the release checksums
+        #[arg(long)]
+        insecure_skip_verify: bool,
+        /// Also make --binary the global default for new environments
+        #[arg(long, requires = "binary")]
+        make_default: bool,
+        /// Fetch the --binary version for another platform, e.g. linux/arm64
+        #[arg(long, requires = "binary", value_parser = Target::parse)]
+        target: Option<Target>,
     },
     /// Edit current environment settings (server URL, dev mode)
     Edit {
@@ -98,30 +134,49 @@ enum Commands {
         /// Toggle development mode on/off
         #[arg(long)]
         dev: Option<bool>,
-        /// Change syftbox binary (path) or version
-        #[arg(long)]
+        /// Change syftbox binary: path, version (0.8.5), range (^0.8, latest), release archive or git:<repo>@<ref>
+        #[arg(long, add = ArgValueCompleter::new(complete_binary_spec))]
         binary: Option<String>,
+        /// Install a downloaded binary without checking the release checksums
+        #[arg(long)]
+        insecure_skip_verify: bool,
+        /// Also make --binary the global default for new environments
+        #[arg(long, requires = "binary")]
+        make_default: bool,
     },
     /// Display information about the current environment
     Info,
     /// Activate the SyftBox environment (outputs shell commands to eval)
     Activate {
+        /// Environment to activate: email, directory name or path (default: current directory)
+        #[arg(add = ArgValueCompleter::new(complete_env_selector))]
+        env: Option<String>,
+        /// Also change to the environment's directory
+        #[arg(long)]
+        cd: bool,
         /// Write activation script to a file instead of stdout
         #[arg(short, long)]
         write_to: Option<PathBuf>,
         /// Suppress instructional comments (for shell function use)
         #[arg(short, long)]
         quiet: bool,
+        /// Shell syntax to emit (default: from $SHELL)
+        #[arg(long, value_enum)]
+        shell: Option<Shell>,
     },
     /// Deactivate the SyftBox environment (outputs shell commands to eval)
     Deactivate {
         /// Suppress instructional comments (for shell function use)
         #[arg(short, long)]
         quiet: bool,
+        /// Shell syntax to emit (default: from $SHELL)
+        #[arg(long, value_enum)]
+        shell: Option<Shell>,
     },
     /// Remove a SyftBox environment
     Remove {
         /// Path to the environment to remove (defaults to current directory)
+        #[arg(add = ArgValueCompleter::new(complete_env_paths))]
         path: Option<PathBuf>,
     },
     /// Install shell functions for easier activation/deactivation
@@ -130,6 +185,12 @@ enum Commands {
         #[arg(short, long)]
         manual: bool,
     },
+    /// Remove the shell functions installed by install-shell
+    UninstallShell {
+        /// Put back the rc file as it was before install-shell last changed it
+        #[arg(long)]
+        restore_backup: bool,
+    },
     /// Start the SyftBox daemon in the background
     Start {
         /// Force start even if another instance is running
@@ -141,6 +202,9 @@ enum Commands {
         /// Run syftbox in daemon mode with control plane HTTP API. Default: on.
         #[arg(long, default_value_t = true)]
         daemon: bool,
+        /// Refuse to start if the pinned binary's SHA-256 changed
+        #[arg(long)]
+        strict: bool,
     },
     /// Stop the running SyftBox daemon
     Stop,
@@ -158,7 +222,11 @@ enum Commands {
         follow: bool,
     },
     /// Login to SyftBox
-    Login,
+    Login {
+        /// Refuse to log in if the pinned binary's SHA-256 changed
+        #[arg(long)]
+        strict: bool,
+    },
     /// List all SyftBox environments
     List,
     /// Update sbenv to the latest version
@@ -169,12 +237,126 @@ enum Commands {
     },
     /// Execute a command within an sbenv environment
     Exec {
-        /// Email address of the environment to use
+        /// Environment to use: email, directory name or path
+        #[arg(add = ArgValueCompleter::new(complete_env_selector))]
         email: String,
         /// Command and arguments to execute
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         command: Vec<String>,
     },
+    /// Upgrade this environment's syftbox, rolling back if the new binary fails to start
+    Upgrade {
+        /// Version, range or other binary spec to move to (default: the env's range, or latest)
+        #[arg(long, add = ArgValueCompleter::new(complete_binary_spec))]
+        to: Option<String>,
+        /// Install a downloaded binary without checking the release checksums
+        #[arg(long)]
+        insecure_skip_verify: bool,
+    },
+    /// Show the upgrade history of this environment
+    History,
+    /// Remove unused cached syftbox versions and leftovers of interrupted installs
+    Gc {
+        /// Only remove unused versions not modified for at least this many days
+        #[arg(long, default_value_t = 30)]
+        days: u64,
+        /// List what would be removed without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Pin the syftbox binary for this directory tree in a .syftbox-version file
+    Pin {
+        /// Version, range or `latest` to pin; omit to show the current pin
+        #[arg(add = ArgValueCompleter::new(complete_pin_spec))]
+        spec: Option<String>,
+        /// Remove the .syftbox-version file in this directory
+        #[arg(long, conflicts_with = "spec")]
+        unset: bool,
+    },
+    /// Manage cached syftbox binaries
+    Binary {
+        #[command(subcommand)]
+        command: BinaryCommands,
+    },
+    /// Manage the global default syftbox binary used by new environments
+    DefaultBinary {
+        #[command(subcommand)]
+        command: DefaultBinaryCommands,
+    },
+    /// Start a subshell with an environment activated; `exit` leaves it
+    Shell {
+        /// Environment to activate: email, directory name or path (default: current directory)
+        #[arg(long, add = ArgValueCompleter::new(complete_env_selector))]
+        env: Option<String>,
+    },
+    /// Print the completion script for a shell, e.g. `source <(sbenv completions bash)`
+    Completions {
+        #[arg(value_enum)]
+        shell: CompletionShell,
+    },
+}
+
+#[derive(Subcommand)]
+enum DefaultBinaryCommands {
+    /// Set the global default syftbox binary
+    Set {
+        /// Path, version (e.g. 0.8.5), range (^0.8, latest), release archive or git:<repo>@<ref>
+        #[arg(add = ArgValueCompleter::new(complete_binary_spec))]
+        spec: String,
+        /// Install without checking the release checksums
+        #[arg(long)]
+        insecure_skip_verify: bool,
+    },
+    /// Clear the global default; new environments fall back to syftbox on PATH
+    Unset,
+    /// Show the global default syftbox binary
+    Show,
+}
+
+#[derive(Subcommand)]
+enum BinaryCommands {
+    /// List cached syftbox versions and the environments using them
+    List,
+    /// Download a syftbox version into the cache
+    Install {
+        /// Version, range or channel to install (e.g. 0.8.5, ^0.8, latest)
+        #[arg(add = ArgValueCompleter::new(complete_cached_versions))]
+        version: String,
+        /// Install without checking the release checksums
+        #[arg(long)]
+        insecure_skip_verify: bool,
+        /// Platform to fetch it for, e.g. linux/arm64 or darwin/arm64 (default: this machine)
+        #[arg(long, value_parser = Target::parse)]
+        target: Option<Target>,
+    },
+    /// Remove a cached syftbox version
+    Remove {
+        /// Version to remove
+        #[arg(add = ArgValueCompleter::new(complete_cached_versions))]
+        version: String,
+        /// Remove even if environments still use it
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Remove cached versions no environment uses
+    Prune {
+        /// Skip confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Show environments that could move to a newer version matching their range
+    Outdated,
+    /// Show which syftbox binary would be used here
+    Which,
+    /// Set the global default syftbox binary (same as `sbenv default-binary set`)
+    Default {
+        /// Path, version (e.g. 0.8.5), range (^0.8, latest), release archive or git:<repo>@<ref>
+        #[arg(add = ArgValueCompleter::new(complete_binary_spec))]
+        spec: String,
+        /// Install without checking the release checksums
+        #[arg(long)]
+        insecure_skip_verify: bool,
+    },
 }
 
 fn get_registry_path() -> PathBuf {
@@ -195,10 +377,85 @@ fn load_registry() -> Result<EnvRegistry> {
         });
     }
     let content = fs::read_to_string(&registry_path)?;
-    let registry: EnvRegistry = serde_json::from_str(&content)?;
+    let mut registry: EnvRegistry = serde_json::from_str(&content)?;
+    if migrate_binary_pins(&mut registry, &content) {
+        // Persist the result so later loads don't re-derive it from a default that may
+        // have changed since; a read-only registry just migrates again next time
+        let _ = save_registry(&registry);
+    }
     Ok(registry)
 }
 
+/// Entries written before `binary_pinned` existed have no such key; work out whether their
+/// binary was set explicitly so a `.syftbox-version` does not override it. Returns whether
+/// any entry was migrated.
+fn migrate_binary_pins(registry: &mut EnvRegistry, content: &str) -> bool {
+    let Ok(raw) = serde_json::from_str::<serde_json::Value>(content) else {
+        return false;
+    };
+    let legacy: Vec<String> = registry
+        .environments
+        .keys()
+        .filter(|key| {
+            raw["environments"][key.as_str()]
+                .as_object()
+                .is_some_and(|e| !e.contains_key("binary_pinned"))
+        })
+        .cloned()
+        .collect();
+    if legacy.is_empty() {
+        return false;
+    }
+    let inherited = InheritedBinary::current();
+    for key in legacy {
+        if let Some(info) = registry.environments.get_mut(&key) {
+            info.binary_pinned = inherited.is_pinned(info);
+        }
+    }
+    true
+}
+
+/// What an environment got when it was set up without --binary: the global default, or
+/// syftbox from PATH
+struct InheritedBinary {
+    default_spec: Option<String>,
+    default_version: Option<String>,
+    paths: Vec<PathBuf>,
+}
+
+impl InheritedBinary {
+    fn current() -> Self {
+        let default_spec = load_global_config().default_binary;
+        let default_version = default_spec.as_deref().and_then(spec_cached_version);
+        let paths = [
+            default_version.as_deref().and_then(cached_binary_path),
+            default_spec.as_ref().map(PathBuf::from),
+            which_syftbox(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        InheritedBinary {
+            default_spec,
+            default_version,
+            paths,
+        }
+    }
+
+    /// Older sbenv stored either the binary passed with --binary or the inherited one, so
+    /// any other binary must have been chosen explicitly
+    fn is_pinned(&self, info: &EnvInfo) -> bool {
+        match (&info.binary, &info.binary_version) {
+            (Some(bin), _) => !self.paths.iter().any(|p| p == Path::new(bin)),
+            (None, Some(ver)) => {
+                self.default_spec.as_deref() != Some(ver.as_str())
+                    && self.default_version.as_deref() != Some(ver.as_str())
+            }
+            (None, None) => false,
+        }
+    }
+}
+
 fn save_registry(registry: &EnvRegistry) -> Result<()> {
     let registry_path = get_registry_path();
     if let Some(parent) = registry_path.parent() {
@@ -264,6 +521,61 @@ fn generate_env_key(path: &Path, email: &str) -> String {
     format!("{}@{}", email, path_str)
 }
 
+/// Find a registered environment by email, directory name, path or registry key
+fn resolve_env_selector(selector: &str) -> Result<EnvInfo> {
+    let registry = load_registry()?;
+    if let Some(info) = registry.environments.get(selector) {
+        return Ok(info.clone());
+    }
+    // A path names exactly one environment, so it wins over emails and names
+    if let Ok(path) = Path::new(selector).canonicalize() {
+        if let Some(info) = registry
+            .environments
+            .values()
+            .find(|info| Path::new(&info.path).canonicalize().ok().as_ref() == Some(&path))
+        {
+            return Ok(info.clone());
+        }
+    }
+    let mut matches: Vec<&EnvInfo> = registry
+        .environments
+        .values()
+        .filter(|info| info.email == selector || info.name == selector)
+        .collect();
+    match matches.len() {
+        0 => Err(anyhow::anyhow!(
+            "No environment matches '{}'. Run 'sbenv list' to see available environments.",
+            selector
+        )),
+        1 => Ok(matches[0].clone()),
+        _ => {
+            matches.sort_by(|a, b| a.path.cmp(&b.path));
+            let paths: Vec<String> = matches.iter().map(|i| format!("  {}", i.path)).collect();
+            Err(anyhow::anyhow!(
+                "'{}' matches several environments; pass one of these paths instead:\n{}",
+                selector,
+                paths.join("\n")
+            ))
+        }
+    }
+}
+
+/// config.json of the selected environment, or of the one around the current directory
+fn selected_config_path(selector: Option<&str>) -> Result<PathBuf> {
+    let Some(selector) = selector else {
+        let current_dir = env::current_dir().context("Failed to get current directory")?;
+        return find_syftbox_config(&current_dir).ok_or_else(|| {
+            anyhow::anyhow!("No SyftBox environment found in current directory or parents")
+        });
+    };
+    let info = resolve_env_selector(selector)?;
+    let config_path = Path::new(&info.path).join(".syftbox").join("config.json");
+    if !config_path.exists() {
+        anyhow::bail!("Environment config not found at {}", config_path.display());
+    }
+    Ok(config_path)
+}
+
 fn register_environment(path: &Path, config: &SyftBoxConfig) -> Result<()> {
     let mut registry = load_registry()?;
 
@@ -303,6 +615,10 @@ fn register_environment(path: &Path, config: &SyftBoxConfig) -> Result<()> {
         binary_hash: existing.as_ref().and_then(|e| e.binary_hash.clone()),
         binary_os: existing.as_ref().and_then(|e| e.binary_os.clone()),
         binary_arch: existing.as_ref().and_then(|e| e.binary_arch.clone()),
+        release_sha256: existing.as_ref().and_then(|e| e.release_sha256.clone()),
+        binary_spec: existing.as_ref().and_then(|e| e.binary_spec.clone()),
+        binary_pinned: existing.as_ref().is_some_and(|e| e.binary_pinned),
+        binary_sha256: existing.as_ref().and_then(|e| e.binary_sha256.clone()),
     };
 
     registry.environments.insert(key, env_info);
@@ -382,6 +698,15 @@ fn ensure_marker_exists(config_path: &Path, config: &SyftBoxConfig) -> Result<()
         if let Some(a) = &info.binary_arch {
             obj["binary_arch"] = serde_json::json!(a);
         }
+        if let Some(d) = &info.release_sha256 {
+            obj["release_sha256"] = serde_json::json!(d);
+        }
+        if let Some(spec) = &info.binary_spec {
+            obj["binary_spec"] = serde_json::json!(spec);
+        }
+        if let Some(sha) = &info.binary_sha256 {
+            obj["binary_sha256"] = serde_json::json!(sha);
+        }
     }
     let content = serde_json::to_string_pretty(&obj)? + "\n";
     fs::write(&marker, content)?;
@@ -393,6 +718,80 @@ fn get_binaries_dir() -> PathBuf {
     Path::new(&home).join(".sbenv").join("binaries")
 }
 
+fn get_locks_dir() -> PathBuf {
+    let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    Path::new(&home).join(".sbenv").join("locks")
+}
+
+/// Exclusive lock on one binaries cache entry; released when dropped
+struct CacheLock {
+    _file: fs::File,
+}
+
+/// Lock a cache entry so parallel sbenv runs install it only once.
+/// Blocks until any other process holding the lock is done.
+fn lock_cache_entry(name: &str, quiet: bool) -> Result<CacheLock> {
+    use fs2::FileExt;
+    let dir = get_locks_dir();
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.lock", name));
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    if file.try_lock_exclusive().is_err() {
+        if !quiet {
+            eprintln!(
+                "   Waiting for another sbenv to finish installing {}...",
+                name.cyan()
+            );
+        }
+        file.lock_exclusive()
+            .with_context(|| format!("Failed to lock {}", path.display()))?;
+    }
+    Ok(CacheLock { _file: file })
+}
+
+/// Fresh staging directory inside the binaries cache, so the final rename stays on
+/// one filesystem
+fn new_staging_dir() -> Result<PathBuf> {
+    let dir = get_binaries_dir().join(format!("_tmp-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Atomically move a fully staged entry into `binaries/<name>`. Callers hold the
+/// entry's lock. If the directory already exists (e.g. it holds builds for other
+/// platforms), the staged files are moved in with the binary last, so a present
+/// `syftbox` always means a complete entry.
+fn publish_cache_entry(staged_dir: &Path, name: &str) -> Result<PathBuf> {
+    let dest = get_binaries_dir().join(name);
+    let bin_path = dest.join("syftbox");
+    if bin_path.exists() {
+        return Err(anyhow::anyhow!("syftbox {} is already cached", name));
+    }
+    let moved = if dest.exists() {
+        fs::read_dir(staged_dir)?
+            .flatten()
+            .filter(|e| e.file_name() != "syftbox")
+            .chain(
+                fs::read_dir(staged_dir)?
+                    .flatten()
+                    .filter(|e| e.file_name() == "syftbox"),
+            )
+            .try_for_each(|e| fs::rename(e.path(), dest.join(e.file_name())))
+    } else {
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(staged_dir, &dest)
+    };
+    moved.with_context(|| format!("Failed to move syftbox {} into the cache", name))?;
+    Ok(bin_path)
+}
+
 fn parse_syftbox_version_output(output: &str) -> Option<String> {
     // Expected: syftbox version 0.8.5 (...)
     let lower = output.trim();
@@ -495,2906 +894,8076 @@ fn get_cached_syftbox_versions() -> Vec<String> {
     }
 
     // Sort versions in reverse order (newest first)
+    // Releases first (newest first), then source builds such as git-<sha>
     versions.sort_by(|a, b| match (Version::parse(a), Version::parse(b)) {
         (Ok(va), Ok(vb)) => vb.cmp(&va),
-        _ => b.cmp(a),
+        (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+        (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+        _ => a.cmp(b),
     });
 
     versions
 }
 
-fn fetch_latest_syftbox_version() -> Result<String> {
-    let url = "https://api.github.com/repos/OpenMined/syftbox/releases/latest";
-    let out = Command::new("curl")
-        .args(["-sL", "-H", "User-Agent: sbenv", url])
-        .output()?;
+/// Errors from fetching and unpacking syftbox release assets
+#[derive(Debug)]
+enum DownloadError {
+    /// The asset does not exist at this location (HTTP 404 or missing file)
+    NotFound {
+        url: String,
+    },
+    /// The server answered with a non-success status
+    Http {
+        url: String,
+        status: u16,
+    },
+    /// The request could not be completed (DNS, TLS, timeout, ...)
+    Network {
+        url: String,
+        source: reqwest::Error,
+    },
+    /// The connection dropped while the body was streaming
+    Interrupted {
+        url: String,
+        source: std::io::Error,
+    },
+    /// The API refused the request because its rate limit is used up
+    RateLimited {
+        url: String,
+        /// When the limit resets, as a unix timestamp
+        reset: Option<i64>,
+    },
+    /// The response body was not what we expected
+    InvalidResponse {
+        url: String,
+        reason: String,
+    },
+    Io(std::io::Error),
+    Archive {
+        asset: String,
+        reason: String,
+    },
+    BinaryNotFound {
+        asset: String,
+    },
+}
 
-    if !out.status.success() {
-        return Err(anyhow::anyhow!(
-            "Failed to fetch latest release info from GitHub"
-        ));
+impl std::fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DownloadError::NotFound { url } => write!(f, "{} not found", url),
+            DownloadError::Http { url, status } => write!(f, "HTTP {} from {}", status, url),
+            DownloadError::Network { url, source } => {
+                write!(f, "request to {} failed: {}", url, source)
+            }
+            DownloadError::Interrupted { url, source } => {
+                write!(f, "download of {} was interrupted: {}", url, source)
+            }
+            DownloadError::RateLimited { url, reset } => {
+                write!(f, "GitHub API rate limit reached for {}", url)?;
+                if let Some(at) = reset.and_then(|r| chrono::DateTime::from_timestamp(r, 0)) {
+                    write!(
+                        f,
+                        " (resets at {})",
+                        at.with_timezone(&chrono::Local).format("%H:%M")
+                    )?;
+                }
+                if github_token().is_none() {
+                    write!(f, "; set GITHUB_TOKEN to raise the limit")?;
+                }
+                Ok(())
+            }
+            DownloadError::InvalidResponse { url, reason } => {
+                write!(f, "unexpected response from {}: {}", url, reason)
+            }
+            DownloadError::Io(e) => write!(f, "I/O error: {}", e),
+            DownloadError::Archive { asset, reason } => {
+                write!(f, "failed to extract {}: {}", asset, reason)
+            }
+            DownloadError::BinaryNotFound { asset } => {
+                write!(f, "syftbox binary not found in {}", asset)
+            }
+        }
     }
+}
 
-    let body = String::from_utf8_lossy(&out.stdout);
-    let v: serde_json::Value = serde_json::from_str(&body)?;
-
-    let tag = v
-        .get("tag_name")
-        .and_then(|t| t.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Could not find tag_name in release"))?;
+impl std::error::Error for DownloadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DownloadError::Network { source, .. } => Some(source),
+            DownloadError::Interrupted { source, .. } => Some(source),
+            DownloadError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
-    // Remove 'v' prefix if present
-    let version = tag.strip_prefix('v').unwrap_or(tag);
+impl From<std::io::Error> for DownloadError {
+    fn from(e: std::io::Error) -> Self {
+        DownloadError::Io(e)
+    }
+}
 
-    Ok(version.to_string())
+impl DownloadError {
+    /// Whether trying the same request again could succeed
+    fn is_retryable(&self) -> bool {
+        match self {
+            DownloadError::Network { .. } | DownloadError::Interrupted { .. } => true,
+            DownloadError::Http { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
 }
 
-fn prompt_for_syftbox_install() -> Result<Option<String>> {
-    println!("{}", "⚠️  SyftBox is not installed in your PATH.".yellow());
-    println!();
+const DEFAULT_DOWNLOAD_RETRIES: u32 = 3;
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+const DOWNLOAD_CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(300);
 
-    // Check for cached versions
-    let cached_versions = get_cached_syftbox_versions();
+fn http_client() -> Result<reqwest::blocking::Client, DownloadError> {
+    reqwest::blocking::Client::builder()
+        .user_agent(concat!("sbenv/", env!("CARGO_PKG_VERSION")))
+        .connect_timeout(DOWNLOAD_CONNECT_TIMEOUT)
+        .timeout(DOWNLOAD_TIMEOUT)
+        .build()
+        .map_err(|e| DownloadError::Network {
+            url: String::new(),
+            source: e,
+        })
+}
 
-    // Try to fetch the latest version
-    let latest_version = fetch_latest_syftbox_version().ok();
+/// How often a failed download is retried: `SBENV_DOWNLOAD_RETRIES`, `download_retries`
+/// in the global config, or 3
+fn download_retries() -> u32 {
+    env::var("SBENV_DOWNLOAD_RETRIES")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .or(load_global_config().download_retries)
+        .unwrap_or(DEFAULT_DOWNLOAD_RETRIES)
+}
 
-    if cached_versions.is_empty() && latest_version.is_none() {
-        println!(
-            "{}",
-            "❌ Could not fetch available versions and no cached versions found.".red()
-        );
-        println!("Please install syftbox manually or ensure internet connectivity.");
-        return Ok(None);
+/// Run a request, retrying transient failures with exponential backoff
+fn with_retries<T>(
+    mut attempt: impl FnMut() -> Result<T, DownloadError>,
+) -> Result<T, DownloadError> {
+    let retries = download_retries();
+    let mut delay = Duration::from_millis(500);
+    let mut failures = 0;
+    loop {
+        match attempt() {
+            Err(e) if e.is_retryable() && failures < retries => {
+                failures += 1;
+                if atty::is(atty::Stream::Stderr) {
+                    eprintln!(
+                        "   {} ({}), retrying in {}s [{}/{}]",
+                        "Download failed".yellow(),
+                        e,
+                        delay.as_secs_f32(),
+                        failures,
+                        retries
+                    );
+                }
+                thread::sleep(delay);
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+            other => return other,
+        }
     }
+}
 
-    let mut options = Vec::new();
+fn http_get(
+    client: &reqwest::blocking::Client,
+    url: &str,
+) -> Result<reqwest::blocking::Response, DownloadError> {
+    send_checked(client.get(url), url)
+}
 
-    // Add latest version if available
-    if let Some(ref latest) = latest_version {
-        options.push(format!("Download latest version ({})", latest));
+fn send_checked(
+    request: reqwest::blocking::RequestBuilder,
+    url: &str,
+) -> Result<reqwest::blocking::Response, DownloadError> {
+    let response = request.send().map_err(|e| DownloadError::Network {
+        url: url.to_string(),
+        source: e,
+    })?;
+    // GitHub answers 403/429 with x-ratelimit-remaining: 0 (or retry-after for its
+    // secondary limits) and a JSON error body that must not be read as data
+    let status = response.status().as_u16();
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+    };
+    if (status == 403 || status == 429)
+        && (header("x-ratelimit-remaining").as_deref() == Some("0")
+            || header("retry-after").is_some())
+    {
+        return Err(DownloadError::RateLimited {
+            url: url.to_string(),
+            reset: header("x-ratelimit-reset").and_then(|r| r.parse().ok()),
+        });
     }
-
-    // Add cached versions
-    for version in &cached_versions {
-        options.push(format!("Use cached version {}", version));
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(DownloadError::NotFound {
+            url: url.to_string(),
+        });
+    }
+    if !response.status().is_success() {
+        return Err(DownloadError::Http {
+            url: url.to_string(),
+            status: response.status().as_u16(),
+        });
     }
+    Ok(response)
+}
 
-    options.push("Skip (I'll install it manually)".to_string());
+/// Token for the GitHub API from `GITHUB_TOKEN` or `GH_TOKEN`
+fn github_token() -> Option<String> {
+    ["GITHUB_TOKEN", "GH_TOKEN"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .map(|t| t.trim().to_string())
+        .find(|t| !t.is_empty())
+}
 
-    println!("What would you like to do?");
-    println!();
+/// GitHub token to send with a request to `url`. Only api.github.com over https gets it,
+/// plus hosts (`host` or `host:port`) listed in `SBENV_GITHUB_API_HOSTS`, e.g. for
+/// GitHub Enterprise, so a custom release source can't collect the token.
+fn github_token_for(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    let trusted = (url.scheme() == "https" && host == "api.github.com")
+        || env::var("SBENV_GITHUB_API_HOSTS").is_ok_and(|hosts| {
+            let with_port = url.port().map(|p| format!("{}:{}", host, p));
+            hosts
+                .split([',', ' '])
+                .map(str::trim)
+                .any(|h| !h.is_empty() && (h == host || Some(h) == with_port.as_deref()))
+        });
+    trusted.then(github_token).flatten()
+}
 
-    for (i, option) in options.iter().enumerate() {
-        println!("  {}. {}", i + 1, option);
-    }
-    println!();
+const DEFAULT_RELEASE_CACHE_TTL: u64 = 60 * 60;
 
-    let selection = Input::<usize>::with_theme(&ColorfulTheme::default())
-        .with_prompt("Select an option")
-        .validate_with(|n: &usize| {
-            if *n > 0 && *n <= options.len() {
-                Ok(())
-            } else {
-                Err(format!(
-                    "Please enter a number between 1 and {}",
-                    options.len()
-                ))
-            }
-        })
-        .interact()?;
+/// Seconds cached release metadata stays fresh: `SBENV_RELEASE_CACHE_TTL`,
+/// `release_cache_ttl` in the global config, or an hour
+fn release_cache_ttl() -> u64 {
+    env::var("SBENV_RELEASE_CACHE_TTL")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .or(load_global_config().release_cache_ttl)
+        .unwrap_or(DEFAULT_RELEASE_CACHE_TTL)
+}
 
-    if selection == options.len() {
-        // User chose to skip
-        return Ok(None);
-    }
+fn get_release_cache_dir() -> PathBuf {
+    let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    Path::new(&home)
+        .join(".sbenv")
+        .join("cache")
+        .join("releases")
+}
 
-    if let Some(ref latest) = latest_version {
-        if selection == 1 {
-            // Download latest version
-            return Ok(Some(latest.clone()));
-        }
-    }
+/// A GitHub API response kept on disk
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedResponse {
+    url: String,
+    /// Unix timestamp of the fetch
+    fetched_at: i64,
+    body: String,
+}
 
-    // User selected a cached version
-    let cached_idx = if latest_version.is_some() {
-        selection - 2 // Adjust index if latest version was in the list
-    } else {
-        selection - 1
-    };
+fn cached_response_path(url: &str) -> PathBuf {
+    get_release_cache_dir().join(format!("{}.json", &sha256_hex(url.as_bytes())[..16]))
+}
 
-    if cached_idx < cached_versions.len() {
-        Ok(Some(cached_versions[cached_idx].clone()))
-    } else {
-        Ok(None)
-    }
+fn read_cached_response(url: &str) -> Option<CachedResponse> {
+    let content = fs::read_to_string(cached_response_path(url)).ok()?;
+    serde_json::from_str::<CachedResponse>(&content)
+        .ok()
+        .filter(|c| c.url == url)
 }
 
-fn current_os_arch() -> (String, String) {
-    let os = match std::env::consts::OS {
-        "macos" => "darwin",
-        other => other,
-    };
-    let arch = match std::env::consts::ARCH {
-        "aarch64" => "arm64",
-        other => other,
+fn write_cached_response(url: &str, body: &str) -> Result<()> {
+    let path = cached_response_path(url);
+    fs::create_dir_all(get_release_cache_dir())?;
+    let entry = CachedResponse {
+        url: url.to_string(),
+        fetched_at: chrono::Utc::now().timestamp(),
+        body: body.to_string(),
     };
-    (os.to_string(), arch.to_string())
+    // Write then rename so parallel runs never read half an entry
+    let tmp = path.with_extension(format!("tmp-{}", uuid::Uuid::new_v4()));
+    fs::write(&tmp, serde_json::to_string(&entry)?)?;
+    fs::rename(&tmp, &path)?;
+    Ok(())
 }
 
-#[allow(clippy::needless_borrows_for_generic_args)]
-fn ensure_syftbox_version(version: &str, quiet: bool) -> Result<PathBuf> {
-    let bin_dir = get_binaries_dir().join(version);
-    let bin_path = bin_dir.join("syftbox");
-    if bin_path.exists() {
-        if !quiet {
-            eprintln!("   Using cached syftbox version {}", version.cyan());
+/// GET a GitHub API URL. Answers come from the on-disk cache while they are fresh;
+/// when GitHub is rate limited or unreachable a stale copy is used instead.
+fn github_api_json(url: &str) -> Result<serde_json::Value, DownloadError> {
+    let parse = |body: &str| {
+        serde_json::from_str(body).map_err(|e| DownloadError::InvalidResponse {
+            url: url.to_string(),
+            reason: e.to_string(),
+        })
+    };
+    let cached = read_cached_response(url);
+    let now = chrono::Utc::now().timestamp();
+    if let Some(c) = &cached {
+        if now.saturating_sub(c.fetched_at) < release_cache_ttl() as i64 {
+            return parse(&c.body);
         }
-        return Ok(bin_path);
     }
 
-    if !quiet {
-        eprintln!("   Downloading syftbox version {}...", version.cyan());
+    let client = http_client()?;
+    let fetched = with_retries(|| {
+        let mut request = client
+            .get(url)
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json");
+        if let Some(token) = github_token_for(url) {
+            request = request.bearer_auth(token);
+        }
+        send_checked(request, url)?
+            .text()
+            .map_err(|e| DownloadError::Network {
+                url: url.to_string(),
+                source: e,
+            })
+    });
+    match fetched {
+        Ok(body) => {
+            let value = parse(&body)?;
+            // The cache only saves requests; failing to write it is not fatal
+            let _ = write_cached_response(url, &body);
+            Ok(value)
+        }
+        Err(e) => match cached {
+            Some(c) if !matches!(e, DownloadError::NotFound { .. }) => {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "⚠️  {}; using release info cached {} ago",
+                        e,
+                        format_age(now.saturating_sub(c.fetched_at))
+                    )
+                    .yellow()
+                );
+                parse(&c.body)
+            }
+            _ => Err(e),
+        },
     }
-    fs::create_dir_all(&bin_dir)?;
-    let (os, arch) = current_os_arch();
-    let base = format!(
-        "https://github.com/OpenMined/syftbox/releases/download/v{}/",
-        version
-    );
-    let candidates = vec![
-        format!("syftbox_{}_{}_{}.tar.gz", version, os, arch),
-        format!("syftbox-{}-{}-{}.tar.gz", version, os, arch),
-        format!("syftbox_{}_{}_{}.zip", version, os, arch),
-        format!("syftbox-{}-{}-{}.zip", version, os, arch),
-        format!("syftbox_{}_{}_{}", version, os, arch),
-        format!("syftbox-{}-{}-{}", version, os, arch),
-    ];
+}
 
-    let tmp_dir = bin_dir.join("_tmp");
-    let _ = fs::remove_dir_all(&tmp_dir);
-    fs::create_dir_all(&tmp_dir)?;
+/// Rough human-readable duration for a number of seconds
+fn format_age(secs: i64) -> String {
+    match secs {
+        s if s < 60 * 60 => format!("{}m", s / 60),
+        s if s < 48 * 60 * 60 => format!("{}h", s / (60 * 60)),
+        s => format!("{}d", s / (24 * 60 * 60)),
+    }
+}
 
-    let mut last_err: Option<anyhow::Error> = None;
+/// Local path of a `file://` URL
+fn file_url_path(url: &str) -> Option<PathBuf> {
+    url.strip_prefix("file://").map(PathBuf::from)
+}
 
-    // Try GitHub API to find the correct asset for this OS/arch
-    if let Some((asset_url, asset_name)) = github_release_asset_for(version) {
-        let tmp_file = tmp_dir.join("download_asset");
-        let status = Command::new("curl")
-            .args(["-fL", "-o", tmp_file.to_str().unwrap(), &asset_url])
-            .status();
-        if let Ok(st) = status {
-            if st.success() {
-                if let Err(e) =
-                    install_syftbox_from_download(&tmp_file, &asset_name, &tmp_dir, &bin_path)
-                {
-                    last_err = Some(e);
-                } else {
-                    let _ = fs::remove_dir_all(&tmp_dir);
-                    return Ok(bin_path);
-                }
-            }
-        }
-    }
-    for name in candidates {
-        let url = format!("{}{}", base, name);
-        let tmp_file = tmp_dir.join("download.bin");
-        let status = Command::new("curl")
-            .args(["-fL", "-o", tmp_file.to_str().unwrap(), &url])
-            .status();
-        if let Ok(st) = status {
-            if st.success() {
-                // Try to detect archive by extension
-                let lower = name.to_lowercase();
-                if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
-                    let st2 = Command::new("tar")
-                        .args([
-                            "-xzf",
-                            tmp_file.to_str().unwrap(),
-                            "-C",
-                            tmp_dir.to_str().unwrap(),
-                        ])
-                        .status();
-                    if st2.as_ref().map(|s| s.success()).unwrap_or(false) {
-                        // find a file named syftbox in tmp_dir tree
-                        if let Some(found) = find_in_dir(&tmp_dir, "syftbox") {
-                            fs::rename(&found, &bin_path)?;
-                            let _ = fs::remove_dir_all(&tmp_dir);
-                            #[cfg(unix)]
-                            {
-                                use std::os::unix::fs::PermissionsExt;
-                                let mut perm = fs::metadata(&bin_path)?.permissions();
-                                perm.set_mode(0o755);
-                                fs::set_permissions(&bin_path, perm)?;
-                            }
-                            return Ok(bin_path);
-                        }
-                    }
-                } else if lower.ends_with(".zip") {
-                    // Try unzip
-                    let st2 = Command::new("unzip")
-                        .args([
-                            "-o",
-                            tmp_file.to_str().unwrap(),
-                            "-d",
-                            tmp_dir.to_str().unwrap(),
-                        ])
-                        .status();
-                    if st2.as_ref().map(|s| s.success()).unwrap_or(false) {
-                        if let Some(found) = find_in_dir(&tmp_dir, "syftbox") {
-                            fs::rename(&found, &bin_path)?;
-                            let _ = fs::remove_dir_all(&tmp_dir);
-                            #[cfg(unix)]
-                            {
-                                use std::os::unix::fs::PermissionsExt;
-                                let mut perm = fs::metadata(&bin_path)?.permissions();
-                                perm.set_mode(0o755);
-                                fs::set_permissions(&bin_path, perm)?;
-                            }
-                            return Ok(bin_path);
-                        }
-                    }
-                } else {
-                    // Assume it's the binary itself
-                    fs::rename(&tmp_file, &bin_path)?;
-                    let _ = fs::remove_dir_all(&tmp_dir);
-                    #[cfg(unix)]
-                    {
-                        use std::os::unix::fs::PermissionsExt;
-                        let mut perm = fs::metadata(&bin_path)?.permissions();
-                        perm.set_mode(0o755);
-                        fs::set_permissions(&bin_path, perm)?;
-                    }
-                    return Ok(bin_path);
-                }
-            }
-        } else if let Err(e) = status {
-            last_err = Some(anyhow::anyhow!("curl failed: {}", e));
-        }
-    }
-    let _ = fs::remove_dir_all(&tmp_dir);
-    if let Some(e) = last_err {
-        Err(e)
-    } else {
-        let (os2, arch2) = current_os_arch();
-        Err(anyhow::anyhow!(
-            "Failed to download syftbox {} for {}-{}",
-            version,
-            os2,
-            arch2
-        ))
+fn read_local_url(url: &str, path: &Path) -> Result<Vec<u8>, DownloadError> {
+    fs::read(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => DownloadError::NotFound {
+            url: url.to_string(),
+        },
+        _ => DownloadError::Io(e),
+    })
+}
+
+fn http_get_text(url: &str) -> Result<String, DownloadError> {
+    if let Some(path) = file_url_path(url) {
+        return Ok(String::from_utf8_lossy(&read_local_url(url, &path)?).to_string());
     }
+    let client = http_client()?;
+    with_retries(|| {
+        http_get(&client, url)?
+            .text()
+            .map_err(|e| DownloadError::Network {
+                url: url.to_string(),
+                source: e,
+            })
+    })
 }
 
-fn github_release_asset_for(version: &str) -> Option<(String, String)> {
-    // Use GitHub API to get assets for the tag and choose the best match
-    let url = format!(
-        "https://api.github.com/repos/OpenMined/syftbox/releases/tags/v{}",
-        version
-    );
-    let out = Command::new("curl")
-        .args(["-sL", "-H", "User-Agent: sbenv", &url])
-        .output()
-        .ok()?;
-    if !out.status.success() {
-        return None;
+fn get_downloads_dir() -> PathBuf {
+    let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    Path::new(&home).join(".sbenv").join("downloads")
+}
+
+/// Last path segment of a URL, without query or fragment
+fn url_file_name(url: &str) -> Option<&str> {
+    url.split(['?', '#'])
+        .next()
+        .and_then(|u| u.rsplit('/').next())
+        .filter(|n| !n.is_empty())
+}
+
+/// Where a partial download of `url` is kept between attempts and runs
+fn partial_download_path(url: &str) -> PathBuf {
+    let name = url_file_name(url).unwrap_or("download");
+    get_downloads_dir().join(format!(
+        "{}-{}.part",
+        &sha256_hex(url.as_bytes())[..16],
+        name
+    ))
+}
+
+/// Stream a URL into `dest`. Interrupted downloads are resumed with a range request,
+/// also across runs, since the partial file stays in `~/.sbenv/downloads/`.
+fn download_to_file(url: &str, dest: &Path) -> Result<(), DownloadError> {
+    if let Some(path) = file_url_path(url) {
+        fs::write(dest, read_local_url(url, &path)?)?;
+        return Ok(());
     }
-    let body = String::from_utf8_lossy(&out.stdout);
-    let v: serde_json::Value = serde_json::from_str(&body).ok()?;
-    let assets = v.get("assets")?.as_array()?;
-    let (os, arch) = current_os_arch();
-    let os_tokens = match os.as_str() {
-        "darwin" => vec!["darwin", "macos", "osx", "apple-darwin"],
-        "linux" => vec!["linux", "gnu", "musl", "linux-gnu"],
-        other => vec![other],
-    };
-    let arch_tokens = match arch.as_str() {
-        "arm64" => vec!["arm64", "aarch64"],
-        "x86_64" => vec!["x86_64", "amd64"],
-        other => vec![other],
+    let client = http_client()?;
+    let part = partial_download_path(url);
+    fs::create_dir_all(get_downloads_dir())?;
+    let lock_name = part
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let _lock = lock_cache_entry(&format!("download-{}", lock_name), true)
+        .map_err(|e| DownloadError::Io(std::io::Error::other(e.to_string())))?;
+    with_retries(|| fetch_resumable(&client, url, &part))?;
+    fs::rename(&part, dest)?;
+    let _ = fs::remove_file(part_validator_path(&part));
+    Ok(())
+}
+
+/// ETag or Last-Modified of the response a partial download came from
+fn part_validator_path(part: &Path) -> PathBuf {
+    part.with_extension("part.validator")
+}
+
+/// Validator to send in `If-Range`: a strong ETag, else Last-Modified
+fn response_validator(response: &reqwest::blocking::Response) -> Option<String> {
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
     };
-    let mut best: Option<(String, String, i32)> = None; // (url, name, score)
-    for a in assets {
-        let name = a.get("name").and_then(|n| n.as_str()).unwrap_or("");
-        let url = a
-            .get("browser_download_url")
-            .and_then(|u| u.as_str())
-            .unwrap_or("");
-        let lname = name.to_lowercase();
-        if !lname.contains("syftbox") {
-            continue;
-        }
-        if !os_tokens.iter().any(|t| lname.contains(t)) {
-            continue;
-        }
-        if !arch_tokens.iter().any(|t| lname.contains(t)) {
-            continue;
+    header(reqwest::header::ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(reqwest::header::LAST_MODIFIED))
+}
+
+/// One attempt at fetching `url` into `part`, continuing after the bytes it already holds.
+/// A resume is conditional on the server still having the same file (`If-Range`).
+fn fetch_resumable(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    part: &Path,
+) -> Result<(), DownloadError> {
+    use std::io::{Read, Write};
+    let validator_file = part_validator_path(part);
+    let mut offset = fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+    let validator = fs::read_to_string(&validator_file).ok();
+    // Without a validator the bytes on disk may belong to an older upload
+    if offset > 0 && validator.is_none() {
+        fs::remove_file(part)?;
+        offset = 0;
+    }
+    let mut request = client.get(url);
+    if let Some(validator) = validator.filter(|_| offset > 0) {
+        request = request
+            .header(reqwest::header::RANGE, format!("bytes={}-", offset))
+            .header(reqwest::header::IF_RANGE, validator.trim());
+    }
+    let mut response = match send_checked(request, url) {
+        // The partial file is no prefix of what the server has now; start over
+        Err(DownloadError::Http { status: 416, .. }) if offset > 0 => {
+            fs::remove_file(part)?;
+            return fetch_resumable(client, url, part);
         }
-        let score = if lname.ends_with(".tar.gz") || lname.ends_with(".tgz") {
-            3
-        } else if lname.ends_with(".zip") {
-            2
-        } else {
-            1
-        };
-        match &best {
-            None => best = Some((url.to_string(), name.to_string(), score)),
-            Some((_, _, bs)) if score > *bs => {
-                best = Some((url.to_string(), name.to_string(), score))
+        other => other?,
+    };
+    // Servers without range support, or whose file changed, answer 200 with the whole body
+    let resumed = offset > 0 && response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    let start = if resumed { offset } else { 0 };
+    let mut file = if resumed {
+        fs::OpenOptions::new().append(true).open(part)?
+    } else {
+        match response_validator(&response) {
+            Some(v) => fs::write(&validator_file, v)?,
+            None => {
+                let _ = fs::remove_file(&validator_file);
             }
-            _ => {}
         }
-        if score == 3 {
-            // Good enough
-            break;
+        fs::File::create(part)?
+    };
+
+    let bar = download_progress_bar(url, response.content_length().map(|n| n + start));
+    bar.set_position(start);
+    bar.reset_eta();
+    let mut buf = vec![0u8; 64 * 1024];
+    let result = loop {
+        let n = match response.read(&mut buf) {
+            Ok(0) => break Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                break Err(DownloadError::Interrupted {
+                    url: url.to_string(),
+                    source: e,
+                })
+            }
+        };
+        if let Err(e) = file.write_all(&buf[..n]) {
+            break Err(e.into());
         }
-    }
-    best.map(|(u, n, _)| (u, n))
+        bar.inc(n as u64);
+    };
+    bar.finish_and_clear();
+    result
 }
 
-#[allow(clippy::needless_borrows_for_generic_args)]
-fn install_syftbox_from_download(
-    tmp_file: &Path,
-    asset_name: &str,
-    tmp_dir: &Path,
-    bin_path: &Path,
-) -> Result<()> {
-    let lname = asset_name.to_lowercase();
-    if lname.ends_with(".tar.gz") || lname.ends_with(".tgz") {
-        let st2 = Command::new("tar")
-            .args([
-                "-xzf",
-                tmp_file.to_str().unwrap(),
-                "-C",
-                tmp_dir.to_str().unwrap(),
-            ])
-            .status()?;
-        if !st2.success() {
-            return Err(anyhow::anyhow!("Failed to extract tar.gz"));
-        }
-        let found = find_in_dir(tmp_dir, "syftbox")
-            .ok_or_else(|| anyhow::anyhow!("syftbox binary not found in archive"))?;
-        fs::rename(&found, &bin_path)?;
-    } else if lname.ends_with(".zip") {
-        let st2 = Command::new("unzip")
-            .args([
-                "-o",
-                tmp_file.to_str().unwrap(),
-                "-d",
-                tmp_dir.to_str().unwrap(),
-            ])
-            .status()?;
-        if !st2.success() {
-            return Err(anyhow::anyhow!("Failed to unzip asset"));
-        }
-        let found = find_in_dir(tmp_dir, "syftbox")
-            .ok_or_else(|| anyhow::anyhow!("syftbox binary not found in zip"))?;
-        fs::rename(&found, &bin_path)?;
+/// Progress bar for a download on stderr; hidden when stderr is not a terminal
+fn download_progress_bar(url: &str, total: Option<u64>) -> indicatif::ProgressBar {
+    use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+    let bar = ProgressBar::with_draw_target(total, ProgressDrawTarget::stderr());
+    let template = if total.is_some() {
+        "   {msg} [{bar:30.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})"
     } else {
-        // Assume it's the binary itself
-        fs::rename(&tmp_file, &bin_path)?;
-    }
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        let mut perm = fs::metadata(&bin_path)?.permissions();
-        perm.set_mode(0o755);
-        fs::set_permissions(&bin_path, perm)?;
+        "   {msg} {spinner} {bytes} ({bytes_per_sec})"
+    };
+    if let Ok(style) = ProgressStyle::with_template(template) {
+        bar.set_style(style.progress_chars("=> "));
     }
-    Ok(())
+    bar.set_message(url_file_name(url).unwrap_or(url).to_string());
+    bar
 }
 
-fn find_in_dir(dir: &Path, name: &str) -> Option<PathBuf> {
-    let mut stack = vec![dir.to_path_buf()];
-    while let Some(d) = stack.pop() {
-        if let Ok(read) = fs::read_dir(&d) {
-            for e in read.flatten() {
-                let p = e.path();
-                if p.is_dir() {
-                    stack.push(p.clone());
-                }
-                if p.file_name().and_then(|n| n.to_str()) == Some(name) {
-                    return Some(p);
-                }
-            }
-        }
-    }
-    None
+const DEFAULT_RELEASE_API: &str = "https://api.github.com/repos/OpenMined/syftbox";
+
+/// Where syftbox release metadata and assets come from
+#[derive(Debug, Clone, PartialEq)]
+enum ReleaseSource {
+    /// GitHub-compatible releases API for a repo, e.g. `https://api.github.com/repos/OpenMined/syftbox`
+    GitHub { api: String },
+    /// Plain HTTP mirror serving `index.json` and `v<version>/<asset>` files
+    Mirror { base: String },
+    /// Local directory laid out like a mirror (`index.json` optional)
+    Local { dir: PathBuf },
 }
 
-fn resolve_or_install_syftbox(spec: &str, quiet: bool) -> Result<(PathBuf, Option<String>)> {
-    // If spec parses as semver => version
-    if Version::parse(spec).is_ok() {
-        let bin = ensure_syftbox_version(spec, quiet)?;
-        let ver = detect_binary_version(&bin);
-        return Ok((bin, ver));
-    }
-    // Otherwise treat as path
-    let p = PathBuf::from(spec);
-    let path = if p.is_absolute() || p.exists() {
-        p
-    } else {
-        // Fallback: try PATH command name
-        which_syftbox().unwrap_or_else(|| PathBuf::from("syftbox"))
-    };
-    let ver = detect_binary_version(&path);
-    Ok((path, ver))
+/// Index file served by mirrors and local release directories:
+/// `{"latest": "0.9.0", "releases": [{"version": "0.9.0", "assets": ["checksums.txt", ...]}]}`
+#[derive(Debug, Deserialize)]
+struct MirrorIndex {
+    #[serde(default)]
+    latest: Option<String>,
+    #[serde(default)]
+    releases: Vec<MirrorRelease>,
 }
 
-fn detect_binary_version(bin: &Path) -> Option<String> {
-    let out = Command::new(bin).arg("--version").output().ok()?;
-    if !out.status.success() {
-        return None;
-    }
-    parse_syftbox_version_output(&String::from_utf8_lossy(&out.stdout))
+#[derive(Debug, Deserialize)]
+struct MirrorRelease {
+    version: String,
+    #[serde(default)]
+    assets: Vec<MirrorAsset>,
 }
 
-fn is_semver_spec(spec: &str) -> bool {
-    Version::parse(spec).is_ok()
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum MirrorAsset {
+    /// File name relative to `v<version>/`
+    Name(String),
+    /// Explicit URL, absolute or relative to the mirror base
+    Full { name: String, url: String },
 }
 
-fn resolve_binary_for_env(config_path: &Path, quiet: bool) -> Result<(PathBuf, Option<String>)> {
-    // Load config to get email for key generation
-    let config = load_config(config_path)?;
+impl ReleaseSource {
+    /// Parse `github:<api repo url>`, `mirror:<url>`, a bare `http(s)://` mirror URL or `file://<dir>`
+    fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        if spec.is_empty() || spec == "github" {
+            return Ok(ReleaseSource::GitHub {
+                api: DEFAULT_RELEASE_API.to_string(),
+            });
+        }
+        if let Some(api) = spec.strip_prefix("github:") {
+            return Ok(ReleaseSource::GitHub {
+                api: api.trim_end_matches('/').to_string(),
+            });
+        }
+        if let Some(dir) = file_url_path(spec) {
+            return Ok(ReleaseSource::Local { dir });
+        }
+        let base = spec.strip_prefix("mirror:").unwrap_or(spec);
+        if base.starts_with("http://") || base.starts_with("https://") {
+            return Ok(ReleaseSource::Mirror {
+                base: base.trim_end_matches('/').to_string(),
+            });
+        }
+        Err(anyhow::anyhow!(
+            "Invalid release source '{}'. Expected github:<api url>, mirror:<url> or file://<dir>",
+            spec
+        ))
+    }
 
-    // Prefer env-specific registry entry
-    let registry = load_registry()?;
-    let env_dir = config_path.parent().unwrap().parent().unwrap();
-    let env_key = generate_env_key(env_dir, &config.email);
-    let entry = registry.environments.get(&env_key);
-    if let Some(info) = entry {
-        if let Some(b) = &info.binary {
-            let p = PathBuf::from(b);
-            if p.exists() {
-                return Ok((p.clone(), detect_binary_version(&p)));
+    /// Base URL for `index.json` and release assets of mirror-style sources
+    fn mirror_base(&self) -> Option<String> {
+        match self {
+            ReleaseSource::GitHub { .. } => None,
+            ReleaseSource::Mirror { base } => Some(base.clone()),
+            ReleaseSource::Local { dir } => Some(format!("file://{}", dir.display())),
+        }
+    }
+
+    /// Where well-known asset names for a version can be guessed, if anywhere
+    fn download_base(&self, version: &str) -> Option<String> {
+        match self {
+            ReleaseSource::GitHub { api } if api == DEFAULT_RELEASE_API => Some(format!(
+                "https://github.com/OpenMined/syftbox/releases/download/v{}/",
+                version
+            )),
+            ReleaseSource::GitHub { .. } => None,
+            _ => self
+                .mirror_base()
+                .map(|base| format!("{}/v{}/", base, version)),
+        }
+    }
+
+    fn load_index(&self) -> Result<Option<MirrorIndex>, DownloadError> {
+        let Some(base) = self.mirror_base() else {
+            return Ok(None);
+        };
+        let url = format!("{}/index.json", base);
+        match http_get_text(&url) {
+            Ok(body) => {
+                serde_json::from_str(&body)
+                    .map(Some)
+                    .map_err(|e| DownloadError::InvalidResponse {
+                        url,
+                        reason: e.to_string(),
+                    })
             }
+            // Local directories may omit the index
+            Err(DownloadError::NotFound { .. }) if matches!(self, ReleaseSource::Local { .. }) => {
+                Ok(None)
+            }
+            Err(e) => Err(e),
         }
-        if let Some(ver) = &info.binary_version {
-            if is_semver_spec(ver) {
-                let bin = ensure_syftbox_version(ver, quiet)?;
-                let v = detect_binary_version(&bin).or_else(|| Some(ver.clone()));
-                return Ok((bin, v));
+    }
+
+    /// Versions found in a local directory without an index (`v<version>/` or `<version>/`)
+    fn scan_local_versions(dir: &Path) -> Vec<(String, PathBuf)> {
+        let mut found = Vec::new();
+        if let Ok(entries) = fs::read_dir(dir) {
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let ver = name.strip_prefix('v').unwrap_or(&name).to_string();
+                if entry.path().is_dir() && Version::parse(&ver).is_ok() {
+                    found.push((ver, entry.path()));
+                }
             }
         }
+        found
     }
-    // Fallback to global default
-    let gc = load_global_config();
-    if let Some(spec) = gc.default_binary {
-        return resolve_or_install_syftbox(&spec, quiet);
+
+    fn latest_version(&self) -> Result<String> {
+        if let ReleaseSource::GitHub { api } = self {
+            let url = format!("{}/releases/latest", api);
+            let v = github_api_json(&url).context("Failed to fetch latest release info")?;
+            let tag = v
+                .get("tag_name")
+                .and_then(|t| t.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Could not find tag_name in release"))?;
+            // Remove 'v' prefix if present
+            return Ok(tag.strip_prefix('v').unwrap_or(tag).to_string());
+        }
+
+        let index = self
+            .load_index()
+            .context("Failed to read release mirror index")?;
+        if let Some(latest) = index.as_ref().and_then(|i| i.latest.as_ref()) {
+            return Ok(latest.trim_start_matches('v').to_string());
+        }
+        self.index_versions(index)
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("No releases found in release source"))
     }
-    // Fallback to PATH
-    if let Some(p) = which_syftbox() {
-        return Ok((p.clone(), detect_binary_version(&p)));
+
+    /// All published versions, oldest first
+    fn list_versions(&self) -> Result<Vec<String>> {
+        if let ReleaseSource::GitHub { api } = self {
+            let url = format!("{}/releases?per_page=100", api);
+            let v = github_api_json(&url).context("Failed to fetch release list")?;
+            let releases = v
+                .as_array()
+                .ok_or_else(|| anyhow::anyhow!("Unexpected release list from {}", url))?;
+            let mut versions: Vec<String> = releases
+                .iter()
+                .filter(|r| !r.get("draft").and_then(|d| d.as_bool()).unwrap_or(false))
+                .filter_map(|r| r.get("tag_name").and_then(|t| t.as_str()))
+                .map(|t| t.strip_prefix('v').unwrap_or(t).to_string())
+                .filter(|t| Version::parse(t).is_ok())
+                .collect();
+            versions.sort_by_key(|v| Version::parse(v).unwrap());
+            return Ok(versions);
+        }
+        let index = self
+            .load_index()
+            .context("Failed to read release mirror index")?;
+        Ok(self.index_versions(index))
     }
-    // Final fallback: plain name (might fail at runtime)
-    Ok((PathBuf::from("syftbox"), None))
-}
 
-fn ensure_env_has_binary(env_dir: &Path, email: &str) -> Result<()> {
-    let env_key = generate_env_key(env_dir, email);
-    let mut registry = load_registry()?;
-    if let Some(info) = registry.environments.get_mut(&env_key) {
-        if info.binary.is_none() && info.binary_version.is_none() {
-            let gc = load_global_config();
-            if let Some(spec) = gc.default_binary {
-                let (p, v) = resolve_or_install_syftbox(&spec, false)?;
-                info.binary = Some(p.to_string_lossy().to_string());
-                info.binary_version = if is_semver_spec(&spec) {
-                    v.clone()
-                } else {
-                    None
+    /// Versions listed in a mirror index, or found by scanning a local directory, oldest first
+    fn index_versions(&self, index: Option<MirrorIndex>) -> Vec<String> {
+        let mut versions: Vec<String> = match index {
+            Some(index) => index
+                .releases
+                .iter()
+                .map(|r| r.version.trim_start_matches('v').to_string())
+                .collect(),
+            None => match self {
+                ReleaseSource::Local { dir } => Self::scan_local_versions(dir)
+                    .into_iter()
+                    .map(|(v, _)| v)
+                    .collect(),
+                _ => Vec::new(),
+            },
+        };
+        versions.retain(|v| Version::parse(v).is_ok());
+        versions.sort_by_key(|v| Version::parse(v).unwrap());
+        versions
+    }
+
+    /// Assets (url, name) of the release for a version; `None` if the source doesn't list it.
+    /// GitHub errors such as the rate limit are returned rather than treated as "not listed".
+    fn release_assets(&self, version: &str) -> Result<Option<Vec<(String, String)>>> {
+        let ReleaseSource::GitHub { api } = self else {
+            return Ok(self.mirror_assets(version));
+        };
+        let url = format!("{}/releases/tags/v{}", api, version);
+        let v = match github_api_json(&url) {
+            Ok(v) => v,
+            Err(DownloadError::NotFound { .. }) => return Ok(None),
+            Err(e) => return Err(e).context(format!("Failed to fetch release {} info", version)),
+        };
+        let Some(assets) = v.get("assets").and_then(|a| a.as_array()) else {
+            return Ok(None);
+        };
+        Ok(Some(
+            assets
+                .iter()
+                .map(|a| {
+                    let name = a.get("name").and_then(|n| n.as_str()).unwrap_or("");
+                    let url = a
+                        .get("browser_download_url")
+                        .and_then(|u| u.as_str())
+                        .unwrap_or("");
+                    (url.to_string(), name.to_string())
+                })
+                .collect(),
+        ))
+    }
+
+    /// Assets of a release in a mirror index or local release directory
+    fn mirror_assets(&self, version: &str) -> Option<Vec<(String, String)>> {
+        let base = self.mirror_base()?;
+        match self.load_index().ok()? {
+            Some(index) => {
+                let release = index
+                    .releases
+                    .iter()
+                    .find(|r| r.version.trim_start_matches('v') == version)?;
+                Some(
+                    release
+                        .assets
+                        .iter()
+                        .map(|a| match a {
+                            MirrorAsset::Name(name) => {
+                                (format!("{}/v{}/{}", base, version, name), name.clone())
+                            }
+                            MirrorAsset::Full { name, url } if url.contains("://") => {
+                                (url.clone(), name.clone())
+                            }
+                            MirrorAsset::Full { name, url } => (
+                                format!("{}/{}", base, url.trim_start_matches('/')),
+                                name.clone(),
+                            ),
+                        })
+                        .collect(),
+                )
+            }
+            None => {
+                let ReleaseSource::Local { dir } = self else {
+                    return None;
                 };
-                let d = detect_binary_details(&p);
-                info.binary_hash = d.hash;
-                info.binary_os = d.os;
-                info.binary_arch = d.arch;
-                save_registry(&registry)?;
-            } else if let Some(p) = which_syftbox() {
-                info.binary = Some(p.to_string_lossy().to_string());
-                let d = detect_binary_details(&p);
-                info.binary_version = d.version;
-                info.binary_hash = d.hash;
-                info.binary_os = d.os;
-                info.binary_arch = d.arch;
-                save_registry(&registry)?;
+                let (_, release_dir) = Self::scan_local_versions(dir)
+                    .into_iter()
+                    .find(|(v, _)| v == version)?;
+                let mut assets = Vec::new();
+                for entry in fs::read_dir(&release_dir).ok()?.flatten() {
+                    if entry.path().is_file() {
+                        let name = entry.file_name().to_string_lossy().to_string();
+                        assets.push((format!("file://{}", entry.path().display()), name));
+                    }
+                }
+                Some(assets)
             }
         }
     }
-    Ok(())
 }
-fn find_syftbox_config(start_dir: &Path) -> Option<PathBuf> {
-    let mut current = start_dir.to_path_buf();
-    loop {
-        let config_path = current.join(".syftbox").join("config.json");
-        if config_path.exists() {
-            return Some(config_path);
-        }
-        if !current.pop() {
-            break;
-        }
+
+/// Release source from `SBENV_RELEASE_SOURCE`, the global config, or GitHub by default
+fn release_source() -> Result<ReleaseSource> {
+    if let Ok(spec) = env::var("SBENV_RELEASE_SOURCE") {
+        return ReleaseSource::parse(&spec).context("Invalid SBENV_RELEASE_SOURCE");
+    }
+    match load_global_config().release_source {
+        Some(spec) => ReleaseSource::parse(&spec).context("Invalid release_source in config"),
+        None => ReleaseSource::parse("github"),
     }
-    None
 }
 
-fn load_config(config_path: &Path) -> Result<SyftBoxConfig> {
-    let content = fs::read_to_string(config_path)
-        .with_context(|| format!("Failed to read config from {:?}", config_path))?;
-    let config: SyftBoxConfig = serde_json::from_str(&content)
-        .with_context(|| format!("Failed to parse config from {:?}", config_path))?;
-    Ok(config)
+fn fetch_latest_syftbox_version() -> Result<String> {
+    release_source()?.latest_version()
 }
 
-fn init_environment_with_binary(
-    email: Option<String>,
-    server_url: Option<String>,
-    dev: bool,
-    binary: Option<String>,
-    quiet: bool,
-) -> Result<()> {
-    let current_dir = env::current_dir().context("Failed to get current directory")?;
-    let syftbox_dir = current_dir.join(".syftbox");
+fn prompt_for_syftbox_install() -> Result<Option<String>> {
+    println!("{}", "⚠️  SyftBox is not installed in your PATH.".yellow());
+    println!();
 
-    if syftbox_dir.exists() {
-        println!(
-            "{}",
-            "A SyftBox environment already exists in this directory!".red()
-        );
-        return Ok(());
-    }
+    // Check for cached versions
+    let cached_versions = get_cached_syftbox_versions();
 
-    let email = if let Some(email) = email {
-        email
-    } else {
-        if quiet {
-            return Err(anyhow::anyhow!(
-                "Email address is required when using --quiet flag. Use -e <email> to provide it."
-            ));
-        }
-        Input::<String>::with_theme(&ColorfulTheme::default())
-            .with_prompt("Email address")
-            .interact_text()
-            .context("Failed to read email input")?
-    };
+    // Try to fetch the latest version
+    let latest_version = fetch_latest_syftbox_version().ok();
 
-    let port = find_available_port().context("Failed to find available port")?;
-    let client_url = format!("http://127.0.0.1:{}", port);
-    let resolved_server_url = match server_url {
-        Some(url) => url,
-        None => {
-            if dev {
-                "http://localhost:8080".to_string()
-            } else {
-                "https://syftbox.net".to_string()
-            }
-        }
-    };
+    if cached_versions.is_empty() && latest_version.is_none() {
+        println!(
+            "{}",
+            "❌ Could not fetch available versions and no cached versions found.".red()
+        );
+        println!("Please install syftbox manually or ensure internet connectivity.");
+        return Ok(None);
+    }
 
-    // Generate a random token for the control plane API
-    let client_token = generate_client_token();
+    let mut options = Vec::new();
 
-    let config = SyftBoxConfig {
-        data_dir: current_dir.to_string_lossy().to_string(),
-        email: email.clone(),
-        server_url: resolved_server_url.clone(),
-        client_url: Some(client_url.clone()),
-        client_token: Some(client_token.clone()),
-        refresh_token: None,
-        dev_mode: dev,
-    };
+    // Add latest version if available
+    if let Some(ref latest) = latest_version {
+        options.push(format!("Download latest version ({})", latest));
+    }
 
-    fs::create_dir_all(&syftbox_dir).context("Failed to create .syftbox directory")?;
+    // Add cached versions
+    for version in &cached_versions {
+        options.push(format!("Use cached version {}", version));
+    }
 
-    let config_path = syftbox_dir.join("config.json");
-    let config_json =
-        serde_json::to_string_pretty(&config).context("Failed to serialize config")?;
-    fs::write(&config_path, config_json).context("Failed to write config file")?;
+    options.push("Skip (I'll install it manually)".to_string());
 
-    register_environment(&current_dir, &config)?;
+    println!("What would you like to do?");
+    println!();
 
-    // Check if syftbox is available, prompt for install if not
-    let binary_to_use = if binary.is_some() {
-        binary
-    } else {
-        // Check if syftbox is in PATH
-        let syftbox_in_path = which_syftbox().is_some();
+    for (i, option) in options.iter().enumerate() {
+        println!("  {}. {}", i + 1, option);
+    }
+    println!();
 
-        if !syftbox_in_path {
-            if quiet {
-                // In quiet mode, automatically try to get the latest version
-                println!("📦 SyftBox not found in PATH. Fetching latest version...");
-                match fetch_latest_syftbox_version() {
-                    Ok(version) => Some(version),
-                    Err(e) => {
-                        // If we can't fetch latest, try to use a cached version
-                        let cached = get_cached_syftbox_versions();
-                        if !cached.is_empty() {
-                            println!("   Could not fetch latest version: {}", e);
-                            println!("   Using cached version: {}", cached[0]);
-                            Some(cached[0].clone())
-                        } else {
-                            println!("⚠️  Could not fetch latest version and no cached versions available");
-                            println!("   Error: {}", e);
-                            None
-                        }
-                    }
-                }
+    let selection = Input::<usize>::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select an option")
+        .validate_with(|n: &usize| {
+            if *n > 0 && *n <= options.len() {
+                Ok(())
             } else {
-                // Interactive mode - prompt user
-                // This will show cached versions and option to download latest
-                if let Ok(Some(version)) = prompt_for_syftbox_install() {
-                    Some(version)
-                } else {
-                    None
-                }
+                Err(format!(
+                    "Please enter a number between 1 and {}",
+                    options.len()
+                ))
             }
-        } else {
-            None
-        }
-    };
-
-    // Resolve and persist binary preference
-    if let Some(bin_spec) = binary_to_use {
-        println!("📦 Setting up SyftBox binary...");
-        let (bin_path, bin_ver) = resolve_or_install_syftbox(&bin_spec, false)?;
-        println!("✅ SyftBox binary configured successfully!");
-        // Update registry entry
-        let mut registry = load_registry()?;
-        let env_key = generate_env_key(&current_dir, &email);
-        if let Some(info) = registry.environments.get_mut(&env_key) {
-            info.binary = Some(bin_path.to_string_lossy().to_string());
-            // Only persist a version when the user provided a semantic version spec
-            info.binary_version = if is_semver_spec(&bin_spec) {
-                bin_ver
-            } else {
-                None
-            };
-            let d = detect_binary_details(&bin_path);
-            info.binary_hash = d.hash;
-            info.binary_os = d.os;
-            info.binary_arch = d.arch;
-        }
-        save_registry(&registry)?;
+        })
+        .interact()?;
 
-        // Save as global default for future envs
-        let mut gc = load_global_config();
-        gc.default_binary = Some(bin_spec);
-        let _ = save_global_config(&gc);
-    } else {
-        // If no spec, ensure global default exists (noop if not set)
-        let _ = ensure_env_has_binary(&current_dir, &email);
+    if selection == options.len() {
+        // User chose to skip
+        return Ok(None);
     }
 
-    // Write a marker file so other tools can detect the environment
-    // This will include binary info if it was set in the registry
-    let _ = ensure_marker_exists(&config_path, &config);
-
-    println!("{}", "✅ SyftBox environment initialized!".green().bold());
-    println!();
-    println!("📧 Email: {}", email.cyan());
-    println!("🌐 Server: {}", resolved_server_url.cyan());
-    println!("📁 Data dir: {}", current_dir.display().to_string().cyan());
-    println!("🔌 Client port: {}", port.to_string().cyan());
-    // Show resolved binary information
-    if let Ok((bin_path, bin_ver)) = resolve_binary_for_env(&config_path, false) {
-        println!("🛠 Binary: {}", bin_path.display().to_string().cyan());
-        if let Some(v) = bin_ver {
-            println!("🔢 Version: {}", v.cyan());
+    if let Some(ref latest) = latest_version {
+        if selection == 1 {
+            // Download latest version
+            return Ok(Some(latest.clone()));
         }
     }
-    println!();
-    println!("Run {} to see this info again", "sbenv info".yellow());
-    println!(
-        "Run {} to activate this environment",
-        "sbenv activate".yellow()
-    );
 
-    Ok(())
+    // User selected a cached version
+    let cached_idx = if latest_version.is_some() {
+        selection - 2 // Adjust index if latest version was in the list
+    } else {
+        selection - 1
+    };
+
+    if cached_idx < cached_versions.len() {
+        Ok(Some(cached_versions[cached_idx].clone()))
+    } else {
+        Ok(None)
+    }
 }
 
-fn show_info() -> Result<()> {
-    let current_dir = env::current_dir().context("Failed to get current directory")?;
-    let config_path = find_syftbox_config(&current_dir).ok_or_else(|| {
-        anyhow::anyhow!("No SyftBox environment found in current directory or parents")
-    })?;
+fn current_os_arch() -> (String, String) {
+    let os = match std::env::consts::OS {
+        "macos" => "darwin",
+        other => other,
+    };
+    let arch = match std::env::consts::ARCH {
+        "aarch64" => "arm64",
+        other => other,
+    };
+    (os.to_string(), arch.to_string())
+}
 
-    let config = load_config(&config_path)?;
+/// Platform a syftbox binary is built for, named like `current_os_arch`
+#[derive(Debug, Clone, PartialEq)]
+struct Target {
+    os: String,
+    arch: String,
+}
 
-    // Register the environment if not already registered
-    let env_dir = config_path.parent().unwrap().parent().unwrap();
-    let _ = register_environment(env_dir, &config);
+impl Target {
+    fn host() -> Self {
+        let (os, arch) = current_os_arch();
+        Target { os, arch }
+    }
 
-    let _port = config
-        .client_url
-        .as_deref()
-        .and_then(|u| u.rsplit(':').next())
-        .unwrap_or("unknown");
+    /// Parse `<os>/<arch>`, accepting the usual aliases (macos, amd64, aarch64, ...)
+    fn parse(spec: &str) -> Result<Self> {
+        let (os, arch) = spec
+            .trim()
+            .split_once('/')
+            .ok_or_else(|| anyhow::anyhow!("Invalid target '{}'. Expected <os>/<arch>", spec))?;
+        let os = match os.to_lowercase().as_str() {
+            "darwin" | "macos" | "osx" => "darwin".to_string(),
+            "linux" => "linux".to_string(),
+            "windows" => "windows".to_string(),
+            other => return Err(anyhow::anyhow!("Unsupported target OS '{}'", other)),
+        };
+        let arch = match arch.to_lowercase().as_str() {
+            "amd64" | "x86_64" | "x64" => "x86_64".to_string(),
+            "arm64" | "aarch64" => "arm64".to_string(),
+            other => return Err(anyhow::anyhow!("Unsupported target arch '{}'", other)),
+        };
+        Ok(Target { os, arch })
+    }
 
-    println!("{}", "📦 SyftBox Environment Info".green().bold());
-    println!();
-    println!("{}", "── Local Environment ──".dimmed());
-    println!("📧 Email: {}", config.email.cyan());
-    println!("🌐 Server URL: {}", config.server_url.cyan());
-    println!("📁 Data dir: {}", config.data_dir.cyan());
-    println!(
-        "🔌 Client URL: {}",
-        config.client_url.as_deref().unwrap_or("not set").cyan()
-    );
-    println!(
-        "⚙️  Dev mode: {}",
-        if config.dev_mode {
-            "enabled".green()
-        } else {
-            "disabled".dimmed()
-        }
-    );
-    println!(
-        "📄 Config path: {}",
-        config_path.display().to_string().cyan()
-    );
+    fn is_host(&self) -> bool {
+        *self == Target::host()
+    }
 
-    // Show binary details resolved for this environment
-    if let Ok((bin_path, bin_ver)) = resolve_binary_for_env(&config_path, false) {
-        println!("🛠 Binary: {}", bin_path.display().to_string().cyan());
-        if let Some(v) = bin_ver {
-            println!("🔢 Version: {}", v.cyan());
-        }
-        let d = detect_binary_details(&bin_path);
-        if let Some(h) = d.hash {
-            println!("    Hash: {}", h.cyan());
-        }
-        if let (Some(os), Some(arch)) = (d.os, d.arch) {
-            println!("    Target: {}/{}", os.cyan(), arch.cyan());
-        }
+    /// Whether an os/arch pair reported by `syftbox --version` is this platform
+    fn matches(&self, os: &str, arch: &str) -> bool {
+        let spec = format!("{}/{}", os, arch);
+        Target::parse(&spec).is_ok_and(|t| t == *self)
     }
 
-    // Show raw config.json content
-    println!();
-    println!("{}", "── Config File Content ──".dimmed());
-    if let Ok(config_content) = fs::read_to_string(&config_path) {
-        // Parse and pretty print the JSON
-        if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(&config_content) {
-            if let Ok(pretty_json) = serde_json::to_string_pretty(&json_value) {
-                for line in pretty_json.lines() {
-                    println!("  {}", line.dimmed());
-                }
-            }
-        }
+    /// Cache subdirectory of a version holding builds for this platform
+    fn dir_name(&self) -> String {
+        format!("{}-{}", self.os, self.arch)
     }
+}
 
-    // Show global sbenv registry info
-    println!();
-    println!("{}", "── Global sbenv Registry ──".dimmed());
-    let registry = load_registry().unwrap_or(EnvRegistry {
-        environments: HashMap::new(),
-    });
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.os, self.arch)
+    }
+}
 
-    let env_dir_str = env_dir.to_string_lossy().to_string();
-    let env_name = env_dir
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("unknown");
-    let env_key = generate_env_key(env_dir, &config.email);
+/// Builds of a cached version for other platforms (`binaries/<version>/<os>-<arch>/`)
+fn cached_target_builds(version: &str) -> Vec<Target> {
+    let mut targets: Vec<Target> = fs::read_dir(get_binaries_dir().join(version))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.path().join("syftbox").exists())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            let (os, arch) = name.split_once('-')?;
+            Target::parse(&format!("{}/{}", os, arch)).ok()
+        })
+        .collect();
+    targets.sort_by_key(|t| t.to_string());
+    targets
+}
 
-    if let Some(env_info) = registry.environments.get(&env_key) {
-        println!("📝 Registered as: {}", env_name.cyan());
-        println!("🏠 Location: {}", env_info.path.cyan());
-        println!("🔗 Server: {}", env_info.server_url.cyan());
-        println!("🔌 Port: {}", env_info.port.to_string().cyan());
-        println!(
-            "⚙️  Dev mode: {}",
-            if env_info.dev_mode {
-                "enabled".green()
-            } else {
-                "disabled".dimmed()
-            }
-        );
-        if let Some(b) = &env_info.binary {
-            println!("🛠  Binary: {}", b.cyan());
+fn ensure_syftbox_version(version: &str, quiet: bool, skip_verify: bool) -> Result<PathBuf> {
+    ensure_syftbox_target(version, &Target::host(), quiet, skip_verify)
+}
+
+/// Download a release for a platform into the cache. Host builds live in
+/// `binaries/<version>/`, others in `binaries/<version>/<os>-<arch>/`.
+fn ensure_syftbox_target(
+    version: &str,
+    target: &Target,
+    quiet: bool,
+    skip_verify: bool,
+) -> Result<PathBuf> {
+    let (entry, label) = if target.is_host() {
+        (version.to_string(), version.to_string())
+    } else {
+        (
+            format!("{}/{}", version, target.dir_name()),
+            format!("{} for {}", version, target),
+        )
+    };
+    let bin_path = get_binaries_dir().join(&entry).join("syftbox");
+    if bin_path.exists() {
+        if !quiet {
+            eprintln!("   Using cached syftbox version {}", label.cyan());
         }
-        if let Some(v) = &env_info.binary_version {
-            println!("    Version: {}", v.cyan());
+        return Ok(bin_path);
+    }
+
+    let _lock = lock_cache_entry(&entry.replace('/', "-"), quiet)?;
+    // Another sbenv may have installed it while we waited for the lock
+    if bin_path.exists() {
+        if !quiet {
+            eprintln!("   Using cached syftbox version {}", label.cyan());
         }
-        if let Some(h) = &env_info.binary_hash {
-            println!("    Hash: {}", h.cyan());
+        return Ok(bin_path);
+    }
+
+    if !quiet {
+        eprintln!("   Downloading syftbox version {}...", label.cyan());
+    }
+    let Target { os, arch } = target.clone();
+    let source = release_source()?;
+
+    // Candidate assets in order of preference: the one the release lists for
+    // this OS/arch first, then well-known asset names
+    let mut candidates: Vec<(String, String)> = Vec::new();
+    let assets = source.release_assets(version)?;
+    let mut checksums = None;
+    if let Some(assets) = &assets {
+        if let Some((url, _)) = find_checksums_asset(assets) {
+            checksums = fetch_checksums(&url)?;
         }
-        if env_info.binary_os.is_some() || env_info.binary_arch.is_some() {
-            println!(
-                "    Target: {}/{}",
-                env_info.binary_os.as_deref().unwrap_or("?"),
-                env_info.binary_arch.as_deref().unwrap_or("?")
-            );
+        if let Some(asset) = select_platform_asset(assets, target) {
+            candidates.push(asset);
         }
-    } else {
-        println!("⚠️  Not registered in global sbenv");
-        println!("   Run {} to register", "sbenv init".yellow());
     }
-
-    // Show all registered environments
-    if registry.environments.len() > 1 {
-        println!();
-        println!("{}", "── Other Environments ──".dimmed());
-        for (name, info) in registry.environments.iter() {
-            if info.path != env_dir_str {
-                println!("  • {} ({})", name.cyan(), info.path.dimmed());
+    if let Some(base) = source.download_base(version) {
+        for name in [
+            "checksums.txt".to_string(),
+            format!("syftbox_{}_checksums.txt", version),
+        ] {
+            if checksums.is_some() {
+                break;
+            }
+            checksums = fetch_checksums(&format!("{}{}", base, name))?;
+        }
+        for name in [
+            format!("syftbox_{}_{}_{}.tar.gz", version, os, arch),
+            format!("syftbox-{}-{}-{}.tar.gz", version, os, arch),
+            format!("syftbox_{}_{}_{}.zip", version, os, arch),
+            format!("syftbox-{}-{}-{}.zip", version, os, arch),
+            format!("syftbox_{}_{}_{}", version, os, arch),
+            format!("syftbox-{}-{}-{}", version, os, arch),
+        ] {
+            if !candidates.iter().any(|(_, n)| *n == name) {
+                candidates.push((format!("{}{}", base, name), name));
             }
         }
     }
 
-    Ok(())
+    // Everything is assembled in a private staging dir and moved into place in one
+    // rename, so readers never see a half-written binary
+    let tmp_dir = new_staging_dir()?;
+    let staged_dir = tmp_dir.join("stage");
+    let staged_bin = staged_dir.join("syftbox");
+    let result = fs::create_dir_all(&staged_dir)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| {
+            install_first_available(
+                &candidates,
+                checksums.as_ref(),
+                skip_verify,
+                &tmp_dir,
+                &staged_bin,
+            )
+        })
+        .and_then(|installed| match installed {
+            Some((asset_name, digest)) => {
+                record_verified_digest(&staged_bin, &asset_name, digest.as_deref())?;
+                publish_cache_entry(&staged_dir, &entry)
+            }
+            None => Err(anyhow::anyhow!(
+                "Failed to download syftbox {} for {}-{}: no matching release asset",
+                version,
+                os,
+                arch
+            )),
+        });
+    let _ = fs::remove_dir_all(&tmp_dir);
+    result
 }
 
-fn activate_environment(quiet: bool) -> Result<()> {
-    let current_dir = env::current_dir().context("Failed to get current directory")?;
-    let config_path = find_syftbox_config(&current_dir).ok_or_else(|| {
-        anyhow::anyhow!("No SyftBox environment found in current directory or parents")
-    })?;
-
-    let config = load_config(&config_path)?;
-
-    // Ensure .sbenv marker exists for this environment
-    let _ = ensure_marker_exists(&config_path, &config);
-
-    if !quiet {
-        println!("# Run this command to activate the environment:");
-        println!("# eval \"$(sbenv activate)\"");
-        println!();
-    }
-
-    println!("export SYFTBOX_EMAIL=\"{}\"", config.email);
-    println!("export SYFTBOX_DATA_DIR=\"{}\"", config.data_dir);
-    println!("export SYFTBOX_SERVER_URL=\"{}\"", config.server_url);
-    println!("export SYFTBOX_CONFIG_PATH=\"{}\"", config_path.display());
-    if let Some(url) = &config.client_url {
-        println!("export SYFTBOX_CLIENT_URL=\"{}\"", url);
-    }
-    // Resolve syftbox binary + version for this env (fallback to 'syftbox')
-    let (bin_path, bin_ver) =
-        resolve_binary_for_env(&config_path, quiet).unwrap_or((PathBuf::from("syftbox"), None));
-    println!("export SYFTBOX_BINARY=\"{}\"", bin_path.display());
-    if let Some(v) = bin_ver {
-        println!("export SYFTBOX_VERSION=\"{}\"", v);
-    }
-    let d = detect_binary_details(&bin_path);
-    if let Some(h) = d.hash {
-        println!("export SYFTBOX_BUILD_HASH=\"{}\"", h);
+/// Download, verify and install the first candidate asset that exists.
+/// Returns the installed asset name and its verified digest, or `None` if no candidate exists.
+fn install_first_available(
+    candidates: &[(String, String)],
+    checksums: Option<&HashMap<String, String>>,
+    skip_verify: bool,
+    tmp_dir: &Path,
+    bin_path: &Path,
+) -> Result<Option<(String, Option<String>)>> {
+    let mut last_err: Option<anyhow::Error> = None;
+    for (url, name) in candidates {
+        let tmp_file = tmp_dir.join("download.bin");
+        match download_to_file(url, &tmp_file) {
+            Ok(()) => {}
+            Err(DownloadError::NotFound { .. }) => continue,
+            Err(e) => {
+                last_err = Some(e.into());
+                continue;
+            }
+        }
+        // A download that fails verification is refused outright
+        let digest = verify_download(&tmp_file, name, checksums, skip_verify)?;
+        match install_syftbox_from_download(&tmp_file, name, tmp_dir, bin_path) {
+            Ok(()) => return Ok(Some((name.clone(), digest))),
+            Err(e) => last_err = Some(e),
+        }
     }
-    if let (Some(os), Some(arch)) = (d.os, d.arch) {
-        println!("export SYFTBOX_BUILD_TARGET=\"{}/{}\"", os, arch);
+    match last_err {
+        Some(e) => Err(e),
+        None => Ok(None),
     }
-    println!("export SYFTBOX_ENV_ACTIVE=\"1\"");
-
-    // Use email as the environment name for better identification
-    let env_name = config.email.clone();
-
-    println!("export SYFTBOX_ENV_NAME=\"{}\"", env_name);
-
-    // Use VIRTUAL_ENV for compatibility with Powerlevel10k and other prompt tools
-    println!("export SYFTBOX_OLD_VIRTUAL_ENV=\"$VIRTUAL_ENV\"");
-    println!("export VIRTUAL_ENV=\"{}\"", config.data_dir);
-
-    // Customize Powerlevel10k virtualenv display
-    println!("if [ -n \"$ZSH_VERSION\" ]; then");
-    println!("    # For Powerlevel10k - override the virtualenv display format");
-    println!("    if typeset -f _p9k_prompt_virtualenv_init >/dev/null 2>&1; then");
-    println!("        # Save old format settings");
-    println!(
-        "        export SYFTBOX_OLD_P9K_CONTENT=\"${{POWERLEVEL9K_VIRTUALENV_CONTENT_EXPANSION}}\""
-    );
-    println!(
-        "        export SYFTBOX_OLD_P9K_VISUAL=\"${{POWERLEVEL9K_VIRTUALENV_VISUAL_IDENTIFIER_EXPANSION}}\""
-    );
-    println!("        # Override to show box icon and email without 'Py'");
-    println!(
-        "        export POWERLEVEL9K_VIRTUALENV_CONTENT_EXPANSION='📦 {}'",
-        config.email
-    );
-    println!("        export POWERLEVEL9K_VIRTUALENV_VISUAL_IDENTIFIER_EXPANSION=''");
-    println!("        export POWERLEVEL9K_VIRTUALENV_SHOW_PYTHON_VERSION=false");
-    println!("        export POWERLEVEL9K_VIRTUALENV_SHOW_WITH_PYENV=false");
-    println!("    fi");
-    println!("    # For non-Powerlevel10k ZSH");
-    println!("    if [ -z \"$POWERLEVEL9K_LEFT_PROMPT_ELEMENTS\" ]; then");
-    println!("        export SYFTBOX_OLD_PS1=\"$PS1\"");
-    println!("        export PS1=\"📦 ($SYFTBOX_ENV_NAME) $PS1\"");
-    println!("    fi");
-    println!("else");
-    println!("    # Bash - detect if using PROMPT_COMMAND (modern prompt frameworks)");
-    println!("    if [ -n \"$PROMPT_COMMAND\" ]; then");
-    println!("        # Using a prompt framework like Starship, Oh My Bash, etc.");
-    println!("        # Only add decorator if not already active");
-    println!("        if [ -z \"$SYFTBOX_OLD_PROMPT_COMMAND\" ]; then");
-    println!("            export SYFTBOX_OLD_PROMPT_COMMAND=\"$PROMPT_COMMAND\"");
-    println!("            # Create a function to show the decorator");
-    println!("            _sbenv_prompt_decorator() {{");
-    println!("                if [ -n \"$SYFTBOX_ENV_NAME\" ]; then");
-    println!("                    printf '\\033[0m📦 (%s) ' \"$SYFTBOX_ENV_NAME\"");
-    println!("                fi");
-    println!("            }}");
-    println!("            export -f _sbenv_prompt_decorator");
-    println!("            export PROMPT_COMMAND='_sbenv_prompt_decorator; '\"$PROMPT_COMMAND\"");
-    println!("        fi");
-    println!("    else");
-    println!("        # Traditional bash prompt");
-    println!("        if [ -z \"$SYFTBOX_OLD_PS1\" ]; then");
-    println!("            export SYFTBOX_OLD_PS1=\"$PS1\"");
-    println!("            export PS1=\"\\[\\033[0m\\]📦 (${{SYFTBOX_ENV_NAME}}) ${{PS1}}\"");
-    println!("        fi");
-    println!("    fi");
-    println!("fi");
-
-    // Set flag to refresh Powerlevel10k on next prompt (deferred to avoid instant prompt issues)
-    println!("if typeset -f p10k >/dev/null 2>&1; then");
-    println!("    export _SBENV_NEEDS_P10K_RELOAD=1");
-    println!("fi");
-
-    Ok(())
 }
 
-fn deactivate_environment(quiet: bool) -> Result<()> {
-    if !quiet {
-        println!("# Run this command to deactivate the environment:");
-        println!("# eval \"$(sbenv deactivate)\"");
-        println!();
+/// Choose the best syftbox asset for an OS/arch
+fn select_platform_asset(assets: &[(String, String)], target: &Target) -> Option<(String, String)> {
+    let Target { os, arch } = target;
+    let os_tokens = match os.as_str() {
+        "darwin" => vec!["darwin", "macos", "osx", "apple-darwin"],
+        "linux" => vec!["linux", "gnu", "musl", "linux-gnu"],
+        other => vec![other],
+    };
+    let arch_tokens = match arch.as_str() {
+        "arm64" => vec!["arm64", "aarch64"],
+        "x86_64" => vec!["x86_64", "amd64"],
+        other => vec![other],
+    };
+    let mut best: Option<(String, String, i32)> = None; // (url, name, score)
+    for (url, name) in assets {
+        let lname = name.to_lowercase();
+        if !lname.contains("syftbox") || lname.contains("checksum") {
+            continue;
+        }
+        if !os_tokens.iter().any(|t| lname.contains(t)) {
+            continue;
+        }
+        if !arch_tokens.iter().any(|t| lname.contains(t)) {
+            continue;
+        }
+        let score = if lname.ends_with(".tar.gz") || lname.ends_with(".tgz") {
+            3
+        } else if lname.ends_with(".zip") {
+            2
+        } else {
+            1
+        };
+        match &best {
+            None => best = Some((url.clone(), name.clone(), score)),
+            Some((_, _, bs)) if score > *bs => best = Some((url.clone(), name.clone(), score)),
+            _ => {}
+        }
+        if score == 3 {
+            // Good enough
+            break;
+        }
     }
+    best.map(|(u, n, _)| (u, n))
+}
 
-    println!("unset SYFTBOX_EMAIL");
-    println!("unset SYFTBOX_DATA_DIR");
-    println!("unset SYFTBOX_SERVER_URL");
-    println!("unset SYFTBOX_CONFIG_PATH");
-    println!("unset SYFTBOX_CLIENT_URL");
-    println!("unset SYFTBOX_ENV_NAME");
-    println!("unset SYFTBOX_BINARY");
-    println!("unset SYFTBOX_VERSION");
-    println!("unset SYFTBOX_BUILD_HASH");
-    println!("unset SYFTBOX_BUILD_TARGET");
-
-    // Restore VIRTUAL_ENV
-    println!("if [ -n \"$SYFTBOX_OLD_VIRTUAL_ENV\" ]; then");
-    println!("    export VIRTUAL_ENV=\"$SYFTBOX_OLD_VIRTUAL_ENV\"");
-    println!("    unset SYFTBOX_OLD_VIRTUAL_ENV");
-    println!("else");
-    println!("    unset VIRTUAL_ENV");
-    println!("fi");
+fn find_checksums_asset(assets: &[(String, String)]) -> Option<(String, String)> {
+    assets
+        .iter()
+        .find(|(_, name)| {
+            let lname = name.to_lowercase();
+            lname.contains("checksums") || lname.ends_with("sha256sums")
+        })
+        .cloned()
+}
 
-    // Restore Powerlevel10k settings
-    println!("if [ -n \"$ZSH_VERSION\" ]; then");
-    println!("    if [ -n \"$SYFTBOX_OLD_P9K_CONTENT\" ]; then");
-    println!(
-        "        export POWERLEVEL9K_VIRTUALENV_CONTENT_EXPANSION=\"$SYFTBOX_OLD_P9K_CONTENT\""
-    );
-    println!("        unset SYFTBOX_OLD_P9K_CONTENT");
-    println!("    else");
-    println!("        unset POWERLEVEL9K_VIRTUALENV_CONTENT_EXPANSION");
-    println!("    fi");
-    println!("    if [ -n \"$SYFTBOX_OLD_P9K_VISUAL\" ]; then");
-    println!(
-        "        export POWERLEVEL9K_VIRTUALENV_VISUAL_IDENTIFIER_EXPANSION=\"$SYFTBOX_OLD_P9K_VISUAL\""
-    );
-    println!("        unset SYFTBOX_OLD_P9K_VISUAL");
-    println!("    else");
-    println!("        unset POWERLEVEL9K_VIRTUALENV_VISUAL_IDENTIFIER_EXPANSION");
-    println!("    fi");
-    println!("    unset POWERLEVEL9K_VIRTUALENV_SHOW_PYTHON_VERSION");
-    println!("    unset POWERLEVEL9K_VIRTUALENV_SHOW_WITH_PYENV");
-    println!("fi");
+/// Checksums published at `url`; `None` only if there is no file there.
+/// Other failures (network, rate limit, ...) are returned so they aren't mistaken for "unsigned".
+fn fetch_checksums(url: &str) -> Result<Option<HashMap<String, String>>> {
+    let body = match http_get_text(url) {
+        Ok(body) => body,
+        Err(DownloadError::NotFound { .. }) => return Ok(None),
+        Err(e) => return Err(e).context(format!("Failed to fetch checksums from {}", url)),
+    };
+    let sums = parse_checksums(&body);
+    Ok(if sums.is_empty() { None } else { Some(sums) })
+}
 
-    // Restore PS1 or PROMPT_COMMAND for non-Powerlevel10k shells
-    println!("# Restore bash prompt");
-    println!("if [ -n \"$SYFTBOX_OLD_PROMPT_COMMAND\" ]; then");
-    println!("    export PROMPT_COMMAND=\"$SYFTBOX_OLD_PROMPT_COMMAND\"");
-    println!("    unset SYFTBOX_OLD_PROMPT_COMMAND");
-    println!("    # Remove the decorator function if it exists");
-    println!("    if declare -f _sbenv_prompt_decorator >/dev/null 2>&1; then");
-    println!("        unset -f _sbenv_prompt_decorator");
-    println!("    fi");
-    println!("elif [ -n \"$SYFTBOX_OLD_PS1\" ]; then");
-    println!("    export PS1=\"$SYFTBOX_OLD_PS1\"");
-    println!("    unset SYFTBOX_OLD_PS1");
-    println!("fi");
-
-    println!("unset SYFTBOX_ENV_ACTIVE");
-    // No console I/O here to avoid conflicts with instant prompt
+/// Parse `sha256sum` style lines: `<hex digest>  <file name>`
+fn parse_checksums(body: &str) -> HashMap<String, String> {
+    let mut sums = HashMap::new();
+    for line in body.lines() {
+        let mut parts = line.split_whitespace();
+        if let (Some(digest), Some(name)) = (parts.next(), parts.next()) {
+            if digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit()) {
+                let name = name.trim_start_matches('*');
+                sums.insert(name.to_string(), digest.to_lowercase());
+            }
+        }
+    }
+    sums
+}
 
-    Ok(())
+fn sha256_hex(data: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
 }
 
-fn remove_environment(path: Option<PathBuf>) -> Result<()> {
-    let target_path = path.unwrap_or_else(|| env::current_dir().unwrap());
-    let syftbox_dir = target_path.join(".syftbox");
+fn sha256_file(path: &Path) -> Result<String> {
+    use sha2::{Digest, Sha256};
+    let mut file = fs::File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
 
-    if !syftbox_dir.exists() {
-        println!("{}", "No SyftBox environment found at this location".red());
-        return Ok(());
+/// Check a downloaded asset against the release checksums.
+/// Returns the verified digest, or `None` when verification was explicitly skipped.
+fn verify_download(
+    file: &Path,
+    asset_name: &str,
+    checksums: Option<&HashMap<String, String>>,
+    skip_verify: bool,
+) -> Result<Option<String>> {
+    if skip_verify {
+        eprintln!(
+            "   {}",
+            format!("⚠️  Skipping checksum verification of {}", asset_name).yellow()
+        );
+        return Ok(None);
+    }
+    let expected = checksums
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "No checksums file found for this release; refusing to install {}. \
+                 Use --insecure-skip-verify to install it anyway.",
+                asset_name
+            )
+        })?
+        .get(asset_name)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} is not listed in the release checksums; refusing to install it. \
+                 Use --insecure-skip-verify to install it anyway.",
+                asset_name
+            )
+        })?;
+    let actual = sha256_file(file)?;
+    if &actual != expected {
+        return Err(anyhow::anyhow!(
+            "Checksum mismatch for {}: expected {}, got {}. Refusing to install.",
+            asset_name,
+            expected,
+            actual
+        ));
     }
+    Ok(Some(actual))
+}
 
-    let config_path = syftbox_dir.join("config.json");
-    if let Ok(config) = load_config(&config_path) {
-        println!("About to remove SyftBox environment:");
-        println!("  Email: {}", config.email.cyan());
-        println!("  Path: {}", target_path.display().to_string().cyan());
-        println!();
+/// Persist the verified release digest next to a cached binary
+fn record_verified_digest(bin_path: &Path, asset_name: &str, digest: Option<&str>) -> Result<()> {
+    let digest_file = bin_path.with_file_name("syftbox.sha256");
+    match digest {
+        Some(d) => fs::write(&digest_file, format!("{}  {}\n", d, asset_name))?,
+        None => {
+            let _ = fs::remove_file(&digest_file);
+        }
+    }
+    Ok(())
+}
 
-        let confirmation = dialoguer::Confirm::with_theme(&ColorfulTheme::default())
-            .with_prompt("Are you sure you want to remove this environment?")
-            .default(false)
-            .interact()
-            .context("Failed to read confirmation")?;
+/// Verified release digest of a cached binary, if it was checked at download time
+fn read_verified_digest(bin_path: &Path) -> Option<String> {
+    let content = fs::read_to_string(bin_path.with_file_name("syftbox.sha256")).ok()?;
+    content.split_whitespace().next().map(|s| s.to_string())
+}
 
-        if confirmation {
-            unregister_environment(&target_path)?;
-            fs::remove_dir_all(&syftbox_dir).context("Failed to remove .syftbox directory")?;
-            // Remove marker file if present
-            let marker = target_path.join(".sbenv");
-            if marker.exists() {
-                fs::remove_file(marker).ok();
-            }
-            println!("{}", "✅ SyftBox environment removed".green());
+/// Unpack a downloaded asset (tar.gz, zip or bare binary) and move the syftbox binary to `bin_path`
+fn install_syftbox_from_download(
+    tmp_file: &Path,
+    asset_name: &str,
+    tmp_dir: &Path,
+    bin_path: &Path,
+) -> Result<()> {
+    let lname = asset_name.to_lowercase();
+    let archive_err = |reason: String| DownloadError::Archive {
+        asset: asset_name.to_string(),
+        reason,
+    };
+    if lname.ends_with(".tar.gz") || lname.ends_with(".tgz") || lname.ends_with(".zip") {
+        let extract_dir = tmp_dir.join("extract");
+        let _ = fs::remove_dir_all(&extract_dir);
+        fs::create_dir_all(&extract_dir)?;
+        let file = fs::File::open(tmp_file)?;
+        if lname.ends_with(".zip") {
+            zip::ZipArchive::new(file)
+                .and_then(|mut z| z.extract(&extract_dir))
+                .map_err(|e| archive_err(e.to_string()))?;
         } else {
-            println!("{}", "Cancelled".yellow());
+            tar::Archive::new(flate2::read::GzDecoder::new(file))
+                .unpack(&extract_dir)
+                .map_err(|e| archive_err(e.to_string()))?;
         }
+        let found =
+            find_in_dir(&extract_dir, "syftbox").ok_or_else(|| DownloadError::BinaryNotFound {
+                asset: asset_name.to_string(),
+            })?;
+        fs::rename(&found, bin_path)?;
     } else {
-        println!("{}", "Invalid or corrupted environment".red());
+        // Assume it's the binary itself
+        fs::rename(tmp_file, bin_path)?;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perm = fs::metadata(bin_path)?.permissions();
+        perm.set_mode(0o755);
+        fs::set_permissions(bin_path, perm)?;
     }
-
     Ok(())
 }
 
-fn activate_environment_to_file(path: &Path) -> Result<()> {
-    let current_dir = env::current_dir().context("Failed to get current directory")?;
-    let config_path = find_syftbox_config(&current_dir).ok_or_else(|| {
-        anyhow::anyhow!("No SyftBox environment found in current directory or parents")
-    })?;
+fn find_in_dir(dir: &Path, name: &str) -> Option<PathBuf> {
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(d) = stack.pop() {
+        if let Ok(read) = fs::read_dir(&d) {
+            for e in read.flatten() {
+                let p = e.path();
+                if p.is_dir() {
+                    stack.push(p.clone());
+                }
+                if p.file_name().and_then(|n| n.to_str()) == Some(name) {
+                    return Some(p);
+                }
+            }
+        }
+    }
+    None
+}
 
-    let config = load_config(&config_path)?;
+fn resolve_or_install_syftbox(
+    spec: &str,
+    quiet: bool,
+    skip_verify: bool,
+) -> Result<(PathBuf, Option<String>)> {
+    // If spec parses as semver => version
+    if Version::parse(spec).is_ok() {
+        let bin = ensure_syftbox_version(spec, quiet, skip_verify)?;
+        let ver = detect_binary_version(&bin);
+        return Ok((bin, ver));
+    }
+    if is_archive_spec(spec) {
+        return install_syftbox_from_archive_spec(spec, quiet, skip_verify);
+    }
+    if is_git_spec(spec) {
+        return install_syftbox_from_git(spec, quiet);
+    }
+    // A cached build by name (e.g. git-<sha>)
+    if let Some(bin) = cached_binary_path(spec) {
+        return Ok((bin, Some(spec.to_string())));
+    }
+    if is_range_spec(spec) {
+        let version = resolve_version_spec(spec, quiet)?;
+        if !quiet {
+            eprintln!("   {} resolved to syftbox {}", spec, version.cyan());
+        }
+        let bin = ensure_syftbox_version(&version, quiet, skip_verify)?;
+        return Ok((bin, Some(version)));
+    }
+    // Otherwise treat as path
+    let p = PathBuf::from(spec);
+    let path = if p.is_absolute() || p.exists() {
+        p
+    } else {
+        // Fallback: try PATH command name
+        which_syftbox().unwrap_or_else(|| PathBuf::from("syftbox"))
+    };
+    let ver = detect_binary_version(&path);
+    Ok((path, ver))
+}
 
-    // Ensure .sbenv marker exists for this environment
-    let _ = ensure_marker_exists(&config_path, &config);
+/// A release archive given as a local path or URL (`.tar.gz`, `.tgz`, `.zip`)
+fn is_archive_spec(spec: &str) -> bool {
+    let lower = spec.split(['?', '#']).next().unwrap_or(spec).to_lowercase();
+    let is_archive =
+        lower.ends_with(".tar.gz") || lower.ends_with(".tgz") || lower.ends_with(".zip");
+    let is_url = lower.starts_with("http://")
+        || lower.starts_with("https://")
+        || lower.starts_with("file://");
+    is_archive && (is_url || Path::new(spec).is_file())
+}
 
-    let env_name = Path::new(&config.data_dir)
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("sbenv");
+/// Whether a binary spec resolves to a concrete cached version worth recording
+fn spec_pins_version(spec: &str) -> bool {
+    is_semver_spec(spec)
+        || is_range_spec(spec)
+        || is_archive_spec(spec)
+        || is_git_spec(spec)
+        || cached_binary_path(spec).is_some()
+}
 
-    let mut script = String::new();
-    script.push_str(&format!("export SYFTBOX_EMAIL=\"{}\"\n", config.email));
-    script.push_str(&format!(
-        "export SYFTBOX_DATA_DIR=\"{}\"\n",
-        config.data_dir
-    ));
-    script.push_str(&format!(
-        "export SYFTBOX_SERVER_URL=\"{}\"\n",
-        config.server_url
-    ));
-    script.push_str(&format!(
-        "export SYFTBOX_CONFIG_PATH=\"{}\"\n",
-        config_path.display()
-    ));
-    if let Some(url) = &config.client_url {
-        script.push_str(&format!("export SYFTBOX_CLIENT_URL=\"{}\"\n", url));
+/// Cached binary for a plain cache entry name such as `0.8.5` or `git-<sha>`
+fn cached_binary_path(name: &str) -> Option<PathBuf> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return None;
     }
-    let (bin_path, bin_ver) =
-        resolve_binary_for_env(&config_path, false).unwrap_or((PathBuf::from("syftbox"), None));
-    script.push_str(&format!(
-        "export SYFTBOX_BINARY=\"{}\"\n",
-        bin_path.display()
-    ));
-    if let Some(v) = bin_ver {
-        script.push_str(&format!("export SYFTBOX_VERSION=\"{}\"\n", v));
-    }
-    script.push_str("export SYFTBOX_ENV_ACTIVE=\"1\"\n");
-    script.push_str(&format!("export SYFTBOX_ENV_NAME=\"{}\"\n", env_name));
-
-    script.push_str("if [ -n \"$ZSH_VERSION\" ]; then\n");
-    script.push_str("    export SYFTBOX_OLD_PS1=\"$PS1\"\n");
-    script.push_str("    export SYFTBOX_OLD_PROMPT=\"$PROMPT\"\n");
-    script.push_str("    export SYFTBOX_OLD_RPROMPT=\"$RPROMPT\"\n");
-    script.push_str(&format!(
-        "    export PROMPT=\"📦 ({}) $PROMPT\"\n",
-        env_name
-    ));
-    script.push_str(&format!("    export PS1=\"📦 ({}) $PS1\"\n", env_name));
-    script.push_str("else\n");
-    script.push_str("    export SYFTBOX_OLD_PS1=\"$PS1\"\n");
-    script.push_str(&format!("    export PS1=\"📦 ({}) $PS1\"\n", env_name));
-    script.push_str("fi\n");
+    let bin = get_binaries_dir().join(name).join("syftbox");
+    bin.exists().then_some(bin)
+}
 
-    fs::write(path, script).context("Failed to write activation script")?;
+/// Spec to remember as a default: archives and git refs are built once, so point at the
+/// cache entry they produced instead of fetching them again on every resolve
+fn stored_binary_spec(spec: &str, version: Option<&str>) -> String {
+    match version {
+        Some(v) if is_archive_spec(spec) || is_git_spec(spec) => v.to_string(),
+        _ => spec.to_string(),
+    }
+}
 
-    println!("Activation script written to: {}", path.display());
-    println!("Run: source {}", path.display());
+/// Checksums for an archive URL: a `#sha256=<hex>` fragment, or else the `checksums.txt`
+/// published next to the archive
+fn archive_url_checksums(url: &str, asset_name: &str) -> Result<Option<HashMap<String, String>>> {
+    if let Some((_, fragment)) = url.split_once('#') {
+        if let Some(digest) = fragment.strip_prefix("sha256=") {
+            let valid = digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit());
+            return Ok(
+                valid.then(|| HashMap::from([(asset_name.to_string(), digest.to_lowercase())]))
+            );
+        }
+    }
+    let base = url.split(['?', '#']).next().unwrap_or(url);
+    let Some((dir, _)) = base.rsplit_once('/') else {
+        return Ok(None);
+    };
+    fetch_checksums(&format!("{}/checksums.txt", dir))
+}
 
-    Ok(())
+/// Install a syftbox release archive from a local path or URL into the binaries cache,
+/// keyed by the version the extracted binary reports
+fn install_syftbox_from_archive_spec(
+    spec: &str,
+    quiet: bool,
+    skip_verify: bool,
+) -> Result<(PathBuf, Option<String>)> {
+    let is_url = spec.contains("://");
+    let asset_name = spec
+        .split(['?', '#'])
+        .next()
+        .unwrap_or(spec)
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(spec)
+        .to_string();
+
+    let tmp_dir = new_staging_dir()?;
+    let result = (|| -> Result<(PathBuf, Option<String>)> {
+        let tmp_file = tmp_dir.join("download.bin");
+        let mut digest = None;
+        if is_url {
+            let url = spec.split('#').next().unwrap_or(spec);
+            // Look up the checksum first so an unverifiable archive is never downloaded
+            let checksums = if skip_verify {
+                None
+            } else {
+                archive_url_checksums(spec, &asset_name)?
+            };
+            if checksums.is_none() && !skip_verify {
+                return Err(anyhow::anyhow!(
+                    "No checksum found for {}; refusing to install it. Append #sha256=<digest> \
+                     to the URL, publish a checksums.txt next to it, or use --insecure-skip-verify.",
+                    asset_name
+                ));
+            }
+            if !quiet {
+                eprintln!("   Downloading {}...", url.cyan());
+            }
+            download_to_file(url, &tmp_file)?;
+            digest = verify_download(&tmp_file, &asset_name, checksums.as_ref(), skip_verify)?;
+        } else {
+            fs::copy(spec, &tmp_file).with_context(|| format!("Failed to read {}", spec))?;
+        }
+        let staged_dir = tmp_dir.join("stage");
+        fs::create_dir_all(&staged_dir)?;
+        let staged = staged_dir.join("syftbox");
+        install_syftbox_from_download(&tmp_file, &asset_name, &tmp_dir, &staged)?;
+
+        let version = detect_binary_version(&staged)
+            .filter(|v| is_semver_spec(v))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Could not detect the syftbox version of {}; is it built for this platform?",
+                    asset_name
+                )
+            })?;
+        let _lock = lock_cache_entry(&version, quiet)?;
+        let bin_path = get_binaries_dir().join(&version).join("syftbox");
+        if bin_path.exists() {
+            if sha256_file(&bin_path)? == sha256_file(&staged)? {
+                if !quiet {
+                    eprintln!(
+                        "   syftbox {} from {} is already cached",
+                        version, asset_name
+                    );
+                }
+                return Ok((bin_path, Some(version)));
+            }
+            return Err(anyhow::anyhow!(
+                "A different syftbox {} is already cached. Remove it first with: sbenv binary remove {}",
+                version,
+                version
+            ));
+        }
+        // Local archives come without release checksums
+        record_verified_digest(&staged, &asset_name, digest.as_deref())?;
+        let bin_path = publish_cache_entry(&staged_dir, &version)?;
+        if !quiet {
+            eprintln!(
+                "   Installed syftbox {} from {}",
+                version.cyan(),
+                asset_name
+            );
+        }
+        Ok((bin_path, Some(version)))
+    })();
+    let _ = fs::remove_dir_all(&tmp_dir);
+    result
 }
 
-fn get_shell_config_file() -> Result<PathBuf> {
-    let shell = env::var("SHELL").unwrap_or_else(|_| String::from("/bin/bash"));
-    let home = dirs::home_dir().context("Failed to get home directory")?;
+const DEFAULT_GIT_REPO: &str = "https://github.com/OpenMined/syftbox.git";
 
-    let config_file = if shell.contains("zsh") {
-        home.join(".zshrc")
-    } else if shell.contains("bash") {
-        home.join(".bashrc")
-    } else if shell.contains("fish") {
-        home.join(".config").join("fish").join("config.fish")
+fn is_git_spec(spec: &str) -> bool {
+    spec.starts_with("git:")
+}
+
+/// Split a `git:<repo-or-path>@<ref>` spec into repo and ref.
+/// An empty repo means upstream syftbox; a missing ref means the remote HEAD.
+fn parse_git_spec(spec: &str) -> Result<(String, String)> {
+    let rest = spec
+        .strip_prefix("git:")
+        .ok_or_else(|| anyhow::anyhow!("Not a git spec: {}", spec))?;
+    // scp-style remotes (git@host:org/repo) contain '@' too; a ref never contains ':'
+    let (repo, git_ref) = match rest.rsplit_once('@') {
+        Some((repo, r)) if !r.is_empty() && !r.contains(':') => (repo, r),
+        _ => (rest, "HEAD"),
+    };
+    // Both end up on git's command line, where a leading '-' would be read as an option
+    if repo.starts_with('-') || git_ref.starts_with('-') {
+        return Err(anyhow::anyhow!(
+            "Invalid git spec '{}': repo and ref must not start with '-'",
+            spec
+        ));
+    }
+    let repo = if repo.is_empty() {
+        DEFAULT_GIT_REPO.to_string()
+    } else if repo.contains("://") || repo.contains('@') || !Path::new(repo).exists() {
+        repo.to_string()
     } else {
-        // Default to bashrc
-        home.join(".bashrc")
+        fs::canonicalize(repo)
+            .with_context(|| format!("Failed to resolve {}", repo))?
+            .to_string_lossy()
+            .to_string()
     };
+    Ok((repo, git_ref.to_string()))
+}
 
-    Ok(config_file)
+fn get_git_cache_dir() -> PathBuf {
+    let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    Path::new(&home).join(".sbenv").join("git")
 }
 
-fn check_shell_functions_installed(rc_file: &Path) -> Result<bool> {
-    if !rc_file.exists() {
-        return Ok(false);
+fn run_git(dir: &Path, args: &[&str]) -> Result<String> {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .context("Failed to run git. Is git installed?")?;
+    if !out.status.success() {
+        return Err(anyhow::anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
     }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
 
-    let file = fs::File::open(rc_file)?;
-    let reader = BufReader::new(file);
+/// Source checkout cache for a repo
+fn git_source_dir(repo: &str) -> PathBuf {
+    get_git_cache_dir().join(&sha256_hex(repo.as_bytes())[..16])
+}
+
+/// Resolve a ref in a checkout: the remote branch first so a moved branch is picked up,
+/// then tags and commits
+fn resolve_git_commit(src_dir: &Path, git_ref: &str) -> Option<String> {
+    [format!("origin/{}", git_ref), git_ref.to_string()]
+        .iter()
+        .find_map(|r| {
+            run_git(
+                src_dir,
+                &[
+                    "rev-parse",
+                    "--verify",
+                    "--quiet",
+                    &format!("{}^{{commit}}", r),
+                ],
+            )
+            .ok()
+        })
+}
+
+fn git_build_name(commit: &str) -> String {
+    format!("git-{}", &commit[..12.min(commit.len())])
+}
+
+/// Commit a binary built from git was built from, if any
+fn read_git_commit(bin_path: &Path) -> Option<String> {
+    let content = fs::read_to_string(bin_path.with_file_name("commit")).ok()?;
+    content.split_whitespace().next().map(|s| s.to_string())
+}
+
+/// Check out a git ref into the source cache and build syftbox from it with the local
+/// Go toolchain. Builds are cached under `binaries/git-<sha>/`.
+fn install_syftbox_from_git(spec: &str, quiet: bool) -> Result<(PathBuf, Option<String>)> {
+    let (repo, git_ref) = parse_git_spec(spec)?;
+    let src_dir = git_source_dir(&repo);
+    // The checkout is shared, so one build per source at a time
+    let src_name = src_dir
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let _lock = lock_cache_entry(&format!("git-src-{}", src_name), quiet)?;
+
+    if src_dir.join(".git").exists() {
+        if !quiet {
+            eprintln!("   Fetching {}...", repo.cyan());
+        }
+        run_git(
+            &src_dir,
+            &[
+                "fetch",
+                "--force",
+                "--tags",
+                "--",
+                "origin",
+                "+refs/heads/*:refs/remotes/origin/*",
+            ],
+        )?;
+    } else {
+        if !quiet {
+            eprintln!("   Cloning {}...", repo.cyan());
+        }
+        fs::create_dir_all(&src_dir)?;
+        if let Err(e) = run_git(&src_dir, &["clone", "--no-checkout", "--", &repo, "."]) {
+            let _ = fs::remove_dir_all(&src_dir);
+            return Err(e);
+        }
+    }
 
-    for line in reader.lines() {
-        let line = line?;
-        if line.contains("# SyftBox environment functions") || line.contains("sbenv()") {
-            return Ok(true);
+    let commit = resolve_git_commit(&src_dir, &git_ref)
+        .ok_or_else(|| anyhow::anyhow!("Unknown ref '{}' in {}", git_ref, repo))?;
+    let name = git_build_name(&commit);
+    let bin_dir = get_binaries_dir().join(&name);
+    let bin_path = bin_dir.join("syftbox");
+    if bin_path.exists() {
+        if !quiet {
+            eprintln!("   syftbox {} ({}) is already built", name.cyan(), git_ref);
         }
+        return Ok((bin_path, Some(name)));
+    }
+
+    let go_ok = Command::new("go")
+        .arg("version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false);
+    if !go_ok {
+        return Err(anyhow::anyhow!(
+            "Building syftbox from git needs a Go toolchain. Install it from https://go.dev/dl/"
+        ));
+    }
+
+    run_git(
+        &src_dir,
+        &["checkout", "--force", "--detach", &commit, "--"],
+    )?;
+    if !src_dir.join("cmd").join("client").is_dir() {
+        return Err(anyhow::anyhow!(
+            "{} at {} has no ./cmd/client package to build",
+            repo,
+            git_ref
+        ));
     }
 
-    Ok(false)
+    let _build_lock = lock_cache_entry(&name, quiet)?;
+    if bin_path.exists() {
+        return Ok((bin_path, Some(name)));
+    }
+    let tmp_dir = new_staging_dir()?;
+    let result = (|| -> Result<()> {
+        if !quiet {
+            eprintln!(
+                "   Building syftbox {} at {}...",
+                git_ref.cyan(),
+                &commit[..12.min(commit.len())]
+            );
+        }
+        let out = Command::new("go")
+            .args(["build", "-trimpath", "-o"])
+            .arg(tmp_dir.join("syftbox"))
+            .arg("./cmd/client")
+            .current_dir(&src_dir)
+            .stdin(Stdio::null())
+            .output()
+            .context("Failed to run go build")?;
+        if !out.status.success() {
+            return Err(anyhow::anyhow!(
+                "go build failed:\n{}",
+                String::from_utf8_lossy(&out.stderr).trim()
+            ));
+        }
+        fs::write(
+            tmp_dir.join("commit"),
+            format!("{}  {}@{}\n", commit, repo, git_ref),
+        )?;
+        publish_cache_entry(&tmp_dir, &name)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_dir_all(&tmp_dir);
+    }
+    result?;
+    if !quiet {
+        eprintln!("   Built syftbox {}", name.cyan());
+    }
+    Ok((bin_path, Some(name)))
 }
 
-fn check_auto_activation_installed(rc_file: &Path) -> Result<bool> {
-    if !rc_file.exists() {
-        return Ok(false);
+fn detect_binary_version(bin: &Path) -> Option<String> {
+    let out = Command::new(bin).arg("--version").output().ok()?;
+    if !out.status.success() {
+        return None;
     }
+    parse_syftbox_version_output(&String::from_utf8_lossy(&out.stdout))
+}
+
+fn is_semver_spec(spec: &str) -> bool {
+    Version::parse(spec).is_ok()
+}
+
+/// `latest` or a semver range such as `^0.8`, `~0.8.4` or `>=0.8.5,<0.9`
+fn is_range_spec(spec: &str) -> bool {
+    spec == "latest" || (!is_semver_spec(spec) && VersionReq::parse(spec).is_ok())
+}
 
-    let file = fs::File::open(rc_file)?;
-    let reader = BufReader::new(file);
+/// Newest version in `versions` matching a range spec; `latest` means the newest stable release
+fn newest_matching(spec: &str, versions: &[String]) -> Option<String> {
+    let req = if spec == "latest" {
+        None
+    } else {
+        Some(VersionReq::parse(spec).ok()?)
+    };
+    versions
+        .iter()
+        .filter_map(|v| Version::parse(v).ok())
+        .filter(|v| match &req {
+            Some(req) => req.matches(v),
+            None => v.pre.is_empty(),
+        })
+        .max()
+        .map(|v| v.to_string())
+}
 
-    for line in reader.lines() {
-        let line = line?;
-        if line.contains("_sbenv_auto_hook") || line.contains("Auto-activate SyftBox envs") {
-            return Ok(true);
+/// Published and cached versions; falls back to the cache alone when the release source is
+/// unreachable
+fn available_versions(quiet: bool) -> Vec<String> {
+    let mut versions = get_cached_syftbox_versions();
+    match release_source().and_then(|s| s.list_versions()) {
+        Ok(remote) => versions.extend(remote),
+        Err(e) => {
+            if !quiet {
+                eprintln!(
+                    "   {}",
+                    format!(
+                        "⚠️  Could not list syftbox releases ({:#}); using cached versions only",
+                        e
+                    )
+                    .yellow()
+                );
+            }
         }
     }
+    versions
+}
 
-    Ok(false)
+fn resolve_version_spec(spec: &str, quiet: bool) -> Result<String> {
+    newest_matching(spec, &available_versions(quiet))
+        .ok_or_else(|| anyhow::anyhow!("No syftbox release matches '{}'", spec))
 }
 
-fn get_shell_functions() -> String {
-    let mut functions = String::new();
+/// Point an environment at a resolved binary, remembering the version and range it came from
+fn set_env_binary(info: &mut EnvInfo, spec: &str, bin: &Path, version: Option<String>) {
+    info.binary = Some(bin.to_string_lossy().to_string());
+    // Only persist a version when the spec names one; plain paths are re-detected
+    info.binary_version = if spec_pins_version(spec) {
+        version
+    } else {
+        None
+    };
+    info.binary_spec = is_range_spec(spec).then(|| spec.to_string());
+    info.binary_pinned = true;
+    apply_binary_details(info, bin);
+}
 
-    // Add P10k deferred reload handler
-    functions.push_str(
-        "# P10k deferred reload handler to avoid instant prompt issues
-_sbenv_p10k_precmd() {
-    if (( ${+functions[p10k]} )) && [[ -n $_SBENV_NEEDS_P10K_RELOAD ]]; then
-        unset _SBENV_NEEDS_P10K_RELOAD
-        p10k reload 2>/dev/null
-    fi
+const PIN_FILE: &str = ".syftbox-version";
+
+/// Nearest `.syftbox-version` in `start` or one of its parents
+fn find_pin_file(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PIN_FILE))
+        .find(|p| p.is_file())
 }
 
-# Add to precmd hooks if in ZSH
-if [ -n \"$ZSH_VERSION\" ]; then
-    if (( ${+functions[add-zsh-hook]} )); then
-        autoload -Uz add-zsh-hook 2>/dev/null
-        add-zsh-hook precmd _sbenv_p10k_precmd 2>/dev/null
-    fi
-fi
+/// Specs a pin file may hold. Pin files come with checked-out projects, so they may only
+/// select a release, never a path, archive URL or git ref that would run arbitrary code.
+fn is_pin_spec(spec: &str) -> bool {
+    is_semver_spec(spec) || is_range_spec(spec)
+}
 
-",
-    );
-    functions.push_str(
-        "
-# SyftBox environment functions
-",
-    );
-    functions.push_str(
-        "sbenv() {
-",
-    );
-    functions.push_str("    case \"$1\" in\n");
-    functions.push_str(
-        "        activate)
-",
-    );
-    functions.push_str("            eval \"$(command sbenv activate --quiet)\"\n");
-    functions.push_str(
-        "            # Fix Powerlevel10k prompt to show 📦 and email instead of 'Py'
-",
-    );
-    functions
-        .push_str("            if [ -n \"$ZSH_VERSION\" ] && [ -n \"$SYFTBOX_EMAIL\" ]; then\n");
-    functions.push_str(
-        "                export POWERLEVEL9K_VIRTUALENV_CONTENT_EXPANSION=\"📦 $SYFTBOX_EMAIL\"\n",
-    );
-    functions.push_str(
-        "                export POWERLEVEL9K_VIRTUALENV_VISUAL_IDENTIFIER_EXPANSION=''
-",
-    );
-    functions.push_str(
-        "                export POWERLEVEL9K_VIRTUALENV_SHOW_PYTHON_VERSION=false
-",
-    );
-    functions.push_str(
-        "                export POWERLEVEL9K_VIRTUALENV_SHOW_WITH_PYENV=false
-",
-    );
-    functions.push_str(
-        "                # Force P10k to rebuild its prompt cache
-",
-    );
-    functions.push_str(
-        "                unset _p9k__cached_p10k_param_sig 2>/dev/null
-",
+/// Binary spec in a pin file: the first line that is not empty or a `#` comment
+fn read_pin_file(pin: &Path) -> Result<String> {
+    let content =
+        fs::read_to_string(pin).with_context(|| format!("Failed to read {}", pin.display()))?;
+    let spec = content
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('#'))
+        .ok_or_else(|| anyhow::anyhow!("{} is empty", pin.display()))?
+        .to_string();
+    if !is_pin_spec(&spec) {
+        return Err(anyhow::anyhow!(
+            "{} pins '{}', but only a version, range or 'latest' is allowed there. \
+             Use --binary to run a specific path, archive or git build.",
+            pin.display(),
+            spec
+        ));
+    }
+    Ok(spec)
+}
+
+/// Pin file that applies to an environment: nearest to the cwd, else nearest to the env
+fn project_pin(env_dir: &Path) -> Option<PathBuf> {
+    env::current_dir()
+        .ok()
+        .and_then(|cwd| find_pin_file(&cwd))
+        .or_else(|| find_pin_file(env_dir))
+}
+
+/// Binary a pin file selects. Without `install` only the binaries cache is consulted, so
+/// activation on `cd`, `info` and `exec` never list releases or start a download.
+fn resolve_pin_file(pin: &Path, quiet: bool, install: bool) -> Result<(PathBuf, Option<String>)> {
+    let spec = read_pin_file(pin)?;
+    if !install {
+        return spec_cached_version(&spec)
+            .and_then(|v| cached_binary_path(&v).map(|bin| (bin, Some(v))))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "syftbox '{}' pinned in {} is not installed yet; `sbenv start` fetches it",
+                    spec,
+                    pin.display()
+                )
+            });
+    }
+    resolve_or_install_syftbox(&spec, quiet, false).with_context(|| {
+        format!(
+            "Failed to resolve syftbox '{}' pinned in {}",
+            spec,
+            pin.display()
+        )
+    })
+}
+
+/// How to treat a pinned binary whose contents changed since it was pinned
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum IntegrityCheck {
+    Skip,
+    Warn,
+    Strict,
+}
+
+fn integrity_check(strict: bool) -> IntegrityCheck {
+    if strict {
+        IntegrityCheck::Strict
+    } else {
+        IntegrityCheck::Warn
+    }
+}
+
+/// Compare an env's binary with the SHA-256 recorded when it was pinned
+fn verify_binary_integrity(info: &EnvInfo, bin: &Path, check: IntegrityCheck) -> Result<()> {
+    let Some(expected) = info.binary_sha256.as_deref() else {
+        return require_recorded_hash(bin, check);
+    };
+    if check == IntegrityCheck::Skip {
+        return Ok(());
+    }
+    let actual = sha256_file(bin)?;
+    if actual == expected {
+        return Ok(());
+    }
+    let problem = format!(
+        "{} changed since it was pinned (SHA-256 {}, expected {})",
+        bin.display(),
+        actual,
+        expected
     );
-    functions.push_str(
-        "                # Defer P10k reload to avoid instant prompt issues
-",
+    let hint = format!(
+        "If the change is intended, re-pin it with: sbenv edit --binary {}",
+        bin.display()
     );
-    functions.push_str(
-        "                if typeset -f p10k >/dev/null 2>&1; then
-",
-    );
-    functions.push_str(
-        "                    export _SBENV_NEEDS_P10K_RELOAD=1
-",
-    );
-    functions.push_str(
-        "                fi
-",
+    if check == IntegrityCheck::Strict {
+        return Err(anyhow::anyhow!(
+            "{}. Refusing to run it in --strict mode. {}",
+            problem,
+            hint
+        ));
+    }
+    eprintln!("{}", format!("⚠️  {}", problem).yellow());
+    eprintln!("   {}", hint);
+    Ok(())
+}
+
+/// `--strict` only runs binaries with a recorded SHA-256; nothing can be checked otherwise
+fn require_recorded_hash(bin: &Path, check: IntegrityCheck) -> Result<()> {
+    if check != IntegrityCheck::Strict {
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(
+        "No SHA-256 is recorded for {}, so --strict can't check it. Pin it with: sbenv edit --binary {}",
+        bin.display(),
+        bin.display()
+    ))
+}
+
+/// Binary recorded in the registry for an environment, if it is still usable
+fn registered_binary(
+    info: &EnvInfo,
+    quiet: bool,
+    integrity: IntegrityCheck,
+) -> Result<Option<(PathBuf, Option<String>)>> {
+    if let Some(b) = &info.binary {
+        let p = PathBuf::from(b);
+        if p.exists() {
+            verify_binary_integrity(info, &p, integrity)?;
+            let v = detect_binary_version(&p);
+            return Ok(Some((p, v)));
+        }
+    }
+    if let Some(ver) = &info.binary_version {
+        if is_semver_spec(ver) {
+            // Re-fetch for the platform the env was set up for
+            let target = match (&info.binary_os, &info.binary_arch) {
+                (Some(os), Some(arch)) => Target::parse(&format!("{}/{}", os, arch)).ok(),
+                _ => None,
+            };
+            let target = target.unwrap_or_else(Target::host);
+            let bin = ensure_syftbox_target(ver, &target, quiet, false)?;
+            let v = detect_binary_version(&bin).or_else(|| Some(ver.clone()));
+            return Ok(Some((bin, v)));
+        }
+        if let Some(bin) = cached_binary_path(ver) {
+            return Ok(Some((bin, Some(ver.clone()))));
+        }
+    }
+    Ok(None)
+}
+
+/// Binary an environment runs, from what is already installed (see `resolve_pin_file`)
+fn resolve_binary_for_env(config_path: &Path, quiet: bool) -> Result<(PathBuf, Option<String>)> {
+    resolve_binary_for_env_with_source(config_path, quiet, IntegrityCheck::Skip, false)
+        .map(|(bin, ver, _)| (bin, ver))
+}
+
+/// Binary for an environment and where it came from. Precedence: an explicit env pin
+/// (--binary), the project's `.syftbox-version`, the binary the env inherited at init,
+/// the global default, then PATH. A registered binary is checked against its pinned SHA-256.
+/// Pin files and the global default are only fetched when `install` is set (start, login,
+/// `binary which`).
+fn resolve_binary_for_env_with_source(
+    config_path: &Path,
+    quiet: bool,
+    integrity: IntegrityCheck,
+    install: bool,
+) -> Result<(PathBuf, Option<String>, String)> {
+    // Load config to get email for key generation
+    let config = load_config(config_path)?;
+
+    let registry = load_registry()?;
+    let env_dir = config_path.parent().unwrap().parent().unwrap();
+    let env_key = generate_env_key(env_dir, &config.email);
+    let entry = registry.environments.get(&env_key);
+    if let Some(info) = entry.filter(|i| i.binary_pinned) {
+        if let Some((bin, ver)) = registered_binary(info, quiet, integrity)? {
+            return Ok((bin, ver, "environment pin".to_string()));
+        }
+    }
+    if let Some(pin) = project_pin(env_dir) {
+        let (bin, ver) = resolve_pin_file(&pin, quiet, install)?;
+        require_recorded_hash(&bin, integrity)?;
+        return Ok((bin, ver, pin.display().to_string()));
+    }
+    if let Some(info) = entry.filter(|i| !i.binary_pinned) {
+        if let Some((bin, ver)) = registered_binary(info, quiet, integrity)? {
+            return Ok((bin, ver, "current environment".to_string()));
+        }
+    }
+    let (bin, ver, source) = fallback_binary(quiet, install)?;
+    require_recorded_hash(&bin, integrity)?;
+    Ok((bin, ver, source))
+}
+
+/// Binary for an environment without one of its own: the global default, then PATH
+fn fallback_binary(quiet: bool, install: bool) -> Result<(PathBuf, Option<String>, String)> {
+    let gc = load_global_config();
+    if let Some(spec) = gc.default_binary {
+        if install {
+            let (bin, ver) = resolve_or_install_syftbox(&spec, quiet, false)?;
+            return Ok((bin, ver, "global default".to_string()));
+        }
+        if let Some(bin) = default_cached_version().and_then(|v| cached_binary_path(&v)) {
+            let ver = detect_binary_version(&bin);
+            return Ok((bin, ver, "global default".to_string()));
+        }
+        let path = PathBuf::from(&spec);
+        if path.is_file() {
+            let ver = detect_binary_version(&path);
+            return Ok((path, ver, "global default".to_string()));
+        }
+    }
+    // Fallback to PATH
+    if let Some(p) = which_syftbox() {
+        let v = detect_binary_version(&p);
+        return Ok((p, v, "PATH".to_string()));
+    }
+    // Final fallback: plain name (might fail at runtime)
+    Ok((PathBuf::from("syftbox"), None, "PATH".to_string()))
+}
+
+/// Refuse to run an env's binary that was fetched for a different OS/arch
+fn check_binary_platform(config_path: &Path, bin: &Path) -> Result<()> {
+    let config = load_config(config_path)?;
+    let env_dir = config_path.parent().unwrap().parent().unwrap();
+    let registry = load_registry()?;
+    let Some(info) = registry
+        .environments
+        .get(&generate_env_key(env_dir, &config.email))
+    else {
+        return Ok(());
+    };
+    if info.binary.as_deref() != Some(bin.to_string_lossy().as_ref()) {
+        return Ok(());
+    }
+    let (Some(os), Some(arch)) = (&info.binary_os, &info.binary_arch) else {
+        return Ok(());
+    };
+    let host = Target::host();
+    if host.matches(os, arch) {
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(
+        "The syftbox binary of this environment is built for {}/{}, but this machine is {}.\nSwitch to a binary for this machine with: sbenv edit --binary {}",
+        os,
+        arch,
+        host,
+        info.binary_version.as_deref().unwrap_or("<version>")
+    ))
+}
+
+/// Record build details and the verified release digest of an env's binary
+fn apply_binary_details(info: &mut EnvInfo, bin: &Path) -> SyftboxDetails {
+    let d = detect_binary_details(bin);
+    // Source builds record the exact commit they were built from
+    info.binary_hash = read_git_commit(bin).or_else(|| d.hash.clone());
+    info.binary_os = d.os.clone();
+    info.binary_arch = d.arch.clone();
+    info.release_sha256 = read_verified_digest(bin);
+    info.binary_sha256 = sha256_file(bin).ok();
+    d
+}
+
+fn ensure_env_has_binary(env_dir: &Path, email: &str) -> Result<()> {
+    let env_key = generate_env_key(env_dir, email);
+    let mut registry = load_registry()?;
+    if let Some(info) = registry.environments.get_mut(&env_key) {
+        if info.binary.is_none() && info.binary_version.is_none() {
+            let gc = load_global_config();
+            if let Some(spec) = gc.default_binary {
+                let (p, v) = resolve_or_install_syftbox(&spec, false, false)?;
+                set_env_binary(info, &spec, &p, v);
+                // Inherited, so a project's .syftbox-version still takes precedence
+                info.binary_pinned = false;
+                save_registry(&registry)?;
+            } else if let Some(p) = which_syftbox() {
+                info.binary = Some(p.to_string_lossy().to_string());
+                info.binary_version = apply_binary_details(info, &p).version;
+                save_registry(&registry)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Cached version an environment runs, if its binary lives in the binaries cache
+fn env_cached_version(info: &EnvInfo) -> Option<String> {
+    match &info.binary {
+        Some(b) => cache_entry_for_path(Path::new(b)),
+        None => info.binary_version.clone(),
+    }
+}
+
+/// Cache entry (version directory) a path inside the binaries cache belongs to
+fn cache_entry_for_path(path: &Path) -> Option<String> {
+    path.strip_prefix(get_binaries_dir())
+        .ok()
+        .and_then(|rel| rel.components().next())
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+}
+
+/// Map of cached version -> environments (email and path) that still use it
+fn cached_version_references(registry: &EnvRegistry) -> HashMap<String, Vec<String>> {
+    let mut refs: HashMap<String, Vec<String>> = HashMap::new();
+    for info in registry.environments.values() {
+        if let Some(ver) = env_cached_version(info) {
+            refs.entry(ver)
+                .or_default()
+                .push(format!("{} ({})", info.email, info.path));
+        }
+    }
+    for users in refs.values_mut() {
+        users.sort();
+    }
+    refs
+}
+
+/// Cached version the global default points at, if any
+fn default_cached_version() -> Option<String> {
+    spec_cached_version(&load_global_config().default_binary?)
+}
+
+/// Cache entry a binary spec resolves to, using only what is on disk (no downloads or fetches)
+fn spec_cached_version(spec: &str) -> Option<String> {
+    if is_semver_spec(spec) || cached_binary_path(spec).is_some() {
+        return Some(spec.to_string());
+    }
+    if is_range_spec(spec) {
+        return newest_matching(spec, &get_cached_syftbox_versions());
+    }
+    if is_git_spec(spec) {
+        let (repo, git_ref) = parse_git_spec(spec).ok()?;
+        let src_dir = git_source_dir(&repo);
+        if !src_dir.join(".git").exists() {
+            return None;
+        }
+        return resolve_git_commit(&src_dir, &git_ref).map(|c| git_build_name(&c));
+    }
+    cache_entry_for_path(Path::new(spec))
+}
+
+/// Cached versions still referenced: by registered environments, the global default, and
+/// `.syftbox-version` files above those environments or the cwd
+fn cache_references() -> Result<HashMap<String, Vec<String>>> {
+    let registry = load_registry()?;
+    let mut refs = cached_version_references(&registry);
+    if let Some(ver) = default_cached_version() {
+        refs.entry(ver)
+            .or_default()
+            .push("global default".to_string());
+    }
+    let mut pins: Vec<PathBuf> = registry
+        .environments
+        .values()
+        .filter_map(|info| find_pin_file(Path::new(&info.path)))
+        .chain(env::current_dir().ok().and_then(|d| find_pin_file(&d)))
+        .collect();
+    pins.sort();
+    pins.dedup();
+    for pin in pins {
+        if let Some(ver) = read_pin_file(&pin)
+            .ok()
+            .and_then(|spec| spec_cached_version(&spec))
+        {
+            refs.entry(ver).or_default().push(pin.display().to_string());
+        }
+    }
+    Ok(refs)
+}
+
+/// Leftovers of interrupted installs younger than this may still be in use
+const STALE_TMP_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Entries of the binaries cache `sbenv gc` would remove: unreferenced versions not modified
+/// for `days`, plus stale temp dirs and incomplete installs
+fn gc_candidates(
+    refs: &HashMap<String, Vec<String>>,
+    days: u64,
+    now: std::time::SystemTime,
+) -> Vec<(PathBuf, String)> {
+    let age_of = |p: &Path| {
+        fs::metadata(p)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| now.duration_since(t).ok())
+            .unwrap_or_default()
+    };
+    let max_age = Duration::from_secs(days * 24 * 60 * 60);
+    let mut candidates = Vec::new();
+    let Ok(entries) = fs::read_dir(get_binaries_dir()) else {
+        return candidates;
+    };
+    let mut entries: Vec<_> = entries.flatten().filter(|e| e.path().is_dir()).collect();
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let dir = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let age = age_of(&dir);
+        let complete = dir.join("syftbox").exists() || !cached_target_builds(&name).is_empty();
+        if name.starts_with("_tmp") || !complete {
+            if age >= STALE_TMP_AGE {
+                let what = if name.starts_with("_tmp") {
+                    "interrupted install"
+                } else {
+                    "incomplete install"
+                };
+                candidates.push((dir, format!("{} ({})", name, what)));
+            }
+        } else if !refs.contains_key(&name) && age >= max_age {
+            candidates.push((
+                dir,
+                format!(
+                    "{} (unused, {} days old)",
+                    name,
+                    age.as_secs() / (24 * 60 * 60)
+                ),
+            ));
+        }
+    }
+    // Partial downloads are kept for resuming, but not forever
+    if let Ok(parts) = fs::read_dir(get_downloads_dir()) {
+        let mut parts: Vec<_> = parts.flatten().filter(|e| e.path().is_file()).collect();
+        parts.sort_by_key(|e| e.file_name());
+        for part in parts {
+            let age = age_of(&part.path());
+            if age >= max_age {
+                candidates.push((
+                    part.path(),
+                    format!(
+                        "{} (partial download, {} days old)",
+                        part.file_name().to_string_lossy(),
+                        age.as_secs() / (24 * 60 * 60)
+                    ),
+                ));
+            }
+        }
+    }
+    candidates
+}
+
+fn gc_binaries(days: u64, dry_run: bool) -> Result<()> {
+    let refs = cache_references()?;
+    for ver in get_cached_syftbox_versions() {
+        if let Some(users) = refs.get(&ver) {
+            println!("  Keeping {} (used by {})", ver.cyan(), users.join(", "));
+        }
+    }
+    let candidates = gc_candidates(&refs, days, std::time::SystemTime::now());
+    if candidates.is_empty() {
+        println!("{}", "Nothing to collect.".green());
+        return Ok(());
+    }
+
+    let total: u64 = candidates
+        .iter()
+        .map(|(path, _)| match fs::metadata(path) {
+            Ok(m) if m.is_file() => m.len(),
+            _ => dir_size(path),
+        })
+        .sum();
+    if dry_run {
+        println!("Would remove ({}):", format_size(total));
+        for (_, what) in &candidates {
+            println!("  • {}", what);
+        }
+        return Ok(());
+    }
+    for (path, what) in &candidates {
+        if path.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        }
+        .with_context(|| format!("Failed to remove {:?}", path))?;
+        println!("  Removed {}", what);
+    }
+    println!(
+        "{}",
+        format!(
+            "✅ Collected {} cache entr{}, freed {}",
+            candidates.len(),
+            if candidates.len() == 1 { "y" } else { "ies" },
+            format_size(total)
+        )
+        .green()
     );
-    functions.push_str(
-        "            fi
-",
+    Ok(())
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    let mut total = 0;
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(d) = stack.pop() {
+        if let Ok(read) = fs::read_dir(&d) {
+            for e in read.flatten() {
+                match e.metadata() {
+                    Ok(m) if m.is_dir() => stack.push(e.path()),
+                    Ok(m) => total += m.len(),
+                    Err(_) => {}
+                }
+            }
+        }
+    }
+    total
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn binary_list() -> Result<()> {
+    let mut versions = get_cached_syftbox_versions();
+    // Versions only fetched for other platforms
+    let mut foreign_only: Vec<String> = fs::read_dir(get_binaries_dir())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|v| !versions.contains(v) && !cached_target_builds(v).is_empty())
+        .collect();
+    foreign_only.sort();
+    versions.extend(foreign_only);
+    if versions.is_empty() {
+        println!("{}", "No cached syftbox binaries.".yellow());
+        println!(
+            "Use {} to download one.",
+            "sbenv binary install <version>".cyan()
+        );
+        return Ok(());
+    }
+
+    let registry = load_registry()?;
+    let refs = cached_version_references(&registry);
+    let default_ver = default_cached_version();
+
+    println!(
+        "{} ({})",
+        "📦 Cached syftbox binaries".bold(),
+        get_binaries_dir().display()
     );
-    functions.push_str(
-        "            ;;
-",
+    println!();
+    for ver in versions {
+        let dir = get_binaries_dir().join(&ver);
+        let default_label = if default_ver.as_deref() == Some(ver.as_str()) {
+            " (default)"
+        } else {
+            ""
+        };
+        println!("  {}{}", ver.cyan(), default_label);
+        println!("     Size : {}", format_size(dir_size(&dir)));
+        let d = detect_binary_details(&dir.join("syftbox"));
+        if let Some(h) = read_git_commit(&dir.join("syftbox")).or(d.hash) {
+            println!("     Hash : {}", h);
+        }
+        if let Some(g) = d.go_version {
+            println!("     Go   : {}", g);
+        }
+        if let (Some(os), Some(arch)) = (d.os, d.arch) {
+            println!("     Target: {}/{}", os, arch);
+        }
+        if let Some(bt) = d.build_time {
+            println!("     Built: {}", bt);
+        }
+        let targets = cached_target_builds(&ver);
+        if !targets.is_empty() {
+            let names: Vec<String> = targets.iter().map(|t| t.to_string()).collect();
+            println!("     Also for: {}", names.join(", "));
+        }
+        match refs.get(&ver) {
+            Some(users) => {
+                for (i, user) in users.iter().enumerate() {
+                    let label = if i == 0 { "Used by" } else { "       " };
+                    println!("     {}: {}", label, user);
+                }
+            }
+            None => println!("     Used by: {}", "-".dimmed()),
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// Concrete release version for a version, range or channel spec
+fn release_version_for(spec: &str) -> Result<String> {
+    if is_range_spec(spec) {
+        resolve_version_spec(spec, false)
+    } else if is_semver_spec(spec) {
+        Ok(spec.to_string())
+    } else {
+        Err(anyhow::anyhow!(
+            "Invalid version '{}'. Expected a version like 0.8.5, a range like ^0.8 or latest",
+            spec
+        ))
+    }
+}
+
+fn binary_install(spec: &str, skip_verify: bool, target: Option<&Target>) -> Result<()> {
+    let version = release_version_for(spec)?;
+    let target = target.cloned().unwrap_or_else(Target::host);
+    let bin = ensure_syftbox_target(&version, &target, false, skip_verify)?;
+    if target.is_host() {
+        println!("{}", format!("✅ syftbox {} is cached", version).green());
+    } else {
+        println!(
+            "{}",
+            format!("✅ syftbox {} for {} is cached", version, target).green()
+        );
+    }
+    println!("  Path: {}", bin.display().to_string().cyan());
+    Ok(())
+}
+
+/// Directory of a cache entry named on the command line. Only plain entry names
+/// (`0.8.5`, `git-<sha>`) are accepted, never paths that could leave the cache.
+fn cache_entry_dir(name: &str) -> Result<PathBuf> {
+    if name.is_empty()
+        || name.contains(['/', '\\'])
+        || name.contains("..")
+        || Path::new(name).is_absolute()
+    {
+        anyhow::bail!(
+            "'{}' is not a cached version (expected e.g. 0.8.5 or git-<sha>)",
+            name
+        );
+    }
+    let binaries_dir = get_binaries_dir();
+    let dir = binaries_dir.join(name);
+    if let (Ok(base), Ok(target)) = (binaries_dir.canonicalize(), dir.canonicalize()) {
+        if !target.starts_with(&base) || target == base {
+            anyhow::bail!("'{}' is outside the binaries cache", name);
+        }
+    }
+    Ok(dir)
+}
+
+fn binary_remove(version: &str, force: bool) -> Result<()> {
+    let dir = cache_entry_dir(version)?;
+    if !dir.join("syftbox").exists() && cached_target_builds(version).is_empty() {
+        return Err(anyhow::anyhow!("syftbox {} is not cached", version));
+    }
+
+    // Same references prune and gc respect, including .syftbox-version pins
+    let refs = cache_references()?;
+    if let Some(users) = refs.get(version) {
+        if !force {
+            println!(
+                "{}",
+                format!("syftbox {} is still used by:", version).yellow()
+            );
+            for user in users {
+                println!("  • {}", user);
+            }
+            return Err(anyhow::anyhow!(
+                "Refusing to remove a version in use. Use --force to remove it anyway."
+            ));
+        }
+    }
+
+    fs::remove_dir_all(&dir).with_context(|| format!("Failed to remove {:?}", dir))?;
+    println!("{}", format!("✅ Removed syftbox {}", version).green());
+    Ok(())
+}
+
+fn binary_prune(force: bool) -> Result<()> {
+    let refs = cache_references()?;
+
+    let mut to_remove = Vec::new();
+    for ver in get_cached_syftbox_versions() {
+        match refs.get(&ver) {
+            Some(users) => println!("  Keeping {} (used by {})", ver.cyan(), users.join(", ")),
+            None => to_remove.push(ver),
+        }
+    }
+
+    if to_remove.is_empty() {
+        println!("{}", "Nothing to prune.".green());
+        return Ok(());
+    }
+
+    let total: u64 = to_remove
+        .iter()
+        .map(|v| dir_size(&get_binaries_dir().join(v)))
+        .sum();
+    println!(
+        "Unused versions: {} ({})",
+        to_remove.join(", ").yellow(),
+        format_size(total)
     );
-    functions.push_str(
-        "        deactivate)
-",
+
+    let confirm = if force || !atty::is(atty::Stream::Stdin) {
+        true
+    } else {
+        Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Remove these versions?")
+            .default(true)
+            .interact()?
+    };
+    if !confirm {
+        println!("{}", "Cancelled".yellow());
+        return Ok(());
+    }
+
+    for ver in &to_remove {
+        let dir = get_binaries_dir().join(ver);
+        fs::remove_dir_all(&dir).with_context(|| format!("Failed to remove {:?}", dir))?;
+    }
+    println!(
+        "{}",
+        format!(
+            "✅ Pruned {} version(s), freed {}",
+            to_remove.len(),
+            format_size(total)
+        )
+        .green()
     );
-    functions.push_str("            eval \"$(command sbenv deactivate --quiet)\"\n");
-    functions.push_str(
-        "            # Reset P10k virtualenv display
-",
-    );
-    functions.push_str("            if [ -n \"$ZSH_VERSION\" ]; then\n");
-    functions.push_str(
-        "                export POWERLEVEL9K_VIRTUALENV_CONTENT_EXPANSION='${VIRTUAL_ENV:t}'
-",
-    );
-    functions.push_str(
-        "                export POWERLEVEL9K_VIRTUALENV_SHOW_PYTHON_VERSION=false
-",
-    );
-    functions.push_str(
-        "                unset _p9k__cached_p10k_param_sig 2>/dev/null
-",
-    );
-    functions.push_str(
-        "                # Defer P10k reload to avoid instant prompt issues
-",
-    );
-    functions.push_str(
-        "                if typeset -f p10k >/dev/null 2>&1; then
-",
-    );
-    functions.push_str(
-        "                    export _SBENV_NEEDS_P10K_RELOAD=1
-",
-    );
-    functions.push_str(
-        "                fi
-",
-    );
-    functions.push_str(
-        "            fi
-",
-    );
-    functions.push_str(
-        "            ;;
-",
-    );
-    functions.push_str(
-        "        *)
-",
-    );
-    functions.push_str("            command sbenv \"$@\"\n");
-    functions.push_str(
-        "            ;;
-",
-    );
-    functions.push_str(
-        "    esac
-",
-    );
-    functions.push_str(
-        "}
-",
-    );
-    functions.push('\n');
-    functions.push_str(
-        "# SyftBox environment aliases
-",
-    );
-    functions.push_str(
-        "alias sba='sbenv activate'
-",
-    );
-    functions.push_str(
-        "alias sbd='sbenv deactivate'
-",
-    );
-    functions.push_str(
-        "alias sbi='sbenv info'
-",
+    Ok(())
+}
+
+/// Environments tracking a version range whose newest matching release is newer than what
+/// they run
+fn binary_outdated() -> Result<()> {
+    let registry = load_registry()?;
+    let mut tracked: Vec<&EnvInfo> = registry
+        .environments
+        .values()
+        .filter(|info| info.binary_spec.is_some())
+        .collect();
+    if tracked.is_empty() {
+        println!(
+            "{}",
+            "No environments track a version range or channel.".yellow()
+        );
+        println!("Use {} to follow one.", "sbenv edit --binary '^0.8'".cyan());
+        return Ok(());
+    }
+    tracked.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let versions = available_versions(false);
+    let mut outdated = 0;
+    for info in tracked {
+        let spec = info.binary_spec.as_deref().unwrap_or_default();
+        let current = info.binary_version.as_deref().unwrap_or("?");
+        let newest = newest_matching(spec, &versions);
+        let is_newer = match (&newest, Version::parse(current)) {
+            (Some(n), Ok(cur)) => Version::parse(n).map(|n| n > cur).unwrap_or(false),
+            (Some(_), Err(_)) => true,
+            (None, _) => false,
+        };
+        println!("  {} ({})", info.email.cyan(), info.path);
+        println!("     Spec   : {}", spec);
+        println!("     Current: {}", current);
+        match newest {
+            Some(n) if is_newer => {
+                outdated += 1;
+                println!("     Newest : {}", n.green());
+                println!(
+                    "     Update : cd {} && sbenv edit --binary '{}'",
+                    info.path, spec
+                );
+            }
+            Some(_) => println!("     Newest : {}", "up to date".dimmed()),
+            None => println!("     Newest : {}", "no matching release found".yellow()),
+        }
+        println!();
+    }
+    if outdated == 0 {
+        println!("{}", "✅ All environments are up to date".green());
+    } else {
+        println!(
+            "{}",
+            format!("{} environment(s) can be updated", outdated).yellow()
+        );
+    }
+    Ok(())
+}
+
+fn binary_which() -> Result<()> {
+    let current_dir = env::current_dir().context("Failed to get current directory")?;
+    let (bin, ver, source) = if let Some(config_path) = find_syftbox_config(&current_dir) {
+        resolve_binary_for_env_with_source(&config_path, false, IntegrityCheck::Warn, true)?
+    } else if let Some(pin) = find_pin_file(&current_dir) {
+        let (b, v) = resolve_pin_file(&pin, false, true)?;
+        (b, v, pin.display().to_string())
+    } else if let Some(spec) = load_global_config().default_binary {
+        let (b, v) = resolve_or_install_syftbox(&spec, false, false)?;
+        (b, v, "global default".to_string())
+    } else if let Some(p) = which_syftbox() {
+        let v = detect_binary_version(&p);
+        (p, v, "PATH".to_string())
+    } else {
+        return Err(anyhow::anyhow!("No syftbox binary found"));
+    };
+    println!("{}", bin.display());
+    if let Some(v) = ver {
+        eprintln!("  Version: {}", v.cyan());
+    }
+    eprintln!("  From   : {}", source.dimmed());
+    Ok(())
+}
+
+fn default_binary_set(spec: &str, skip_verify: bool) -> Result<()> {
+    let (bin, ver) = resolve_or_install_syftbox(spec, false, skip_verify)?;
+    save_default_binary(spec, ver.as_deref())?;
+    println!("{}", "✅ Global default syftbox binary updated".green());
+    println!("  Spec: {}", spec.cyan());
+    println!("  Path: {}", bin.display().to_string().cyan());
+    if let Some(v) = ver {
+        println!("  Version: {}", v.cyan());
+    }
+    Ok(())
+}
+
+fn save_default_binary(spec: &str, version: Option<&str>) -> Result<()> {
+    let mut gc = load_global_config();
+    gc.default_binary = Some(stored_binary_spec(spec, version));
+    save_global_config(&gc)
+}
+
+fn default_binary_unset() -> Result<()> {
+    let mut gc = load_global_config();
+    if gc.default_binary.take().is_none() {
+        println!("{}", "No global default syftbox binary is set.".yellow());
+        return Ok(());
+    }
+    save_global_config(&gc)?;
+    println!("{}", "✅ Global default syftbox binary cleared".green());
+    println!("  New environments will use syftbox from PATH. Existing environments are unchanged.");
+    Ok(())
+}
+
+fn default_binary_show() -> Result<()> {
+    let Some(spec) = load_global_config().default_binary else {
+        println!("{}", "No global default syftbox binary is set.".yellow());
+        println!(
+            "Set one with {}",
+            "sbenv default-binary set <version|path>".cyan()
+        );
+        return Ok(());
+    };
+    println!("{}", spec);
+    // Report what it points at without downloading anything
+    let bin = default_cached_version()
+        .and_then(|v| cached_binary_path(&v))
+        .or_else(|| Some(PathBuf::from(&spec)).filter(|p| p.exists()));
+    match bin {
+        Some(bin) => {
+            eprintln!("  Path   : {}", bin.display().to_string().cyan());
+            if let Some(v) = detect_binary_version(&bin) {
+                eprintln!("  Version: {}", v.cyan());
+            }
+        }
+        None => eprintln!("  {}", "Not installed yet; fetched on first use".dimmed()),
+    }
+    Ok(())
+}
+
+/// Write (or show / remove) the `.syftbox-version` pin for the current directory
+fn pin_binary(spec: Option<&str>, unset: bool) -> Result<()> {
+    let current_dir = env::current_dir().context("Failed to get current directory")?;
+    let pin = current_dir.join(PIN_FILE);
+    if unset {
+        if !pin.exists() {
+            return Err(anyhow::anyhow!("No {} in this directory", PIN_FILE));
+        }
+        fs::remove_file(&pin)?;
+        println!("{}", format!("✅ Removed {}", pin.display()).green());
+        return Ok(());
+    }
+    let Some(spec) = spec else {
+        match find_pin_file(&current_dir) {
+            Some(pin) => {
+                println!("{}", read_pin_file(&pin)?);
+                eprintln!("  Pinned in: {}", pin.display().to_string().dimmed());
+            }
+            None => println!(
+                "{}",
+                format!("No {} found here or in parent directories.", PIN_FILE).yellow()
+            ),
+        }
+        return Ok(());
+    };
+
+    if !is_pin_spec(spec) {
+        return Err(anyhow::anyhow!(
+            "Only a version, range or 'latest' can be pinned in {}",
+            PIN_FILE
+        ));
+    }
+    // Make sure the spec resolves before teammates start relying on it
+    let (bin, ver) = resolve_or_install_syftbox(spec, false, false)?;
+    fs::write(&pin, format!("{}\n", spec))
+        .with_context(|| format!("Failed to write {}", pin.display()))?;
+    println!(
+        "{}",
+        format!("✅ Pinned syftbox {} in {}", spec, pin.display()).green()
     );
-    functions
+    println!("  Path: {}", bin.display().to_string().cyan());
+    if let Some(v) = ver {
+        println!("  Version: {}", v.cyan());
+    }
+    println!("  Environments below this directory use it unless they set --binary explicitly.");
+    Ok(())
+}
+
+fn find_syftbox_config(start_dir: &Path) -> Option<PathBuf> {
+    let mut current = start_dir.to_path_buf();
+    loop {
+        let config_path = current.join(".syftbox").join("config.json");
+        if config_path.exists() {
+            return Some(config_path);
+        }
+        if !current.pop() {
+            break;
+        }
+    }
+    None
+}
+
+fn load_config(config_path: &Path) -> Result<SyftBoxConfig> {
+    let content = fs::read_to_string(config_path)
+        .with_context(|| format!("Failed to read config from {:?}", config_path))?;
+    let config: SyftBoxConfig = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse config from {:?}", config_path))?;
+    Ok(config)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn init_environment_with_binary(
+    email: Option<String>,
+    server_url: Option<String>,
+    dev: bool,
+    binary: Option<String>,
+    quiet: bool,
+    skip_verify: bool,
+    make_default: bool,
+    target: Option<&Target>,
+) -> Result<()> {
+    let current_dir = env::current_dir().context("Failed to get current directory")?;
+    let syftbox_dir = current_dir.join(".syftbox");
+
+    if syftbox_dir.exists() {
+        println!(
+            "{}",
+            "A SyftBox environment already exists in this directory!".red()
+        );
+        return Ok(());
+    }
+
+    let email = if let Some(email) = email {
+        email
+    } else {
+        if quiet {
+            return Err(anyhow::anyhow!(
+                "Email address is required when using --quiet flag. Use -e <email> to provide it."
+            ));
+        }
+        Input::<String>::with_theme(&ColorfulTheme::default())
+            .with_prompt("Email address")
+            .interact_text()
+            .context("Failed to read email input")?
+    };
+
+    let port = find_available_port().context("Failed to find available port")?;
+    let client_url = format!("http://127.0.0.1:{}", port);
+    let resolved_server_url = match server_url {
+        Some(url) => url,
+        None => {
+            if dev {
+                "http://localhost:8080".to_string()
+            } else {
+                "https://syftbox.net".to_string()
+            }
+        }
+    };
+
+    // Generate a random token for the control plane API
+    let client_token = generate_client_token();
+
+    let config = SyftBoxConfig {
+        data_dir: current_dir.to_string_lossy().to_string(),
+        email: email.clone(),
+        server_url: resolved_server_url.clone(),
+        client_url: Some(client_url.clone()),
+        client_token: Some(client_token.clone()),
+        refresh_token: None,
+        dev_mode: dev,
+    };
+
+    fs::create_dir_all(&syftbox_dir).context("Failed to create .syftbox directory")?;
+
+    let config_path = syftbox_dir.join("config.json");
+    let config_json =
+        serde_json::to_string_pretty(&config).context("Failed to serialize config")?;
+    fs::write(&config_path, config_json).context("Failed to write config file")?;
+
+    register_environment(&current_dir, &config)?;
+
+    // Check if syftbox is available, prompt for install if not
+    let binary_to_use = if binary.is_some() {
+        binary
+    } else {
+        // Check if syftbox is in PATH
+        let syftbox_in_path = which_syftbox().is_some();
+
+        if !syftbox_in_path {
+            if quiet {
+                // In quiet mode, automatically try to get the latest version
+                println!("📦 SyftBox not found in PATH. Fetching latest version...");
+                match fetch_latest_syftbox_version() {
+                    Ok(version) => Some(version),
+                    Err(e) => {
+                        // If we can't fetch latest, try to use a cached version
+                        let cached = get_cached_syftbox_versions();
+                        if !cached.is_empty() {
+                            println!("   Could not fetch latest version: {}", e);
+                            println!("   Using cached version: {}", cached[0]);
+                            Some(cached[0].clone())
+                        } else {
+                            println!("⚠️  Could not fetch latest version and no cached versions available");
+                            println!("   Error: {}", e);
+                            None
+                        }
+                    }
+                }
+            } else {
+                // Interactive mode - prompt user
+                // This will show cached versions and option to download latest
+                if let Ok(Some(version)) = prompt_for_syftbox_install() {
+                    Some(version)
+                } else {
+                    None
+                }
+            }
+        } else {
+            None
+        }
+    };
+
+    // Resolve and persist binary preference
+    if let Some(bin_spec) = binary_to_use {
+        println!("📦 Setting up SyftBox binary...");
+        let target = target.filter(|t| !t.is_host());
+        let (bin_path, bin_ver) = match target {
+            // Builds for another platform can't be run here to detect their version
+            Some(t) => {
+                let ver = release_version_for(&bin_spec)?;
+                (
+                    ensure_syftbox_target(&ver, t, false, skip_verify)?,
+                    Some(ver),
+                )
+            }
+            None => resolve_or_install_syftbox(&bin_spec, false, skip_verify)?,
+        };
+        println!("✅ SyftBox binary configured successfully!");
+        // Update registry entry
+        let mut registry = load_registry()?;
+        let env_key = generate_env_key(&current_dir, &email);
+        if let Some(info) = registry.environments.get_mut(&env_key) {
+            set_env_binary(info, &bin_spec, &bin_path, bin_ver.clone());
+            if let Some(t) = target {
+                info.binary_os = Some(t.os.clone());
+                info.binary_arch = Some(t.arch.clone());
+            }
+        }
+        save_registry(&registry)?;
+        if let Some(t) = target {
+            println!(
+                "   Built for {}; {} refuses to run it on other platforms",
+                t.to_string().cyan(),
+                "sbenv start".yellow()
+            );
+        }
+
+        if make_default {
+            save_default_binary(&bin_spec, bin_ver.as_deref())?;
+            println!("✅ Also set as the global default for new environments");
+        } else if load_global_config().default_binary.is_none() {
+            println!(
+                "   Tip: run {} to use it for new environments too",
+                format!("sbenv default-binary set {}", bin_spec).cyan()
+            );
+        }
+    } else {
+        // If no spec, ensure global default exists (noop if not set)
+        let _ = ensure_env_has_binary(&current_dir, &email);
+    }
+
+    // Write a marker file so other tools can detect the environment
+    // This will include binary info if it was set in the registry
+    let _ = ensure_marker_exists(&config_path, &config);
+
+    println!("{}", "✅ SyftBox environment initialized!".green().bold());
+    println!();
+    println!("📧 Email: {}", email.cyan());
+    println!("🌐 Server: {}", resolved_server_url.cyan());
+    println!("📁 Data dir: {}", current_dir.display().to_string().cyan());
+    println!("🔌 Client port: {}", port.to_string().cyan());
+    // Show resolved binary information
+    if let Ok((bin_path, bin_ver)) = resolve_binary_for_env(&config_path, false) {
+        println!("🛠 Binary: {}", bin_path.display().to_string().cyan());
+        if let Some(v) = bin_ver {
+            println!("🔢 Version: {}", v.cyan());
+        }
+    }
+    println!();
+    println!("Run {} to see this info again", "sbenv info".yellow());
+    println!(
+        "Run {} to activate this environment",
+        "sbenv activate".yellow()
+    );
+
+    Ok(())
+}
+
+fn show_info() -> Result<()> {
+    let current_dir = env::current_dir().context("Failed to get current directory")?;
+    let config_path = find_syftbox_config(&current_dir).ok_or_else(|| {
+        anyhow::anyhow!("No SyftBox environment found in current directory or parents")
+    })?;
+
+    let config = load_config(&config_path)?;
+
+    // Register the environment if not already registered
+    let env_dir = config_path.parent().unwrap().parent().unwrap();
+    let _ = register_environment(env_dir, &config);
+
+    let _port = config
+        .client_url
+        .as_deref()
+        .and_then(|u| u.rsplit(':').next())
+        .unwrap_or("unknown");
+
+    println!("{}", "📦 SyftBox Environment Info".green().bold());
+    println!();
+    println!("{}", "── Local Environment ──".dimmed());
+    println!("📧 Email: {}", config.email.cyan());
+    println!("🌐 Server URL: {}", config.server_url.cyan());
+    println!("📁 Data dir: {}", config.data_dir.cyan());
+    println!(
+        "🔌 Client URL: {}",
+        config.client_url.as_deref().unwrap_or("not set").cyan()
+    );
+    println!(
+        "⚙️  Dev mode: {}",
+        if config.dev_mode {
+            "enabled".green()
+        } else {
+            "disabled".dimmed()
+        }
+    );
+    println!(
+        "📄 Config path: {}",
+        config_path.display().to_string().cyan()
+    );
+
+    // Show binary details resolved for this environment
+    if let Ok((bin_path, bin_ver)) = resolve_binary_for_env(&config_path, false) {
+        println!("🛠 Binary: {}", bin_path.display().to_string().cyan());
+        if let Some(v) = bin_ver {
+            println!("🔢 Version: {}", v.cyan());
+        }
+        let d = detect_binary_details(&bin_path);
+        if let Some(h) = d.hash {
+            println!("    Hash: {}", h.cyan());
+        }
+        if let (Some(os), Some(arch)) = (d.os, d.arch) {
+            println!("    Target: {}/{}", os.cyan(), arch.cyan());
+        }
+    }
+
+    // Show raw config.json content
+    println!();
+    println!("{}", "── Config File Content ──".dimmed());
+    if let Ok(config_content) = fs::read_to_string(&config_path) {
+        // Parse and pretty print the JSON
+        if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(&config_content) {
+            if let Ok(pretty_json) = serde_json::to_string_pretty(&json_value) {
+                for line in pretty_json.lines() {
+                    println!("  {}", line.dimmed());
+                }
+            }
+        }
+    }
+
+    // Show global sbenv registry info
+    println!();
+    println!("{}", "── Global sbenv Registry ──".dimmed());
+    let registry = load_registry().unwrap_or(EnvRegistry {
+        environments: HashMap::new(),
+    });
+
+    let env_dir_str = env_dir.to_string_lossy().to_string();
+    let env_name = env_dir
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown");
+    let env_key = generate_env_key(env_dir, &config.email);
+
+    if let Some(env_info) = registry.environments.get(&env_key) {
+        println!("📝 Registered as: {}", env_name.cyan());
+        println!("🏠 Location: {}", env_info.path.cyan());
+        println!("🔗 Server: {}", env_info.server_url.cyan());
+        println!("🔌 Port: {}", env_info.port.to_string().cyan());
+        println!(
+            "⚙️  Dev mode: {}",
+            if env_info.dev_mode {
+                "enabled".green()
+            } else {
+                "disabled".dimmed()
+            }
+        );
+        if let Some(b) = &env_info.binary {
+            println!("🛠  Binary: {}", b.cyan());
+        }
+        if let Some(v) = &env_info.binary_version {
+            println!("    Version: {}", v.cyan());
+        }
+        if let Some(spec) = &env_info.binary_spec {
+            println!("    Tracks: {}", spec.cyan());
+        }
+        if let Some(h) = &env_info.binary_hash {
+            println!("    Hash: {}", h.cyan());
+        }
+        if let Some(d) = &env_info.release_sha256 {
+            println!("    SHA-256 (verified): {}", d.cyan());
+        }
+        if let Some(sha) = &env_info.binary_sha256 {
+            println!("    Executable SHA-256 (pinned): {}", sha.cyan());
+        }
+        if env_info.binary_os.is_some() || env_info.binary_arch.is_some() {
+            println!(
+                "    Target: {}/{}",
+                env_info.binary_os.as_deref().unwrap_or("?"),
+                env_info.binary_arch.as_deref().unwrap_or("?")
+            );
+        }
+    } else {
+        println!("⚠️  Not registered in global sbenv");
+        println!("   Run {} to register", "sbenv init".yellow());
+    }
+
+    // Show all registered environments
+    if registry.environments.len() > 1 {
+        println!();
+        println!("{}", "── Other Environments ──".dimmed());
+        for (name, info) in registry.environments.iter() {
+            if info.path != env_dir_str {
+                println!("  • {} ({})", name.cyan(), info.path.dimmed());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Shell an activation script is written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Nu,
+    Pwsh,
+}
+
+impl Shell {
+    /// Shell named by $SHELL, bash if it is something else
+    fn detect() -> Self {
+        Self::from_program(&env::var("SHELL").unwrap_or_default()).unwrap_or(Shell::Bash)
+    }
+
+    /// Shell a program path runs, `None` for shells sbenv has no emitter for (sh, dash, ksh...)
+    fn from_program(program: &str) -> Option<Self> {
+        let name = program.rsplit('/').next().unwrap_or("");
+        if name.contains("fish") {
+            Some(Shell::Fish)
+        } else if name.contains("zsh") {
+            Some(Shell::Zsh)
+        } else if name == "nu" {
+            Some(Shell::Nu)
+        } else if name.starts_with("pwsh") || name.starts_with("powershell") {
+            Some(Shell::Pwsh)
+        } else if name == "bash" {
+            Some(Shell::Bash)
+        } else {
+            None
+        }
+    }
+
+    /// Script that sets up `act` in this shell
+    fn activation_script(self, act: &Activation) -> String {
+        let mut s = match self {
+            Shell::Bash | Shell::Zsh => posix_activation_script(act),
+            Shell::Fish => fish_activation_script(&act.vars, &act.data_dir),
+            Shell::Nu => {
+                // Keep the VIRTUAL_ENV from before the first activation only
+                let old = env::var("VIRTUAL_ENV")
+                    .ok()
+                    .filter(|_| env::var_os("SYFTBOX_ENV_ACTIVE").is_none());
+                nu_activation_script(act, old)
+            }
+            Shell::Pwsh => pwsh_activation_script(act),
+        };
+        if let Some(dir) = &act.cd_to {
+            match self {
+                Shell::Bash | Shell::Zsh => s.push_str(&format!("cd -- {}\n", posix_quote(dir))),
+                Shell::Fish => s.push_str(&format!("cd {}\n", fish_quote(dir))),
+                Shell::Pwsh => {
+                    s.push_str(&format!("Set-Location -LiteralPath {}\n", pwsh_quote(dir)))
+                }
+                // Part of the record; `sba` does the cd
+                Shell::Nu => {}
+            }
+        }
+        s
+    }
+
+    /// Script that undoes `activation_script`
+    fn deactivation_script(self) -> String {
+        match self {
+            Shell::Bash | Shell::Zsh => posix_deactivation_script(),
+            Shell::Fish => fish_deactivation_script(),
+            Shell::Nu => nu_deactivation_script(env::var("SYFTBOX_OLD_VIRTUAL_ENV").ok()),
+            Shell::Pwsh => pwsh_deactivation_script(),
+        }
+    }
+
+    /// How to apply the output of `sbenv <command>` by hand
+    fn eval_hint(self, command: &str) -> String {
+        match self {
+            Shell::Bash | Shell::Zsh => format!("eval \"$(sbenv {})\"", command),
+            Shell::Fish => format!("sbenv {} --shell fish | source", command),
+            Shell::Nu if command == "activate" => {
+                "sbenv activate --shell nu | from json | load-env".to_string()
+            }
+            Shell::Nu => "sbd  # from `sbenv install-shell --manual`".to_string(),
+            Shell::Pwsh => format!(
+                "sbenv {} --shell pwsh | Out-String | Invoke-Expression",
+                command
+            ),
+        }
+    }
+
+    /// How to load an activation script written to `path`
+    fn source_hint(self, path: &Path) -> String {
+        match self {
+            Shell::Nu => format!("open {} | load-env", path.display()),
+            Shell::Pwsh => format!(". {}", path.display()),
+            _ => format!("source {}", path.display()),
+        }
+    }
+}
+
+/// Everything activating an environment sets, independent of the shell
+struct Activation {
+    vars: Vec<(&'static str, String)>,
+    data_dir: String,
+    /// Directory to change to afterwards (`activate --cd`)
+    cd_to: Option<String>,
+}
+
+impl Activation {
+    fn new(config: &SyftBoxConfig, config_path: &Path, quiet: bool) -> Self {
+        // Use email as the environment name for better identification
+        Activation {
+            vars: activation_vars(config, config_path, &config.email, quiet),
+            data_dir: config.data_dir.clone(),
+            cd_to: None,
+        }
+    }
+}
+
+/// Variables that describe the active environment, in the order they are set
+const ACTIVATION_VARS: &[&str] = &[
+    "SYFTBOX_EMAIL",
+    "SYFTBOX_DATA_DIR",
+    "SYFTBOX_SERVER_URL",
+    "SYFTBOX_CONFIG_PATH",
+    "SYFTBOX_CLIENT_URL",
+    "SYFTBOX_BINARY",
+    "SYFTBOX_VERSION",
+    "SYFTBOX_BUILD_HASH",
+    "SYFTBOX_BUILD_TARGET",
+    "SYFTBOX_ENV_ACTIVE",
+    "SYFTBOX_ENV_NAME",
+];
+
+/// Values of `ACTIVATION_VARS` for an environment; unknown ones are left out
+fn activation_vars(
+    config: &SyftBoxConfig,
+    config_path: &Path,
+    env_name: &str,
+    quiet: bool,
+) -> Vec<(&'static str, String)> {
+    let mut vars = vec![
+        ("SYFTBOX_EMAIL", config.email.clone()),
+        ("SYFTBOX_DATA_DIR", config.data_dir.clone()),
+        ("SYFTBOX_SERVER_URL", config.server_url.clone()),
+        ("SYFTBOX_CONFIG_PATH", config_path.display().to_string()),
+    ];
+    if let Some(url) = &config.client_url {
+        vars.push(("SYFTBOX_CLIENT_URL", url.clone()));
+    }
+    // Resolve syftbox binary + version for this env (fallback to 'syftbox')
+    let (bin_path, bin_ver) =
+        resolve_binary_for_env(config_path, quiet).unwrap_or((PathBuf::from("syftbox"), None));
+    vars.push(("SYFTBOX_BINARY", bin_path.display().to_string()));
+    if let Some(v) = bin_ver {
+        vars.push(("SYFTBOX_VERSION", v));
+    }
+    let d = detect_binary_details(&bin_path);
+    if let Some(h) = d.hash {
+        vars.push(("SYFTBOX_BUILD_HASH", h));
+    }
+    if let (Some(os), Some(arch)) = (d.os, d.arch) {
+        vars.push(("SYFTBOX_BUILD_TARGET", format!("{}/{}", os, arch)));
+    }
+    vars.push(("SYFTBOX_ENV_ACTIVE", "1".to_string()));
+    vars.push(("SYFTBOX_ENV_NAME", env_name.to_string()));
+    vars
+}
+
+/// Single-quoted POSIX shell word; nothing inside single quotes is expanded,
+/// and a `'` is written as `'\''`
+fn posix_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Single-quoted fish string
+fn fish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Activation script for fish: `set -gx` variables and a 📦 prefix on fish_prompt
+fn fish_activation_script(vars: &[(&str, String)], data_dir: &str) -> String {
+    let mut s = String::new();
+    // Keep the VIRTUAL_ENV from before the first activation only
+    s.push_str("if not set -q SYFTBOX_ENV_ACTIVE; and set -q VIRTUAL_ENV\n");
+    s.push_str("    set -gx SYFTBOX_OLD_VIRTUAL_ENV $VIRTUAL_ENV\n");
+    s.push_str("end\n");
+    for (name, value) in vars {
+        s.push_str(&format!("set -gx {} {}\n", name, fish_quote(value)));
+    }
+    s.push_str(&format!("set -gx VIRTUAL_ENV {}\n", fish_quote(data_dir)));
+    s.push_str("if not functions -q _sbenv_old_fish_prompt\n");
+    s.push_str(
+        "    functions -q fish_prompt; and functions -c fish_prompt _sbenv_old_fish_prompt\n",
+    );
+    s.push_str("    function fish_prompt\n");
+    s.push_str("        set -l last_status $status\n");
+    s.push_str("        set -q SYFTBOX_ENV_NAME; and printf '📦 (%s) ' $SYFTBOX_ENV_NAME\n");
+    s.push_str("        if functions -q _sbenv_old_fish_prompt\n");
+    // Hand the original prompt the exit status it would have seen
+    s.push_str("            echo \"exit $last_status\" | source\n");
+    s.push_str("            _sbenv_old_fish_prompt\n");
+    s.push_str("        end\n");
+    s.push_str("    end\n");
+    s.push_str("end\n");
+    s
+}
+
+/// Deactivation script for fish, undoing `fish_activation_script`
+fn fish_deactivation_script() -> String {
+    let mut s = String::new();
+    for name in ACTIVATION_VARS {
+        s.push_str(&format!("set -e {}\n", name));
+    }
+    s.push_str("if set -q SYFTBOX_OLD_VIRTUAL_ENV\n");
+    s.push_str("    set -gx VIRTUAL_ENV $SYFTBOX_OLD_VIRTUAL_ENV\n");
+    s.push_str("    set -e SYFTBOX_OLD_VIRTUAL_ENV\n");
+    s.push_str("else\n");
+    s.push_str("    set -e VIRTUAL_ENV\n");
+    s.push_str("end\n");
+    s.push_str("if functions -q _sbenv_old_fish_prompt\n");
+    s.push_str("    functions -e fish_prompt\n");
+    s.push_str("    functions -c _sbenv_old_fish_prompt fish_prompt\n");
+    s.push_str("    functions -e _sbenv_old_fish_prompt\n");
+    s.push_str("end\n");
+    s
+}
+
+/// Activation script for bash and zsh, with prompt decoration for both
+fn posix_activation_script(act: &Activation) -> String {
+    let mut s = String::new();
+    for (name, value) in &act.vars {
+        s.push_str(&format!("export {}={}\n", name, posix_quote(value)));
+    }
+
+    // Use VIRTUAL_ENV for compatibility with Powerlevel10k and other prompt tools
+    s.push_str("export SYFTBOX_OLD_VIRTUAL_ENV=\"$VIRTUAL_ENV\"\n");
+    s.push_str(&format!(
+        "export VIRTUAL_ENV={}\n",
+        posix_quote(&act.data_dir)
+    ));
+
+    // Customize Powerlevel10k virtualenv display
+    s.push_str("if [ -n \"$ZSH_VERSION\" ]; then\n");
+    s.push_str("    # For Powerlevel10k - override the virtualenv display format\n");
+    s.push_str("    if typeset -f _p9k_prompt_virtualenv_init >/dev/null 2>&1; then\n");
+    s.push_str("        # Save old format settings\n");
+    s.push_str(
+        "        export SYFTBOX_OLD_P9K_CONTENT=\"${POWERLEVEL9K_VIRTUALENV_CONTENT_EXPANSION}\"\n",
+    );
+    s.push_str("        export SYFTBOX_OLD_P9K_VISUAL=\"${POWERLEVEL9K_VIRTUALENV_VISUAL_IDENTIFIER_EXPANSION}\"\n");
+    s.push_str("        # Override to show box icon and email without 'Py'\n");
+    // p10k expands this when drawing; refer to the variable so the name itself is never evaluated
+    s.push_str(
+        "        export POWERLEVEL9K_VIRTUALENV_CONTENT_EXPANSION='📦 ${SYFTBOX_ENV_NAME//\\%/%%}'\n",
+    );
+    s.push_str("        export POWERLEVEL9K_VIRTUALENV_VISUAL_IDENTIFIER_EXPANSION=''\n");
+    s.push_str("        export POWERLEVEL9K_VIRTUALENV_SHOW_PYTHON_VERSION=false\n");
+    s.push_str("        export POWERLEVEL9K_VIRTUALENV_SHOW_WITH_PYENV=false\n");
+    s.push_str("    fi\n");
+    s.push_str("    # For non-Powerlevel10k ZSH\n");
+    s.push_str("    if [ -z \"$POWERLEVEL9K_LEFT_PROMPT_ELEMENTS\" ]; then\n");
+    s.push_str("        export SYFTBOX_OLD_PS1=\"$PS1\"\n");
+    // With promptsubst the prompt is expanded again when drawn, so a name with
+    // $(...) in it would run; insert a reference to the variable instead
+    s.push_str("        if [[ -o promptsubst ]]; then\n");
+    s.push_str("            export PS1='📦 (${SYFTBOX_ENV_NAME//\\%/%%}) '\"$PS1\"\n");
+    s.push_str("        else\n");
+    s.push_str("            export PS1=\"📦 (${SYFTBOX_ENV_NAME//\\%/%%}) $PS1\"\n");
+    s.push_str("        fi\n");
+    s.push_str("    fi\n");
+    s.push_str("else\n");
+    s.push_str("    # Bash - detect if using PROMPT_COMMAND (modern prompt frameworks)\n");
+    s.push_str("    if [ -n \"$PROMPT_COMMAND\" ]; then\n");
+    s.push_str("        # Using a prompt framework like Starship, Oh My Bash, etc.\n");
+    s.push_str("        # Only add decorator if not already active\n");
+    s.push_str("        if [ -z \"$SYFTBOX_OLD_PROMPT_COMMAND\" ]; then\n");
+    s.push_str("            export SYFTBOX_OLD_PROMPT_COMMAND=\"$PROMPT_COMMAND\"\n");
+    s.push_str("            # Create a function to show the decorator\n");
+    s.push_str("            _sbenv_prompt_decorator() {\n");
+    s.push_str("                if [ -n \"$SYFTBOX_ENV_NAME\" ]; then\n");
+    s.push_str("                    printf '\\033[0m📦 (%s) ' \"$SYFTBOX_ENV_NAME\"\n");
+    s.push_str("                fi\n");
+    s.push_str("            }\n");
+    s.push_str("            export -f _sbenv_prompt_decorator\n");
+    s.push_str(
+        "            export PROMPT_COMMAND='_sbenv_prompt_decorator; '\"$PROMPT_COMMAND\"\n",
+    );
+    s.push_str("        fi\n");
+    s.push_str("    else\n");
+    s.push_str("        # Traditional bash prompt\n");
+    s.push_str("        if [ -z \"$SYFTBOX_OLD_PS1\" ]; then\n");
+    s.push_str("            export SYFTBOX_OLD_PS1=\"$PS1\"\n");
+    // Same as promptsubst above: bash expands PS1 again when promptvars is on
+    s.push_str("            if shopt -q promptvars; then\n");
+    s.push_str("                export PS1='\\[\\033[0m\\]📦 (${SYFTBOX_ENV_NAME}) '\"$PS1\"\n");
+    s.push_str("            else\n");
+    s.push_str("                export PS1=\"\\[\\033[0m\\]📦 (${SYFTBOX_ENV_NAME}) ${PS1}\"\n");
+    s.push_str("            fi\n");
+    s.push_str("        fi\n");
+    s.push_str("    fi\n");
+    s.push_str("fi\n");
+
+    // Set flag to refresh Powerlevel10k on next prompt (deferred to avoid instant prompt issues)
+    s.push_str("if typeset -f p10k >/dev/null 2>&1; then\n");
+    s.push_str("    export _SBENV_NEEDS_P10K_RELOAD=1\n");
+    s.push_str("fi\n");
+    s
+}
+
+/// Deactivation script for bash and zsh, undoing `posix_activation_script`
+fn posix_deactivation_script() -> String {
+    let mut s = String::new();
+    s.push_str("unset SYFTBOX_EMAIL\n");
+    s.push_str("unset SYFTBOX_DATA_DIR\n");
+    s.push_str("unset SYFTBOX_SERVER_URL\n");
+    s.push_str("unset SYFTBOX_CONFIG_PATH\n");
+    s.push_str("unset SYFTBOX_CLIENT_URL\n");
+    s.push_str("unset SYFTBOX_ENV_NAME\n");
+    s.push_str("unset SYFTBOX_BINARY\n");
+    s.push_str("unset SYFTBOX_VERSION\n");
+    s.push_str("unset SYFTBOX_BUILD_HASH\n");
+    s.push_str("unset SYFTBOX_BUILD_TARGET\n");
+
+    // Restore VIRTUAL_ENV
+    s.push_str("if [ -n \"$SYFTBOX_OLD_VIRTUAL_ENV\" ]; then\n");
+    s.push_str("    export VIRTUAL_ENV=\"$SYFTBOX_OLD_VIRTUAL_ENV\"\n");
+    s.push_str("    unset SYFTBOX_OLD_VIRTUAL_ENV\n");
+    s.push_str("else\n");
+    s.push_str("    unset VIRTUAL_ENV\n");
+    s.push_str("fi\n");
+
+    // Restore Powerlevel10k settings
+    s.push_str("if [ -n \"$ZSH_VERSION\" ]; then\n");
+    s.push_str("    if [ -n \"$SYFTBOX_OLD_P9K_CONTENT\" ]; then\n");
+    s.push_str(
+        "        export POWERLEVEL9K_VIRTUALENV_CONTENT_EXPANSION=\"$SYFTBOX_OLD_P9K_CONTENT\"\n",
+    );
+    s.push_str("        unset SYFTBOX_OLD_P9K_CONTENT\n");
+    s.push_str("    else\n");
+    s.push_str("        unset POWERLEVEL9K_VIRTUALENV_CONTENT_EXPANSION\n");
+    s.push_str("    fi\n");
+    s.push_str("    if [ -n \"$SYFTBOX_OLD_P9K_VISUAL\" ]; then\n");
+    s.push_str("        export POWERLEVEL9K_VIRTUALENV_VISUAL_IDENTIFIER_EXPANSION=\"$SYFTBOX_OLD_P9K_VISUAL\"\n");
+    s.push_str("        unset SYFTBOX_OLD_P9K_VISUAL\n");
+    s.push_str("    else\n");
+    s.push_str("        unset POWERLEVEL9K_VIRTUALENV_VISUAL_IDENTIFIER_EXPANSION\n");
+    s.push_str("    fi\n");
+    s.push_str("    unset POWERLEVEL9K_VIRTUALENV_SHOW_PYTHON_VERSION\n");
+    s.push_str("    unset POWERLEVEL9K_VIRTUALENV_SHOW_WITH_PYENV\n");
+    s.push_str("fi\n");
+
+    // Restore PS1 or PROMPT_COMMAND for non-Powerlevel10k shells
+    s.push_str("# Restore bash prompt\n");
+    s.push_str("if [ -n \"$SYFTBOX_OLD_PROMPT_COMMAND\" ]; then\n");
+    s.push_str("    export PROMPT_COMMAND=\"$SYFTBOX_OLD_PROMPT_COMMAND\"\n");
+    s.push_str("    unset SYFTBOX_OLD_PROMPT_COMMAND\n");
+    s.push_str("    # Remove the decorator function if it exists\n");
+    s.push_str("    if declare -f _sbenv_prompt_decorator >/dev/null 2>&1; then\n");
+    s.push_str("        unset -f _sbenv_prompt_decorator\n");
+    s.push_str("    fi\n");
+    s.push_str("elif [ -n \"$SYFTBOX_OLD_PS1\" ]; then\n");
+    s.push_str("    export PS1=\"$SYFTBOX_OLD_PS1\"\n");
+    s.push_str("    unset SYFTBOX_OLD_PS1\n");
+    s.push_str("fi\n");
+
+    s.push_str("unset SYFTBOX_ENV_ACTIVE\n");
+    s
+}
+
+/// Activation for nushell, which cannot eval generated code: a JSON record of
+/// variables for `load-env`. The prompt wrapper from `get_nu_shell_functions`
+/// reads SYFTBOX_ENV_NAME from it.
+fn nu_activation_script(act: &Activation, old_virtual_env: Option<String>) -> String {
+    let mut record = serde_json::Map::new();
+    if let Some(old) = old_virtual_env {
+        record.insert("SYFTBOX_OLD_VIRTUAL_ENV".to_string(), old.into());
+    }
+    for (name, value) in &act.vars {
+        record.insert(name.to_string(), value.clone().into());
+    }
+    record.insert("VIRTUAL_ENV".to_string(), act.data_dir.clone().into());
+    if let Some(dir) = &act.cd_to {
+        record.insert("SBENV_CD".to_string(), dir.clone().into());
+    }
+    format!("{}\n", serde_json::Value::Object(record))
+}
+
+/// Deactivation for nushell: `{"hide": [...], "set": {...}}`, applied by `sbd`
+/// with `hide-env` and `load-env`
+fn nu_deactivation_script(old_virtual_env: Option<String>) -> String {
+    let mut hide: Vec<&str> = ACTIVATION_VARS.to_vec();
+    let mut set = serde_json::Map::new();
+    match old_virtual_env {
+        Some(old) => {
+            hide.push("SYFTBOX_OLD_VIRTUAL_ENV");
+            set.insert("VIRTUAL_ENV".to_string(), old.into());
+        }
+        None => hide.push("VIRTUAL_ENV"),
+    }
+    format!("{}\n", serde_json::json!({ "hide": hide, "set": set }))
+}
+
+/// Single-quoted PowerShell string. PowerShell also ends a string at the
+/// typographic single quotes, so those are doubled as well.
+fn pwsh_quote(value: &str) -> String {
+    let mut s = String::from("'");
+    for c in value.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+            s.push(c);
+        }
+        s.push(c);
+    }
+    s.push('\'');
+    s
+}
+
+/// Activation script for PowerShell: `$env:` variables and a 📦 prefix on prompt
+fn pwsh_activation_script(act: &Activation) -> String {
+    let mut s = String::new();
+    // Keep the VIRTUAL_ENV from before the first activation only
+    s.push_str("if (-not $env:SYFTBOX_ENV_ACTIVE -and $env:VIRTUAL_ENV) {\n");
+    s.push_str("    $env:SYFTBOX_OLD_VIRTUAL_ENV = $env:VIRTUAL_ENV\n");
+    s.push_str("}\n");
+    for (name, value) in &act.vars {
+        s.push_str(&format!("$env:{} = {}\n", name, pwsh_quote(value)));
+    }
+    s.push_str(&format!(
+        "$env:VIRTUAL_ENV = {}\n",
+        pwsh_quote(&act.data_dir)
+    ));
+    // Functions are defined in the global scope so they survive the sba wrapper
+    s.push_str("if (-not ${function:global:_sbenv_old_prompt}) {\n");
+    s.push_str("    ${function:global:_sbenv_old_prompt} = ${function:prompt}\n");
+    s.push_str("    function global:prompt {\n");
+    s.push_str("        if ($env:SYFTBOX_ENV_NAME) {\n");
+    s.push_str("            Write-Host -NoNewline \"📦 ($env:SYFTBOX_ENV_NAME) \"\n");
+    s.push_str("        }\n");
+    s.push_str("        _sbenv_old_prompt\n");
+    s.push_str("    }\n");
+    s.push_str("}\n");
+    s
+}
+
+/// Deactivation script for PowerShell, undoing `pwsh_activation_script`
+fn pwsh_deactivation_script() -> String {
+    let mut s = String::new();
+    for name in ACTIVATION_VARS {
+        s.push_str(&format!(
+            "Remove-Item Env:{} -ErrorAction SilentlyContinue\n",
+            name
+        ));
+    }
+    s.push_str("if ($env:SYFTBOX_OLD_VIRTUAL_ENV) {\n");
+    s.push_str("    $env:VIRTUAL_ENV = $env:SYFTBOX_OLD_VIRTUAL_ENV\n");
+    s.push_str("    Remove-Item Env:SYFTBOX_OLD_VIRTUAL_ENV\n");
+    s.push_str("} else {\n");
+    s.push_str("    Remove-Item Env:VIRTUAL_ENV -ErrorAction SilentlyContinue\n");
+    s.push_str("}\n");
+    s.push_str("if (${function:global:_sbenv_old_prompt}) {\n");
+    s.push_str("    ${function:global:prompt} = ${function:global:_sbenv_old_prompt}\n");
+    s.push_str("    Remove-Item Function:_sbenv_old_prompt\n");
+    s.push_str("}\n");
+    s
+}
+
+/// Print the how-to-apply comment unless the output is a nushell record
+fn print_eval_hint(shell: Shell, command: &str) {
+    let hint = format!(
+        "# Run this command to {} the environment:\n# {}\n",
+        command,
+        shell.eval_hint(command)
+    );
+    if shell == Shell::Nu {
+        eprintln!("{}", hint);
+    } else {
+        println!("{}", hint);
+    }
+}
+
+/// Activation for the selected environment (see `selected_config_path`)
+fn selected_activation(selector: Option<&str>, cd: bool, quiet: bool) -> Result<Activation> {
+    let config_path = selected_config_path(selector)?;
+    let config = load_config(&config_path)?;
+
+    // Ensure .sbenv marker exists for this environment
+    let _ = ensure_marker_exists(&config_path, &config);
+
+    let mut act = Activation::new(&config, &config_path, quiet);
+    if cd {
+        // config.json lives in <root>/.syftbox/
+        let root = config_path
+            .parent()
+            .and_then(Path::parent)
+            .unwrap_or(Path::new("."));
+        act.cd_to = Some(root.display().to_string());
+    }
+    Ok(act)
+}
+
+fn activate_environment(quiet: bool, shell: Shell, selector: Option<&str>, cd: bool) -> Result<()> {
+    let act = selected_activation(selector, cd, quiet)?;
+    if !quiet {
+        print_eval_hint(shell, "activate");
+    }
+    print!("{}", shell.activation_script(&act));
+
+    Ok(())
+}
+
+fn deactivate_environment(quiet: bool, shell: Shell) -> Result<()> {
+    if !quiet {
+        print_eval_hint(shell, "deactivate");
+    }
+    // No console I/O beyond the script to avoid conflicts with instant prompt
+    print!("{}", shell.deactivation_script());
+
+    Ok(())
+}
+
+fn remove_environment(path: Option<PathBuf>) -> Result<()> {
+    let target_path = path.unwrap_or_else(|| env::current_dir().unwrap());
+    let syftbox_dir = target_path.join(".syftbox");
+
+    if !syftbox_dir.exists() {
+        println!("{}", "No SyftBox environment found at this location".red());
+        return Ok(());
+    }
+
+    let config_path = syftbox_dir.join("config.json");
+    if let Ok(config) = load_config(&config_path) {
+        println!("About to remove SyftBox environment:");
+        println!("  Email: {}", config.email.cyan());
+        println!("  Path: {}", target_path.display().to_string().cyan());
+        println!();
+
+        let confirmation = dialoguer::Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Are you sure you want to remove this environment?")
+            .default(false)
+            .interact()
+            .context("Failed to read confirmation")?;
+
+        if confirmation {
+            unregister_environment(&target_path)?;
+            fs::remove_dir_all(&syftbox_dir).context("Failed to remove .syftbox directory")?;
+            // Remove marker file if present
+            let marker = target_path.join(".sbenv");
+            if marker.exists() {
+                fs::remove_file(marker).ok();
+            }
+            println!("{}", "✅ SyftBox environment removed".green());
+        } else {
+            println!("{}", "Cancelled".yellow());
+        }
+    } else {
+        println!("{}", "Invalid or corrupted environment".red());
+    }
+
+    Ok(())
+}
+
+fn activate_environment_to_file(
+    path: &Path,
+    shell: Shell,
+    selector: Option<&str>,
+    cd: bool,
+) -> Result<()> {
+    let act = selected_activation(selector, cd, false)?;
+    fs::write(path, shell.activation_script(&act)).context("Failed to write activation script")?;
+
+    println!("Activation script written to: {}", path.display());
+    println!("Run: {}", shell.source_hint(path));
+
+    Ok(())
+}
+
+fn get_shell_config_file() -> Result<PathBuf> {
+    let shell = env::var("SHELL").unwrap_or_else(|_| String::from("/bin/bash"));
+    let home = dirs::home_dir().context("Failed to get home directory")?;
+
+    let config_file = if shell.contains("zsh") {
+        home.join(".zshrc")
+    } else if shell.contains("bash") {
+        home.join(".bashrc")
+    } else if shell.contains("fish") {
+        home.join(".config").join("fish").join("config.fish")
+    } else {
+        // Default to bashrc
+        home.join(".bashrc")
+    };
+
+    Ok(config_file)
+}
+
+/// Copy of the rc file taken before `install-shell` last changed it
+fn get_shell_backup_file(rc_file: &Path) -> PathBuf {
+    rc_file.with_extension("bak.sbenv")
+}
+
+/// First line of the block `install-shell` writes to bash/zsh rc files; the
+/// sbenv version that wrote it follows
+const SHELL_BLOCK_BEGIN: &str = "# >>> sbenv shell integration";
+const SHELL_BLOCK_END: &str = "# <<< sbenv shell integration <<<";
+
+/// Shell functions and auto-activation hook between begin/end markers
+fn get_shell_block() -> String {
+    format!(
+        "{} (v{}) >>>\n{}\n{}{}\n",
+        SHELL_BLOCK_BEGIN,
+        env!("CARGO_PKG_VERSION"),
+        get_shell_functions(),
+        get_auto_activation_block(),
+        SHELL_BLOCK_END
+    )
+}
+
+/// Shell functions older versions appended to rc files without markers, frozen as written
+/// then, so `remove_shell_blocks` still finds them however `get_shell_functions` changes
+const LEGACY_SHELL_FUNCTIONS: &str = r#"# P10k deferred reload handler to avoid instant prompt issues
+_sbenv_p10k_precmd() {
+    if (( ${+functions[p10k]} )) && [[ -n $_SBENV_NEEDS_P10K_RELOAD ]]; then
+        unset _SBENV_NEEDS_P10K_RELOAD
+        p10k reload 2>/dev/null
+    fi
+}
+
+# Add to precmd hooks if in ZSH
+if [ -n "$ZSH_VERSION" ]; then
+    if (( ${+functions[add-zsh-hook]} )); then
+        autoload -Uz add-zsh-hook 2>/dev/null
+        add-zsh-hook precmd _sbenv_p10k_precmd 2>/dev/null
+    fi
+fi
+
+
+# SyftBox environment functions
+sbenv() {
+    case "$1" in
+        activate)
+            eval "$(command sbenv activate --quiet)"
+            # Fix Powerlevel10k prompt to show 📦 and email instead of 'Py'
+            if [ -n "$ZSH_VERSION" ] && [ -n "$SYFTBOX_EMAIL" ]; then
+                export POWERLEVEL9K_VIRTUALENV_CONTENT_EXPANSION="📦 $SYFTBOX_EMAIL"
+                export POWERLEVEL9K_VIRTUALENV_VISUAL_IDENTIFIER_EXPANSION=''
+                export POWERLEVEL9K_VIRTUALENV_SHOW_PYTHON_VERSION=false
+                export POWERLEVEL9K_VIRTUALENV_SHOW_WITH_PYENV=false
+                # Force P10k to rebuild its prompt cache
+                unset _p9k__cached_p10k_param_sig 2>/dev/null
+                # Defer P10k reload to avoid instant prompt issues
+                if typeset -f p10k >/dev/null 2>&1; then
+                    export _SBENV_NEEDS_P10K_RELOAD=1
+                fi
+            fi
+            ;;
+        deactivate)
+            eval "$(command sbenv deactivate --quiet)"
+            # Reset P10k virtualenv display
+            if [ -n "$ZSH_VERSION" ]; then
+                export POWERLEVEL9K_VIRTUALENV_CONTENT_EXPANSION='${VIRTUAL_ENV:t}'
+                export POWERLEVEL9K_VIRTUALENV_SHOW_PYTHON_VERSION=false
+                unset _p9k__cached_p10k_param_sig 2>/dev/null
+                # Defer P10k reload to avoid instant prompt issues
+                if typeset -f p10k >/dev/null 2>&1; then
+                    export _SBENV_NEEDS_P10K_RELOAD=1
+                fi
+            fi
+            ;;
+        *)
+            command sbenv "$@"
+            ;;
+    esac
+}
+
+# SyftBox environment aliases
+alias sba='sbenv activate'
+alias sbd='sbenv deactivate'
+alias sbi='sbenv info'
+"#;
+
+/// Auto-activation hook older versions appended to rc files without markers
+const LEGACY_AUTO_ACTIVATION_BLOCK: &str = r#"# Auto-activate SyftBox envs when entering directories with a .sbenv marker
+_sbenv_find_root() {
+    local dir="$PWD"
+    while [ "$dir" != "/" ]; do
+        if [ -f "$dir/.sbenv" ]; then
+            echo "$dir"
+            return 0
+        fi
+        dir="$(dirname "$dir")"
+    done
+    return 1
+}
+
+_sbenv_auto_hook() {
+    local root
+    root="$(_sbenv_find_root 2>/dev/null)"
+    if [ -n "$root" ]; then
+        if [ "$SBENV_AUTO_ACTIVE_ROOT" != "$root" ]; then
+            if [ -n "$SYFTBOX_ENV_ACTIVE" ]; then
+                SBENV_SUPPRESS_MESSAGES=1 eval "$(command sbenv deactivate --quiet)"
+            fi
+            SBENV_SUPPRESS_MESSAGES=1 eval "$(command sbenv activate --quiet)"
+            export SBENV_AUTO_ACTIVE_ROOT="$root"
+        fi
+    else
+        if [ -n "$SBENV_AUTO_ACTIVE_ROOT" ] && [ -n "$SYFTBOX_ENV_ACTIVE" ]; then
+            SBENV_SUPPRESS_MESSAGES=1 eval "$(command sbenv deactivate --quiet)"
+            unset SBENV_AUTO_ACTIVE_ROOT
+        fi
+    fi
+}
+
+# Hook into ZSH bash-style directory change or Bash prompt
+if [ -n "$ZSH_VERSION" ]; then
+    typeset -ga chpwd_functions
+    case " ${chpwd_functions[@]} " in *\ _sbenv_auto_hook\ *) ;; *) chpwd_functions+=(_sbenv_auto_hook) ;; esac
+    # Don't call _sbenv_auto_hook immediately - let it run on first directory change
+else
+    if [ -z "$SBENV_AUTO_PROMPT_HOOK" ]; then
+        export PROMPT_COMMAND="_sbenv_auto_hook; ${PROMPT_COMMAND}"
+        export SBENV_AUTO_PROMPT_HOOK=1
+    fi
+    # Don't call _sbenv_auto_hook immediately - let it run on first prompt
+fi
+"#;
+
+/// Remove sbenv blocks, and the unmarked copies older versions appended, from
+/// rc file content. Also returns where the first one started.
+fn remove_shell_blocks(content: &str) -> Result<(String, Option<usize>)> {
+    let mut rest = String::new();
+    let mut first = None;
+    let mut inside = false;
+    for line in content.split_inclusive('\n') {
+        if !inside && line.starts_with(SHELL_BLOCK_BEGIN) {
+            inside = true;
+            first.get_or_insert(rest.len());
+        } else if inside {
+            inside = line.trim_end() != SHELL_BLOCK_END;
+        } else {
+            rest.push_str(line);
+        }
+    }
+    if inside {
+        anyhow::bail!(
+            "Found '{}' without a matching '{}'; fix the file by hand",
+            SHELL_BLOCK_BEGIN,
+            SHELL_BLOCK_END
+        );
+    }
+    for legacy in [LEGACY_SHELL_FUNCTIONS, LEGACY_AUTO_ACTIVATION_BLOCK] {
+        while let Some(pos) = rest.find(legacy) {
+            rest.replace_range(pos..pos + legacy.len(), "");
+            first = Some(first.map_or(pos, |f: usize| f.min(pos)));
+        }
+    }
+    Ok((rest, first))
+}
+
+/// rc file content with the current block in place of any older ones, or appended
+fn with_shell_block(content: &str) -> Result<String> {
+    let (mut rest, first) = remove_shell_blocks(content)?;
+    let pos = match first {
+        Some(pos) => pos,
+        None => {
+            if !rest.is_empty() && !rest.ends_with('\n') {
+                rest.push('\n');
+            }
+            rest.len()
+        }
+    };
+    rest.insert_str(pos, &get_shell_block());
+    Ok(rest)
+}
+
+fn get_shell_functions() -> String {
+    let mut functions = String::new();
+
+    // Add P10k deferred reload handler
+    functions.push_str(
+        "# P10k deferred reload handler to avoid instant prompt issues
+_sbenv_p10k_precmd() {
+    if (( ${+functions[p10k]} )) && [[ -n $_SBENV_NEEDS_P10K_RELOAD ]]; then
+        unset _SBENV_NEEDS_P10K_RELOAD
+        p10k reload 2>/dev/null
+    fi
+}
+
+# Add to precmd hooks if in ZSH
+if [ -n \"$ZSH_VERSION\" ]; then
+    if (( ${+functions[add-zsh-hook]} )); then
+        autoload -Uz add-zsh-hook 2>/dev/null
+        add-zsh-hook precmd _sbenv_p10k_precmd 2>/dev/null
+    fi
+fi
+
+",
+    );
+    functions.push_str(
+        "
+# SyftBox environment functions
+",
+    );
+    functions.push_str(
+        "sbenv() {
+",
+    );
+    functions.push_str("    case \"$1\" in\n");
+    functions.push_str(
+        "        activate)
+",
+    );
+    functions.push_str("            shift\n");
+    functions.push_str("            eval \"$(command sbenv activate --quiet \"$@\")\"\n");
+    functions.push_str(
+        "            # Fix Powerlevel10k prompt to show 📦 and email instead of 'Py'
+",
+    );
+    functions
+        .push_str("            if [ -n \"$ZSH_VERSION\" ] && [ -n \"$SYFTBOX_EMAIL\" ]; then\n");
+    // p10k expands this again when drawing, so refer to the variable rather than its value
+    functions.push_str(
+        "                export POWERLEVEL9K_VIRTUALENV_CONTENT_EXPANSION='📦 ${SYFTBOX_ENV_NAME//\\%/%%}'\n",
+    );
+    functions.push_str(
+        "                export POWERLEVEL9K_VIRTUALENV_VISUAL_IDENTIFIER_EXPANSION=''
+",
+    );
+    functions.push_str(
+        "                export POWERLEVEL9K_VIRTUALENV_SHOW_PYTHON_VERSION=false
+",
+    );
+    functions.push_str(
+        "                export POWERLEVEL9K_VIRTUALENV_SHOW_WITH_PYENV=false
+",
+    );
+    functions.push_str(
+        "                # Force P10k to rebuild its prompt cache
+",
+    );
+    functions.push_str(
+        "                unset _p9k__cached_p10k_param_sig 2>/dev/null
+",
+    );
+    functions.push_str(
+        "                # Defer P10k reload to avoid instant prompt issues
+",
+    );
+    functions.push_str(
+        "                if typeset -f p10k >/dev/null 2>&1; then
+",
+    );
+    functions.push_str(
+        "                    export _SBENV_NEEDS_P10K_RELOAD=1
+",
+    );
+    functions.push_str(
+        "                fi
+",
+    );
+    functions.push_str(
+        "            fi
+",
+    );
+    functions.push_str(
+        "            ;;
+",
+    );
+    functions.push_str(
+        "        deactivate)
+",
+    );
+    functions.push_str("            eval \"$(command sbenv deactivate --quiet)\"\n");
+    functions.push_str(
+        "            # Reset P10k virtualenv display
+",
+    );
+    functions.push_str("            if [ -n \"$ZSH_VERSION\" ]; then\n");
+    functions.push_str(
+        "                export POWERLEVEL9K_VIRTUALENV_CONTENT_EXPANSION='${VIRTUAL_ENV:t}'
+",
+    );
+    functions.push_str(
+        "                export POWERLEVEL9K_VIRTUALENV_SHOW_PYTHON_VERSION=false
+",
+    );
+    functions.push_str(
+        "                unset _p9k__cached_p10k_param_sig 2>/dev/null
+",
+    );
+    functions.push_str(
+        "                # Defer P10k reload to avoid instant prompt issues
+",
+    );
+    functions.push_str(
+        "                if typeset -f p10k >/dev/null 2>&1; then
+",
+    );
+    functions.push_str(
+        "                    export _SBENV_NEEDS_P10K_RELOAD=1
+",
+    );
+    functions.push_str(
+        "                fi
+",
+    );
+    functions.push_str(
+        "            fi
+",
+    );
+    functions.push_str(
+        "            ;;
+",
+    );
+    functions.push_str(
+        "        *)
+",
+    );
+    functions.push_str("            command sbenv \"$@\"\n");
+    functions.push_str(
+        "            ;;
+",
+    );
+    functions.push_str(
+        "    esac
+",
+    );
+    functions.push_str(
+        "}
+",
+    );
+    functions.push('\n');
+    functions.push_str(
+        "# SyftBox environment aliases
+",
+    );
+    functions.push_str(
+        "alias sba='sbenv activate'
+",
+    );
+    functions.push_str(
+        "alias sbd='sbenv deactivate'
+",
+    );
+    functions.push_str(
+        "alias sbi='sbenv info'
+",
+    );
+    functions
+}
+
+fn get_auto_activation_block() -> String {
+    let mut s = String::new();
+    s.push_str("# Auto-activate SyftBox envs when entering directories with a .sbenv marker\n");
+    s.push_str("_sbenv_find_root() {\n");
+    s.push_str("    local dir=\"$PWD\"\n");
+    s.push_str("    while [ \"$dir\" != \"/\" ]; do\n");
+    s.push_str("        if [ -f \"$dir/.sbenv\" ]; then\n");
+    s.push_str("            echo \"$dir\"\n");
+    s.push_str("            return 0\n");
+    s.push_str("        fi\n");
+    s.push_str("        dir=\"$(dirname \"$dir\")\"\n");
+    s.push_str("    done\n");
+    s.push_str("    return 1\n");
+    s.push_str("}\n");
+    s.push('\n');
+    s.push_str("_sbenv_auto_hook() {\n");
+    s.push_str("    local root\n");
+    s.push_str("    root=\"$(_sbenv_find_root 2>/dev/null)\"\n");
+    s.push_str("    if [ -n \"$root\" ]; then\n");
+    s.push_str("        if [ \"$SBENV_AUTO_ACTIVE_ROOT\" != \"$root\" ]; then\n");
+    s.push_str("            if [ -n \"$SYFTBOX_ENV_ACTIVE\" ]; then\n");
+    s.push_str(
+        "                SBENV_SUPPRESS_MESSAGES=1 eval \"$(command sbenv deactivate --quiet)\"\n",
+    );
+    s.push_str("            fi\n");
+    s.push_str(
+        "            SBENV_SUPPRESS_MESSAGES=1 eval \"$(command sbenv activate --quiet)\"\n",
+    );
+    s.push_str("            export SBENV_AUTO_ACTIVE_ROOT=\"$root\"\n");
+    s.push_str("        fi\n");
+    s.push_str("    else\n");
+    s.push_str(
+        "        if [ -n \"$SBENV_AUTO_ACTIVE_ROOT\" ] && [ -n \"$SYFTBOX_ENV_ACTIVE\" ]; then\n",
+    );
+    s.push_str(
+        "            SBENV_SUPPRESS_MESSAGES=1 eval \"$(command sbenv deactivate --quiet)\"\n",
+    );
+    s.push_str("            unset SBENV_AUTO_ACTIVE_ROOT\n");
+    s.push_str("        fi\n");
+    s.push_str("    fi\n");
+    s.push_str("}\n");
+    s.push('\n');
+    s.push_str("# Hook into ZSH bash-style directory change or Bash prompt\n");
+    s.push_str("if [ -n \"$ZSH_VERSION\" ]; then\n");
+    s.push_str("    typeset -ga chpwd_functions\n");
+    s.push_str("    case \" ${chpwd_functions[@]} \" in *\\ _sbenv_auto_hook\\ *) ;; *) chpwd_functions+=(_sbenv_auto_hook) ;; esac\n");
+    s.push_str(
+        "    # Don't call _sbenv_auto_hook immediately - let it run on first directory change\n",
+    );
+    s.push_str("else\n");
+    s.push_str("    if [ -z \"$SBENV_AUTO_PROMPT_HOOK\" ]; then\n");
+    s.push_str("        export PROMPT_COMMAND=\"_sbenv_auto_hook; ${PROMPT_COMMAND}\"\n");
+    s.push_str("        export SBENV_AUTO_PROMPT_HOOK=1\n");
+    s.push_str("    fi\n");
+    s.push_str("    # Don't call _sbenv_auto_hook immediately - let it run on first prompt\n");
+    s.push_str("fi\n");
+    s
+}
+
+/// fish loads every file in conf.d at startup, so sbenv gets a file of its own
+fn get_fish_conf_file() -> Result<PathBuf> {
+    let config_dir = match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => dirs::home_dir()
+            .context("Failed to get home directory")?
+            .join(".config"),
+    };
+    Ok(config_dir.join("fish").join("conf.d").join("sbenv.fish"))
+}
+
+/// fish versions of the sbenv/sba/sbd/sbi wrappers and the auto-activation hook
+fn get_fish_shell_functions() -> String {
+    "# SyftBox environment functions (written by sbenv install-shell)
+function sbenv --description 'SyftBox environment manager'
+    switch \"$argv[1]\"
+        case activate
+            command sbenv activate --shell fish --quiet $argv[2..-1] | source
+        case deactivate
+            command sbenv deactivate --shell fish --quiet | source
+        case '*'
+            command sbenv $argv
+    end
+end
+
+function sba --description 'Activate the SyftBox environment'
+    sbenv activate $argv
+end
+
+function sbd --description 'Deactivate the SyftBox environment'
+    sbenv deactivate $argv
+end
+
+function sbi --description 'Show SyftBox environment info'
+    sbenv info $argv
+end
+
+# Auto-activate SyftBox envs when entering directories with a .sbenv marker
+function _sbenv_find_root
+    set -l dir $PWD
+    while test \"$dir\" != /
+        if test -f \"$dir/.sbenv\"
+            echo $dir
+            return 0
+        end
+        set dir (dirname $dir)
+    end
+    return 1
+end
+
+function _sbenv_auto_hook --on-variable PWD
+    set -l root (_sbenv_find_root 2>/dev/null)
+    if test -n \"$root\"
+        if test \"$SBENV_AUTO_ACTIVE_ROOT\" != \"$root\"
+            if set -q SYFTBOX_ENV_ACTIVE
+                env SBENV_SUPPRESS_MESSAGES=1 sbenv deactivate --shell fish --quiet | source
+            end
+            env SBENV_SUPPRESS_MESSAGES=1 sbenv activate --shell fish --quiet | source
+            set -gx SBENV_AUTO_ACTIVE_ROOT $root
+        end
+    else if set -q SBENV_AUTO_ACTIVE_ROOT; and set -q SYFTBOX_ENV_ACTIVE
+        env SBENV_SUPPRESS_MESSAGES=1 sbenv deactivate --shell fish --quiet | source
+        set -e SBENV_AUTO_ACTIVE_ROOT
+    end
+end
+"
+    .to_string()
+}
+
+/// nushell sources every file in its autoload dir after config.nu
+fn get_nu_conf_file() -> Result<PathBuf> {
+    let config_dir = match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => dirs::home_dir()
+            .context("Failed to get home directory")?
+            .join(".config"),
+    };
+    Ok(config_dir.join("nushell").join("autoload").join("sbenv.nu"))
+}
+
+/// Prefixes the nushell prompt with the active env, keeping the prompt it wraps
+const NU_PROMPT_WRAPPER: &str = r#"# Show the active environment in front of the prompt
+$env.PROMPT_COMMAND = do {
+    let old_prompt = $env.PROMPT_COMMAND?
+    {||
+        let base = match ($old_prompt | describe) {
+            "closure" => (do $old_prompt),
+            "nothing" => $env.PWD,
+            _ => $old_prompt,
+        }
+        if "SYFTBOX_ENV_NAME" in $env {
+            $"📦 \(($env.SYFTBOX_ENV_NAME)\) ($base)"
+        } else {
+            $base
+        }
+    }
+}
+"#;
+
+/// nushell sba/sbd/sbi commands and a prompt wrapper showing the active env
+fn get_nu_shell_functions() -> String {
+    let functions = r#"# SyftBox environment functions (written by sbenv install-shell)
+def --env sba [...args] {
+    ^sbenv activate --shell nu --quiet ...$args | from json | load-env
+    # Set by `sba <env> --cd`
+    if "SBENV_CD" in $env {
+        cd $env.SBENV_CD
+        hide-env SBENV_CD
+    }
+}
+
+def --env sbd [] {
+    let changes = (^sbenv deactivate --shell nu --quiet | from json)
+    hide-env --ignore-errors ...$changes.hide
+    load-env $changes.set
+}
+
+def sbi [...args] {
+    ^sbenv info ...$args
+}
+
+"#;
+    format!("{}{}", functions, NU_PROMPT_WRAPPER)
+}
+
+/// PowerShell sba/sbd/sbi functions; the prompt is wrapped by the activation script
+fn get_pwsh_shell_functions() -> String {
+    "# SyftBox environment functions (written by sbenv install-shell)
+function sba { sbenv activate --shell pwsh --quiet @args | Out-String | Invoke-Expression }
+function sbd { sbenv deactivate --shell pwsh --quiet | Out-String | Invoke-Expression }
+function sbi { sbenv info @args }
+"
+    .to_string()
+}
+
+/// Install shell functions into a file sbenv owns (fish conf.d, nushell autoload)
+fn install_functions_file(shell_name: &str, conf_file: PathBuf, functions: String) -> Result<()> {
+    println!("{}", format!("🐚 Detected shell: {}", shell_name).cyan());
+    println!(
+        "📄 Configuration file: {}",
+        conf_file.display().to_string().cyan()
+    );
+    println!();
+
+    if fs::read_to_string(&conf_file).ok().as_deref() == Some(functions.as_str()) {
+        println!(
+            "{}",
+            "✅ SyftBox shell functions and auto-activation are already installed!".green()
+        );
+        println!("New {} sessions load them automatically.", shell_name);
+        return Ok(());
+    }
+
+    println!("The following will be written to {}:", conf_file.display());
+    println!("{}", "─".repeat(50).dimmed());
+    print!("{}", functions.dimmed());
+    println!("{}", "─".repeat(50).dimmed());
+    println!();
+
+    let confirm = if atty::is(atty::Stream::Stdin) {
+        Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Write {}?", conf_file.display()))
+            .default(true)
+            .interact()?
+    } else {
+        println!("Non-interactive mode detected. Run with --manual flag to see installation instructions.");
+        return Ok(());
+    };
+    if !confirm {
+        println!("{}", "Installation cancelled.".yellow());
+        return Ok(());
+    }
+
+    if let Some(dir) = conf_file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&conf_file, functions)?;
+    println!(
+        "{}",
+        "✅ SyftBox functions successfully installed!"
+            .green()
+            .bold()
+    );
+    println!();
+    println!("To start using the new commands, either:");
+    println!(
+        "  1. Run: {}",
+        format!("source {}", conf_file.display()).yellow()
+    );
+    println!("  2. Open a new terminal");
+    Ok(())
+}
+
+fn install_shell_functions() -> Result<()> {
+    match Shell::detect() {
+        Shell::Fish => {
+            return install_functions_file(
+                "Fish",
+                get_fish_conf_file()?,
+                get_fish_shell_functions(),
+            )
+        }
+        Shell::Nu => {
+            return install_functions_file("Nushell", get_nu_conf_file()?, get_nu_shell_functions())
+        }
+        Shell::Pwsh => {
+            // $PROFILE belongs to the user, so only show what to add
+            println!("{}", "🐚 Detected shell: PowerShell".cyan());
+            println!(
+                "Add these functions to your profile ({}):",
+                "$PROFILE".yellow()
+            );
+            println!();
+            print!("{}", get_pwsh_shell_functions());
+            return Ok(());
+        }
+        Shell::Bash | Shell::Zsh => {}
+    }
+    let shell = env::var("SHELL").unwrap_or_else(|_| String::from("/bin/bash"));
+    let shell_name = if shell.contains("zsh") {
+        "ZSH"
+    } else if shell.contains("bash") {
+        "Bash"
+    } else if shell.contains("fish") {
+        "Fish"
+    } else {
+        "your shell"
+    };
+
+    let rc_file = get_shell_config_file()?;
+
+    println!("{}", format!("🐚 Detected shell: {}", shell_name).cyan());
+    println!(
+        "📄 Configuration file: {}",
+        rc_file.display().to_string().cyan()
+    );
+    println!();
+
+    let existing_content = if rc_file.exists() {
+        fs::read_to_string(&rc_file)?
+    } else {
+        String::new()
+    };
+    let new_content = with_shell_block(&existing_content)?;
+
+    if new_content == existing_content {
+        println!(
+            "{}",
+            "✅ SyftBox shell functions and auto-activation are already installed!".green()
+        );
+        println!("All helpers are ready to use.");
+        println!("If you haven't reloaded your shell config, run:");
+        println!("  {}", format!("source {}", rc_file.display()).yellow());
+        return Ok(());
+    }
+    let upgrading = remove_shell_blocks(&existing_content)?.1.is_some();
+
+    // Show what will be added
+    if upgrading {
+        println!(
+            "The sbenv block in your {} file will be replaced with:",
+            shell_name
+        );
+    } else {
+        println!("The following will be added to your {} file:", shell_name);
+    }
+    println!("{}", "─".repeat(50).dimmed());
+    print!("{}", get_shell_block().dimmed());
+    println!("{}", "─".repeat(50).dimmed());
+    println!();
+
+    // Ask for confirmation
+    let confirm = if atty::is(atty::Stream::Stdin) {
+        Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Add SyftBox functions to {}?", rc_file.display()))
+            .default(true)
+            .interact()?
+    } else {
+        println!("Non-interactive mode detected. Run with --manual flag to see installation instructions.");
+        return Ok(());
+    };
+
+    if !confirm {
+        println!("{}", "Installation cancelled.".yellow());
+        println!("You can manually add the functions by running:");
+        println!("  {}", "sbenv install-shell --manual".cyan());
+        return Ok(());
+    }
+
+    // Create backup
+    if rc_file.exists() {
+        let backup_path = get_shell_backup_file(&rc_file);
+        fs::copy(&rc_file, &backup_path)?;
+        println!(
+            "📦 Backup created: {}",
+            backup_path.display().to_string().dimmed()
+        );
+    }
+
+    fs::write(&rc_file, new_content)?;
+
+    println!(
+        "{}",
+        "✅ SyftBox functions successfully installed!"
+            .green()
+            .bold()
+    );
+    println!();
+    println!("To start using the new commands, either:");
+    println!(
+        "  1. Run: {}",
+        format!("source {}", rc_file.display()).yellow()
+    );
+    println!("  2. Open a new terminal");
+    println!();
+    println!("Available commands:");
+    println!(
+        "  {} - Activate environment (no eval needed!)",
+        "sbenv activate".cyan()
+    );
+    println!("  {} - Deactivate environment", "sbenv deactivate".cyan());
+    println!("  {} - Activate (shortcut)", "sba".cyan());
+    println!("  {} - Deactivate (shortcut)", "sbd".cyan());
+    println!("  {} - Show info (shortcut)", "sbi".cyan());
+    println!();
+    println!(
+        "{}",
+        "⚠️  Important for Powerlevel10k users:".yellow().bold()
+    );
+    println!(
+        "   Add this line at the END of your {} file:",
+        rc_file.display()
+    );
+    println!();
+    println!(
+        "   {}",
+        "(( ! ${+functions[p10k]} )) || p10k finalize".cyan()
+    );
+    println!();
+    println!("   This prevents instant prompt errors with sbenv auto-activation.");
+
+    Ok(())
+}
+
+/// Delete a functions file sbenv wrote for fish or nushell
+fn remove_functions_file(conf_file: &Path) -> Result<()> {
+    if !conf_file.exists() {
+        println!(
+            "No sbenv shell integration found at {}",
+            conf_file.display()
+        );
+        return Ok(());
+    }
+    fs::remove_file(conf_file)
+        .with_context(|| format!("Failed to remove {}", conf_file.display()))?;
+    println!("{}", format!("✅ Removed {}", conf_file.display()).green());
+    println!("Open a new terminal for the change to take effect.");
+    Ok(())
+}
+
+fn uninstall_shell_functions(restore_backup: bool) -> Result<()> {
+    match Shell::detect() {
+        Shell::Fish => return remove_functions_file(&get_fish_conf_file()?),
+        Shell::Nu => return remove_functions_file(&get_nu_conf_file()?),
+        Shell::Pwsh => {
+            println!("Remove the sba, sbd and sbi functions from your profile ($PROFILE).");
+            return Ok(());
+        }
+        Shell::Bash | Shell::Zsh => {}
+    }
+    let rc_file = get_shell_config_file()?;
+
+    if restore_backup {
+        let backup_path = get_shell_backup_file(&rc_file);
+        if !backup_path.exists() {
+            anyhow::bail!("No backup found at {}", backup_path.display());
+        }
+        // The backup predates the last install-shell, so later edits are lost
+        if atty::is(atty::Stream::Stdin)
+            && !Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!(
+                    "Replace {} with {}? Changes made since then are lost.",
+                    rc_file.display(),
+                    backup_path.display()
+                ))
+                .default(false)
+                .interact()?
+        {
+            println!("{}", "Uninstall cancelled.".yellow());
+            return Ok(());
+        }
+        fs::copy(&backup_path, &rc_file)?;
+        println!(
+            "{}",
+            format!(
+                "✅ Restored {} from {}",
+                rc_file.display(),
+                backup_path.display()
+            )
+            .green()
+        );
+        if remove_shell_blocks(&fs::read_to_string(&rc_file)?)?
+            .1
+            .is_some()
+        {
+            println!(
+                "{}",
+                "⚠️  The backup still contains an sbenv block; run `sbenv uninstall-shell` to remove it."
+                    .yellow()
+            );
+        }
+        return Ok(());
+    }
+
+    let content = if rc_file.exists() {
+        fs::read_to_string(&rc_file)?
+    } else {
+        String::new()
+    };
+    let (rest, first) = remove_shell_blocks(&content)?;
+    if first.is_none() {
+        println!("No sbenv shell integration found in {}", rc_file.display());
+        return Ok(());
+    }
+    fs::write(&rc_file, rest)?;
+    println!(
+        "{}",
+        format!(
+            "✅ Removed sbenv shell integration from {}",
+            rc_file.display()
+        )
+        .green()
+    );
+    println!("Open a new terminal for the change to take effect.");
+    Ok(())
+}
+
+fn check_login_status(config_path: &Path) -> Result<bool> {
+    // Check if refresh_token exists in config
+    let config = load_config(config_path)?;
+
+    // If there's a refresh token, assume we're logged in
+    // The daemon will fail if the token is invalid, and we'll handle that
+    Ok(config.refresh_token.is_some())
+}
+
+fn prompt_and_login(config_path: &Path, integrity: IntegrityCheck) -> Result<()> {
+    // If this environment is in dev mode, do not attempt login
+    if load_config(config_path)?.dev_mode {
+        println!("{}", "Dev mode environment: skipping login.".yellow());
+        return Ok(());
+    }
+
+    println!("{}", "You are not logged in to SyftBox.".yellow());
+
+    let confirm = if atty::is(atty::Stream::Stdin) {
+        Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Would you like to log in now?")
+            .default(true)
+            .interact()?
+    } else {
+        println!("Cannot prompt for login in non-interactive mode.");
+        println!(
+            "Please run: {}",
+            format!("syftbox -c {} login", config_path.display()).cyan()
+        );
+        return Err(anyhow::anyhow!("Not logged in"));
+    };
+
+    if !confirm {
+        println!("{}", "Cannot start daemon without logging in.".red());
+        println!(
+            "Run: {}",
+            format!("syftbox -c {} login", config_path.display()).cyan()
+        );
+        return Err(anyhow::anyhow!("Login required"));
+    }
+
+    // Save original config before login
+    let original_config = load_config(config_path)?;
+
+    println!("Logging in to SyftBox...");
+    let status = run_syftbox_login(config_path, original_config.dev_mode, integrity)?;
+
+    if !status.success() {
+        return Err(anyhow::anyhow!("Login failed"));
+    }
+
+    // Restore original config values but keep the new refresh_token
+    restore_config_after_login(config_path, &original_config)?;
+
+    println!("{}", "✅ Login successful!".green());
+    Ok(())
+}
+
+/// HOME used for syftbox processes launched from this environment
+fn get_isolated_home(env_dir: &Path) -> PathBuf {
+    env_dir.join(".syftbox").join("home")
+}
+
+/// Prepare the per-env HOME/XDG layout and place the env config where syftbox expects it.
+/// syftbox ignores `-c` and reads `$HOME/.syftbox/config.json`, so each env gets its own HOME
+/// and the user's global `~/.syftbox/config.json` is never touched. If the isolated copy is
+/// newer (the daemon refreshed its tokens), it is synced back to the env config instead.
+fn prepare_isolated_home(env_dir: &Path, config_path: &Path) -> Result<PathBuf> {
+    let home = get_isolated_home(env_dir);
+    for sub in [
+        PathBuf::from(".syftbox"),
+        PathBuf::from(".config"),
+        PathBuf::from(".cache"),
+        Path::new(".local").join("share"),
+        Path::new(".local").join("state"),
+    ] {
+        fs::create_dir_all(home.join(sub))
+            .with_context(|| format!("Failed to create isolated home at {:?}", home))?;
+    }
+    let isolated_config = home.join(".syftbox").join("config.json");
+    let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    match (modified(&isolated_config), modified(config_path)) {
+        (Some(isolated), Some(env)) if isolated > env => {
+            sync_config_from_isolated_home(&home, config_path)?;
+        }
+        _ => {
+            fs::copy(config_path, &isolated_config)
+                .context("Failed to copy config into isolated home")?;
+        }
+    }
+    Ok(home)
+}
+
+fn apply_isolated_home(cmd: &mut Command, home: &Path) {
+    cmd.env("HOME", home)
+        .env("USERPROFILE", home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("XDG_CACHE_HOME", home.join(".cache"))
+        .env("XDG_DATA_HOME", home.join(".local").join("share"))
+        .env("XDG_STATE_HOME", home.join(".local").join("state"));
+}
+
+/// Copy the config syftbox wrote inside the isolated home back to the env config
+fn sync_config_from_isolated_home(home: &Path, config_path: &Path) -> Result<()> {
+    let isolated_config = home.join(".syftbox").join("config.json");
+    if isolated_config.exists() {
+        fs::copy(&isolated_config, config_path)
+            .context("Failed to copy config back from isolated home")?;
+    }
+    Ok(())
+}
+
+fn get_global_syftbox_config_path() -> PathBuf {
+    let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    Path::new(&home).join(".syftbox").join("config.json")
+}
+
+/// Files left behind when an older sbenv was interrupted while swapping
+/// `~/.syftbox/config.json` with the environment config during `start`
+#[derive(Debug, Default)]
+struct SwapArtifacts {
+    /// `~/.syftbox/config.json.sbenv_backup`: the user's real global config
+    home_backup: Option<PathBuf>,
+    /// (`config.json.sbenv_local_backup`, `config.json`) pairs inside environments
+    local_backups: Vec<(PathBuf, PathBuf)>,
+}
+
+impl SwapArtifacts {
+    fn is_empty(&self) -> bool {
+        self.home_backup.is_none() && self.local_backups.is_empty()
+    }
+}
+
+fn find_interrupted_config_swap() -> SwapArtifacts {
+    let mut artifacts = SwapArtifacts::default();
+
+    let home_backup = get_global_syftbox_config_path().with_extension("json.sbenv_backup");
+    if home_backup.exists() {
+        artifacts.home_backup = Some(home_backup);
+    }
+
+    let mut config_paths: Vec<PathBuf> = load_registry()
+        .map(|r| {
+            r.environments
+                .values()
+                .map(|info| Path::new(&info.path).join(".syftbox").join("config.json"))
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Some(p) = env::current_dir()
+        .ok()
+        .and_then(|d| find_syftbox_config(&d))
+    {
+        config_paths.push(p);
+    }
+    config_paths.sort();
+    config_paths.dedup();
+    for config_path in config_paths {
+        let local_backup = config_path.with_extension("json.sbenv_local_backup");
+        if local_backup.exists() {
+            artifacts.local_backups.push((local_backup, config_path));
+        }
+    }
+
+    artifacts
+}
+
+/// Roll an interrupted swap back. Every step is safe to repeat if we are interrupted again.
+fn restore_interrupted_config_swap(artifacts: &SwapArtifacts) -> Result<()> {
+    if let Some(backup) = &artifacts.home_backup {
+        let home_config = get_global_syftbox_config_path();
+        // Whatever sits at the global path is the environment copy sbenv put there
+        if home_config.exists() {
+            fs::remove_file(&home_config)
+                .with_context(|| format!("Failed to remove {:?}", home_config))?;
+        }
+        fs::rename(backup, &home_config)
+            .with_context(|| format!("Failed to restore {:?}", home_config))?;
+    }
+    for (backup, config_path) in &artifacts.local_backups {
+        fs::copy(backup, config_path)
+            .with_context(|| format!("Failed to restore {:?}", config_path))?;
+        fs::remove_file(backup).with_context(|| format!("Failed to remove {:?}", backup))?;
+    }
+    Ok(())
+}
+
+/// Detect leftovers of an interrupted global config swap and offer to restore them
+fn recover_interrupted_config_swap() -> Result<()> {
+    let artifacts = find_interrupted_config_swap();
+    if artifacts.is_empty() {
+        return Ok(());
+    }
+
+    eprintln!(
+        "{}",
+        "⚠️  Found leftovers of an interrupted sbenv config swap:".yellow()
+    );
+    if let Some(backup) = &artifacts.home_backup {
+        eprintln!("   {}", backup.display());
+    }
+    for (backup, _) in &artifacts.local_backups {
+        eprintln!("   {}", backup.display());
+    }
+    eprintln!("   Your global syftbox config may point at an sbenv environment.");
+
+    let confirm = if atty::is(atty::Stream::Stdin) {
+        Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Restore the original configs now?")
+            .default(true)
+            .interact()?
+    } else {
+        eprintln!("   Non-interactive mode detected, restoring automatically.");
+        true
+    };
+    if !confirm {
+        eprintln!("   Skipped. You will be asked again next time.");
+        return Ok(());
+    }
+
+    restore_interrupted_config_swap(&artifacts)?;
+    eprintln!("{}", "✅ Original configs restored".green());
+    Ok(())
+}
+
+fn run_syftbox_login(
+    config_path: &Path,
+    dev_mode: bool,
+    integrity: IntegrityCheck,
+) -> Result<std::process::ExitStatus> {
+    let env_dir = config_path.parent().unwrap().parent().unwrap();
+    let (bin, _, _) = resolve_binary_for_env_with_source(config_path, false, integrity, true)?;
+    check_binary_platform(config_path, &bin)?;
+    let home = prepare_isolated_home(env_dir, config_path)?;
+    let mut cmd = Command::new(bin);
+    cmd.args(["-c", config_path.to_str().unwrap(), "login"])
+        .env("SYFTBOX_CONFIG", config_path.to_str().unwrap())
+        .env("SYFTBOX_CLIENT_CONFIG_PATH", config_path.to_str().unwrap())
+        // Enable auth bypass only in dev mode
+        .envs(if dev_mode {
+            Some(("SYFTBOX_AUTH_ENABLED", "0"))
+        } else {
+            None::<(&str, &str)>
+        });
+    apply_isolated_home(&mut cmd, &home);
+    let status = cmd
+        .status()
+        .context("Failed to run syftbox login. Is 'syftbox' installed?")?;
+    if status.success() {
+        sync_config_from_isolated_home(&home, config_path)?;
+    }
+    Ok(status)
+}
+
+fn cleanup_orphaned_processes(config_path: &Path) -> Result<()> {
+    // Check for any syftbox processes using this config file
+    let config_path_str = config_path.to_str().unwrap();
+
+    // Use pgrep to find syftbox processes
+    let output = Command::new("pgrep").args(["-fl", "syftbox"]).output();
+
+    if let Ok(output) = output {
+        let processes = String::from_utf8_lossy(&output.stdout);
+        for line in processes.lines() {
+            // Check if this process is using our config file
+            if line.contains(config_path_str) {
+                // Extract PID from the line (first field)
+                if let Some(pid_str) = line.split_whitespace().next() {
+                    if let Ok(pid) = pid_str.parse::<u32>() {
+                        println!(
+                            "Found orphaned syftbox process (PID: {}) for this environment",
+                            pid
+                        );
+                        println!("Killing orphaned process...");
+
+                        // Try graceful kill first
+                        Command::new("kill").arg(pid.to_string()).output()?;
+                        thread::sleep(Duration::from_secs(2));
+
+                        // Check if still running and force kill if needed
+                        let check = Command::new("ps").args(["-p", &pid.to_string()]).output()?;
+                        if check.status.success() {
+                            println!("Force killing stubborn process...");
+                            Command::new("kill")
+                                .args(["-9", &pid.to_string()])
+                                .output()?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn start_daemon(
+    force: bool,
+    skip_login_check: bool,
+    daemon: bool,
+    integrity: IntegrityCheck,
+) -> Result<()> {
+    let current_dir = env::current_dir().context("Failed to get current directory")?;
+    let config_path = find_syftbox_config(&current_dir)
+        .ok_or_else(|| anyhow::anyhow!("No SyftBox environment found. Run 'sbenv init' first."))?;
+
+    // Clean up any orphaned processes for this environment
+    cleanup_orphaned_processes(&config_path)?;
+
+    let mut config = load_config(&config_path)?;
+    // Always use the environment directory for PID and logs, not config.data_dir
+    // because syftbox login might change data_dir
+    let env_dir = config_path.parent().unwrap().parent().unwrap();
+    let syftbox_dir = env_dir.join(".syftbox");
+    let pid_file = syftbox_dir.join("syftbox.pid");
+    let log_file = syftbox_dir.join("daemon.log");
+
+    // Check if already running (only relevant for daemon mode)
+    if daemon && !force && pid_file.exists() {
+        if let Ok(pid_str) = fs::read_to_string(&pid_file) {
+            if let Ok(pid) = pid_str.trim().parse::<u32>() {
+                // Check if process is actually running
+                let check = Command::new("ps").args(["-p", &pid.to_string()]).output()?;
+
+                if check.status.success() {
+                    println!("{}", "SyftBox daemon is already running!".yellow());
+                    println!("  PID: {}", pid.to_string().cyan());
+                    println!("  Use {} to force restart", "sbenv start --force".yellow());
+                    return Ok(());
+                } else {
+                    // Stale PID file
+                    println!("Removing stale PID file...");
+                    fs::remove_file(&pid_file)?;
+                }
+            }
+        }
+    }
+
+    // Resolve (and verify) the binary before doing anything with it
+    let (bin, _, _) = resolve_binary_for_env_with_source(&config_path, false, integrity, true)?;
+    check_binary_platform(&config_path, &bin)?;
+
+    // Check if logged in (unless skipped or dev mode)
+    // Only prompt if there's definitely no token
+    let effective_skip_login = skip_login_check || config.dev_mode;
+    if !effective_skip_login && !check_login_status(&config_path)? {
+        prompt_and_login(&config_path, IntegrityCheck::Skip)?;
+        // Reload config after login
+        config = load_config(&config_path)?;
+    }
+
+    // Prepare args and optionally set http addr if client_url is present (or derivable)
+    let mut syftbox_args: Vec<String> = vec!["-c".into(), config_path.to_str().unwrap().into()];
+    if daemon {
+        syftbox_args.push("daemon".into());
+    }
+    if daemon {
+        // Prefer config client_url, otherwise try derive from registry port
+        let derived_url = if let Some(url) = &config.client_url {
+            Some(url.clone())
+        } else {
+            // derive http://127.0.0.1:<port> from registry if available
+            let registry = load_registry().unwrap_or(EnvRegistry {
+                environments: HashMap::new(),
+            });
+            let env_dir = config_path
+                .parent()
+                .unwrap()
+                .parent()
+                .unwrap()
+                .to_string_lossy()
+                .to_string();
+            let port = registry
+                .environments
+                .values()
+                .find(|info| info.path == env_dir)
+                .map(|info| info.port)
+                .unwrap_or(0);
+            if port > 0 {
+                Some(format!("http://127.0.0.1:{}", port))
+            } else {
+                None
+            }
+        };
+
+        if let Some(url) = derived_url {
+            let http_addr_owned = url.strip_prefix("http://").unwrap_or(&url).to_string();
+            syftbox_args.push("--http-addr".into());
+            syftbox_args.push(http_addr_owned);
+        }
+
+        // Pass the client token if available
+        if let Some(token) = &config.client_token {
+            syftbox_args.push("--http-token".into());
+            syftbox_args.push(token.clone());
+        }
+    }
+
+    if daemon {
+        println!("{}", "Starting SyftBox daemon (background)...".green());
+    } else {
+        println!("{}", "Starting SyftBox (background)...".green());
+    }
+    println!("  Email: {}", config.email.cyan());
+    // Determine client URL for display
+    let client_url_display = if let Some(url) = &config.client_url {
+        url.clone()
+    } else {
+        let registry = load_registry().unwrap_or(EnvRegistry {
+            environments: HashMap::new(),
+        });
+        let env_dir = config_path
+            .parent()
+            .unwrap()
+            .parent()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let port = registry
+            .environments
+            .values()
+            .find(|info| info.path == env_dir)
+            .map(|info| info.port)
+            .unwrap_or(0);
+        if port > 0 {
+            format!("http://127.0.0.1:{}", port)
+        } else {
+            "unknown".to_string()
+        }
+    };
+    println!("  Client URL: {}", client_url_display.cyan());
+    println!("  Data dir: {}", config.data_dir.cyan());
+    println!("  Config: {}", config_path.display().to_string().cyan());
+    if config.dev_mode {
+        println!("  Mode  : {}", "dev".cyan());
+    }
+
+    // Create log file (both modes use the same log so 'sbenv logs' works)
+    let log = fs::File::create(&log_file)?;
+
+    // syftbox ignores -c and always reads $HOME/.syftbox/config.json,
+    // so launch it with a per-env HOME instead of touching the global config
+    let isolated_home = prepare_isolated_home(env_dir, &config_path)?;
+
+    // Background execution using nohup for both modes; write output to log file
+    let mut nohup = Command::new("nohup");
+    nohup
+        .arg(bin.to_str().unwrap())
+        .args(&syftbox_args)
+        .env("SYFTBOX_CONFIG", config_path.to_str().unwrap())
+        .env("SYFTBOX_CLIENT_CONFIG_PATH", config_path.to_str().unwrap())
+        // Enable auth bypass only in dev mode
+        .envs(if config.dev_mode {
+            Some(("SYFTBOX_AUTH_ENABLED", "0"))
+        } else {
+            None::<(&str, &str)>
+        });
+    apply_isolated_home(&mut nohup, &isolated_home);
+    let child = nohup
+        .stdin(Stdio::null())
+        .stdout(Stdio::from(log.try_clone()?))
+        .stderr(Stdio::from(log))
+        .spawn()
+        .context("Failed to start syftbox in background. Is 'syftbox' installed?")?;
+
+    let pid = child.id();
+
+    // Save PID
+    fs::write(&pid_file, pid.to_string())?;
+
+    // Wait a moment and check if it's still running
+    thread::sleep(Duration::from_secs(2));
+
+    let check = Command::new("ps").args(["-p", &pid.to_string()]).output()?;
+
+    if check.status.success() {
+        if daemon {
+            println!(
+                "{}",
+                "✅ SyftBox daemon started successfully!".green().bold()
+            );
+        } else {
+            println!("{}", "✅ SyftBox started in background".green().bold());
+        }
+        println!("  PID: {}", pid.to_string().cyan());
+        println!("  Logs: {}", "sbenv logs".yellow());
+        println!("  Status: {}", "sbenv status".yellow());
+        println!("  Stop: {}", "sbenv stop".yellow());
+
+        // Try to check HTTP API (if URL is available)
+        if let Some(url) = &config.client_url {
+            thread::sleep(Duration::from_secs(1));
+            if api_responding(url) {
+                println!("  API: {} Responding", "✓".green());
+            }
+        }
+    } else {
+        fs::remove_file(&pid_file).ok();
+        println!("{}", "❌ Failed to start daemon".red());
+        println!("Check logs at: {}", log_file.display());
+        return Err(anyhow::anyhow!("Daemon failed to start"));
+    }
+
+    Ok(())
 }
 
-fn get_auto_activation_block() -> String {
-    let mut s = String::new();
-    s.push_str("# Auto-activate SyftBox envs when entering directories with a .sbenv marker\n");
-    s.push_str("_sbenv_find_root() {\n");
-    s.push_str("    local dir=\"$PWD\"\n");
-    s.push_str("    while [ \"$dir\" != \"/\" ]; do\n");
-    s.push_str("        if [ -f \"$dir/.sbenv\" ]; then\n");
-    s.push_str("            echo \"$dir\"\n");
-    s.push_str("            return 0\n");
-    s.push_str("        fi\n");
-    s.push_str("        dir=\"$(dirname \"$dir\")\"\n");
-    s.push_str("    done\n");
-    s.push_str("    return 1\n");
-    s.push_str("}\n");
-    s.push('\n');
-    s.push_str("_sbenv_auto_hook() {\n");
-    s.push_str("    local root\n");
-    s.push_str("    root=\"$(_sbenv_find_root 2>/dev/null)\"\n");
-    s.push_str("    if [ -n \"$root\" ]; then\n");
-    s.push_str("        if [ \"$SBENV_AUTO_ACTIVE_ROOT\" != \"$root\" ]; then\n");
-    s.push_str("            if [ -n \"$SYFTBOX_ENV_ACTIVE\" ]; then\n");
-    s.push_str(
-        "                SBENV_SUPPRESS_MESSAGES=1 eval \"$(command sbenv deactivate --quiet)\"\n",
-    );
-    s.push_str("            fi\n");
-    s.push_str(
-        "            SBENV_SUPPRESS_MESSAGES=1 eval \"$(command sbenv activate --quiet)\"\n",
-    );
-    s.push_str("            export SBENV_AUTO_ACTIVE_ROOT=\"$root\"\n");
-    s.push_str("        fi\n");
-    s.push_str("    else\n");
-    s.push_str(
-        "        if [ -n \"$SBENV_AUTO_ACTIVE_ROOT\" ] && [ -n \"$SYFTBOX_ENV_ACTIVE\" ]; then\n",
-    );
-    s.push_str(
-        "            SBENV_SUPPRESS_MESSAGES=1 eval \"$(command sbenv deactivate --quiet)\"\n",
-    );
-    s.push_str("            unset SBENV_AUTO_ACTIVE_ROOT\n");
-    s.push_str("        fi\n");
-    s.push_str("    fi\n");
-    s.push_str("}\n");
-    s.push('\n');
-    s.push_str("# Hook into ZSH bash-style directory change or Bash prompt\n");
-    s.push_str("if [ -n \"$ZSH_VERSION\" ]; then\n");
-    s.push_str("    typeset -ga chpwd_functions\n");
-    s.push_str("    case \" ${chpwd_functions[@]} \" in *\\ _sbenv_auto_hook\\ *) ;; *) chpwd_functions+=(_sbenv_auto_hook) ;; esac\n");
-    s.push_str(
-        "    # Don't call _sbenv_auto_hook immediately - let it run on first directory change\n",
+fn stop_daemon() -> Result<()> {
+    let current_dir = env::current_dir().context("Failed to get current directory")?;
+    let config_path = find_syftbox_config(&current_dir)
+        .ok_or_else(|| anyhow::anyhow!("No SyftBox environment found"))?;
+
+    let _config = load_config(&config_path)?;
+    // Always use the environment directory for PID file
+    let env_dir = config_path.parent().unwrap().parent().unwrap();
+    let pid_file = env_dir.join(".syftbox").join("syftbox.pid");
+
+    // First, check for and clean up any orphaned processes for this environment
+    cleanup_orphaned_processes(&config_path)?;
+
+    if !pid_file.exists() {
+        println!("{}", "No daemon PID file found".yellow());
+        return Ok(());
+    }
+
+    let pid_str = fs::read_to_string(&pid_file)?;
+    let pid = pid_str
+        .trim()
+        .parse::<u32>()
+        .context("Invalid PID in file")?;
+
+    // Check if process is running
+    let check = Command::new("ps").args(["-p", &pid.to_string()]).output()?;
+
+    if !check.status.success() {
+        println!("Daemon not running (cleaning up stale PID)");
+        fs::remove_file(&pid_file)?;
+        return Ok(());
+    }
+
+    println!(
+        "Stopping SyftBox daemon (PID: {})...",
+        pid.to_string().cyan()
     );
-    s.push_str("else\n");
-    s.push_str("    if [ -z \"$SBENV_AUTO_PROMPT_HOOK\" ]; then\n");
-    s.push_str("        export PROMPT_COMMAND=\"_sbenv_auto_hook; ${PROMPT_COMMAND}\"\n");
-    s.push_str("        export SBENV_AUTO_PROMPT_HOOK=1\n");
-    s.push_str("    fi\n");
-    s.push_str("    # Don't call _sbenv_auto_hook immediately - let it run on first prompt\n");
-    s.push_str("fi\n");
-    s
+
+    // Send SIGTERM
+    Command::new("kill").arg(pid.to_string()).output()?;
+
+    // Wait for graceful shutdown
+    for i in 0..10 {
+        thread::sleep(Duration::from_secs(1));
+        let check = Command::new("ps").args(["-p", &pid.to_string()]).output()?;
+
+        if !check.status.success() {
+            println!("{}", "✅ SyftBox daemon stopped".green());
+            fs::remove_file(&pid_file)?;
+            return Ok(());
+        }
+
+        if i == 5 {
+            println!("Daemon still running, sending force kill...");
+            Command::new("kill")
+                .args(["-9", &pid.to_string()])
+                .output()?;
+        }
+    }
+
+    fs::remove_file(&pid_file)?;
+    println!("{}", "✅ SyftBox daemon force stopped".green());
+    Ok(())
+}
+
+fn show_daemon_status() -> Result<()> {
+    let current_dir = env::current_dir().context("Failed to get current directory")?;
+    let config_path = find_syftbox_config(&current_dir)
+        .ok_or_else(|| anyhow::anyhow!("No SyftBox environment found"))?;
+
+    let config = load_config(&config_path)?;
+
+    // Ensure .sbenv marker exists for this environment
+    let _ = ensure_marker_exists(&config_path, &config);
+    // Always use the environment directory for PID file
+    let env_dir = config_path.parent().unwrap().parent().unwrap();
+    let pid_file = env_dir.join(".syftbox").join("syftbox.pid");
+
+    if !pid_file.exists() {
+        println!("{} No daemon found", "✗".red());
+        println!("  Run {} to start", "sbenv start".yellow());
+        return Ok(());
+    }
+
+    let pid_str = fs::read_to_string(&pid_file)?;
+    let pid = pid_str
+        .trim()
+        .parse::<u32>()
+        .context("Invalid PID in file")?;
+
+    // Check if process is running
+    let check = Command::new("ps").args(["-p", &pid.to_string()]).output()?;
+
+    if !check.status.success() {
+        println!("{} Daemon not running (stale PID: {})", "✗".red(), pid);
+        fs::remove_file(&pid_file)?;
+        return Ok(());
+    }
+
+    println!("{} SyftBox daemon running", "✓".green());
+    println!("  PID: {}", pid.to_string().cyan());
+    println!("  Email: {}", config.email.cyan());
+    // Determine client URL for display (prefer config, fallback to registry)
+    let client_url_display = if let Some(url) = &config.client_url {
+        url.clone()
+    } else {
+        let registry = load_registry().unwrap_or(EnvRegistry {
+            environments: HashMap::new(),
+        });
+        let env_dir = config_path
+            .parent()
+            .unwrap()
+            .parent()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let port = registry
+            .environments
+            .values()
+            .find(|info| info.path == env_dir)
+            .map(|info| info.port)
+            .unwrap_or(0);
+        if port > 0 {
+            format!("http://127.0.0.1:{}", port)
+        } else {
+            "unknown".to_string()
+        }
+    };
+    println!("  Client URL: {}", client_url_display.cyan());
+    println!("  Data dir: {}", config.data_dir.cyan());
+
+    // Check API
+    if let Some(url) = config
+        .client_url
+        .as_ref()
+        .or(Some(&client_url_display))
+        .filter(|u| *u != "unknown")
+    {
+        if api_responding(url) {
+            println!("  API: {} Responding", "✓".green());
+        } else {
+            println!("  API: {} Not responding", "✗".red());
+        }
+    } else {
+        println!("  API: {} URL not set in config", "–".dimmed());
+    }
+
+    Ok(())
+}
+
+fn show_daemon_logs(lines: Option<usize>, follow: bool) -> Result<()> {
+    let current_dir = env::current_dir().context("Failed to get current directory")?;
+    let config_path = find_syftbox_config(&current_dir)
+        .ok_or_else(|| anyhow::anyhow!("No SyftBox environment found"))?;
+
+    let _config = load_config(&config_path)?;
+    // Always use the environment directory for log file
+    let env_dir = config_path.parent().unwrap().parent().unwrap();
+    let log_file = env_dir.join(".syftbox").join("daemon.log");
+
+    if !log_file.exists() {
+        println!("{}", "No log file found".yellow());
+        println!("Start the daemon first with: {}", "sbenv start".cyan());
+        return Ok(());
+    }
+
+    let mut args = vec![];
+    let lines_str;
+
+    if let Some(n) = lines {
+        lines_str = format!("-{}", n);
+        args.push(lines_str.as_str());
+    } else if follow {
+        args.push("-f");
+    } else {
+        args.push("-50");
+    }
+
+    args.push(log_file.to_str().unwrap());
+
+    let status = Command::new("tail").args(&args).status()?;
+
+    if !status.success() {
+        return Err(anyhow::anyhow!("Failed to read log file"));
+    }
+
+    Ok(())
+}
+
+fn restart_daemon() -> Result<()> {
+    println!("{}", "Restarting SyftBox daemon...".yellow());
+
+    // Stop if running
+    let _ = stop_daemon();
+
+    thread::sleep(Duration::from_secs(1));
+
+    // Start again
+    start_daemon(false, false, true, IntegrityCheck::Warn)
 }
 
-fn install_shell_functions() -> Result<()> {
-    let shell = env::var("SHELL").unwrap_or_else(|_| String::from("/bin/bash"));
-    let shell_name = if shell.contains("zsh") {
-        "ZSH"
-    } else if shell.contains("bash") {
-        "Bash"
-    } else if shell.contains("fish") {
-        "Fish"
-    } else {
-        "your shell"
+/// How long a status probe of a starting daemon may take
+const API_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whether the control plane API answers (a 401 still means it is up)
+fn api_responding(url: &str) -> bool {
+    let Ok(client) = http_client() else {
+        return false;
     };
+    client
+        .get(format!("{}/v1/status", url.trim_end_matches('/')))
+        .timeout(API_PROBE_TIMEOUT)
+        .send()
+        .map(|resp| {
+            let status = resp.status();
+            status == reqwest::StatusCode::OK || status == reqwest::StatusCode::UNAUTHORIZED
+        })
+        .unwrap_or(false)
+}
 
-    let rc_file = get_shell_config_file()?;
+/// PID of the environment's daemon, if it is running
+fn running_daemon_pid(env_dir: &Path) -> Option<u32> {
+    let pid_file = env_dir.join(".syftbox").join("syftbox.pid");
+    let pid = fs::read_to_string(pid_file)
+        .ok()?
+        .trim()
+        .parse::<u32>()
+        .ok()?;
+    let check = Command::new("ps")
+        .args(["-o", "stat=", "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    // A daemon we spawned ourselves lingers as a zombie after it dies
+    let stat = String::from_utf8_lossy(&check.stdout);
+    (check.status.success() && !stat.trim_start().starts_with('Z')).then_some(pid)
+}
 
-    println!("{}", format!("🐚 Detected shell: {}", shell_name).cyan());
-    println!(
-        "📄 Configuration file: {}",
-        rc_file.display().to_string().cyan()
-    );
-    println!();
+/// Client URL from the config, or derived from the registered port
+fn env_client_url(config: &SyftBoxConfig, env_dir: &Path) -> Option<String> {
+    if let Some(url) = &config.client_url {
+        return Some(url.clone());
+    }
+    let registry = load_registry().ok()?;
+    let env_dir = env_dir.to_string_lossy();
+    registry
+        .environments
+        .values()
+        .find(|info| info.path == env_dir)
+        .map(|info| info.port)
+        .filter(|port| *port > 0)
+        .map(|port| format!("http://127.0.0.1:{}", port))
+}
 
-    // Determine what needs to be added
-    let already_funcs = check_shell_functions_installed(&rc_file)?;
-    let already_auto = check_auto_activation_installed(&rc_file)?;
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
 
-    if already_funcs && already_auto {
-        println!(
-            "{}",
-            "✅ SyftBox shell functions and auto-activation are already installed!".green()
-        );
-        println!("All helpers are ready to use.");
-        println!("If you haven't reloaded your shell config, run:");
-        println!("  {}", format!("source {}", rc_file.display()).yellow());
-        return Ok(());
+/// Wait for a freshly started daemon to stay up and, when it has a client URL, answer its API
+fn wait_until_ready(env_dir: &Path, url: Option<&str>, timeout: Duration) -> Result<()> {
+    let log_file = env_dir.join(".syftbox").join("daemon.log");
+    let started = std::time::Instant::now();
+    loop {
+        if running_daemon_pid(env_dir).is_none() {
+            return Err(anyhow::anyhow!(
+                "syftbox exited during startup. See {}",
+                log_file.display()
+            ));
+        }
+        match url {
+            Some(url) if api_responding(url) => return Ok(()),
+            // Without an API to ask, staying up for a few seconds is the best signal
+            None if started.elapsed() >= Duration::from_secs(5) => return Ok(()),
+            _ => {}
+        }
+        if started.elapsed() >= timeout {
+            return Err(anyhow::anyhow!(
+                "syftbox did not become ready within {}s. See {}",
+                timeout.as_secs(),
+                log_file.display()
+            ));
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// Paths under `.syftbox` left out of upgrade snapshots: logs, the PID file and caches
+const SNAPSHOT_EXCLUDE: &[&str] = &["daemon.log", "syftbox.pid", "home/.cache"];
+
+fn get_snapshots_dir() -> PathBuf {
+    let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    Path::new(&home).join(".sbenv").join("snapshots")
+}
+
+/// Copy a directory tree, skipping entries whose path relative to `src` is in `exclude`
+fn copy_tree(src: &Path, dst: &Path, exclude: &[&str]) -> Result<()> {
+    let mut stack = vec![PathBuf::new()];
+    while let Some(rel) = stack.pop() {
+        fs::create_dir_all(dst.join(&rel))?;
+        for entry in fs::read_dir(src.join(&rel))? {
+            let entry = entry?;
+            let rel_path = rel.join(entry.file_name());
+            if exclude.iter().any(|e| rel_path == Path::new(e)) {
+                continue;
+            }
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                stack.push(rel_path);
+            } else if file_type.is_symlink() {
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(fs::read_link(entry.path())?, dst.join(&rel_path))?;
+                #[cfg(not(unix))]
+                fs::copy(entry.path(), dst.join(&rel_path))?;
+            } else {
+                fs::copy(entry.path(), dst.join(&rel_path))
+                    .with_context(|| format!("Failed to copy {:?}", entry.path()))?;
+            }
+        }
     }
+    Ok(())
+}
 
-    let mut to_add = String::new();
-    if !already_funcs {
-        to_add.push_str(&get_shell_functions());
-        to_add.push('\n');
+/// Snapshot an environment's `.syftbox` state before touching its binary
+fn snapshot_syftbox_state(env_dir: &Path, env_key: &str) -> Result<PathBuf> {
+    let snapshot = get_snapshots_dir().join(format!(
+        "{}-{}",
+        &sha256_hex(env_key.as_bytes())[..12],
+        chrono::Utc::now().format("%Y%m%dT%H%M%SZ")
+    ));
+    copy_tree(&env_dir.join(".syftbox"), &snapshot, SNAPSHOT_EXCLUDE)
+        .with_context(|| format!("Failed to snapshot {:?}", env_dir.join(".syftbox")))?;
+    Ok(snapshot)
+}
+
+/// Put a snapshot back in place. Current logs are kept so the failed start can be inspected.
+fn restore_syftbox_state(env_dir: &Path, snapshot: &Path) -> Result<()> {
+    let syftbox_dir = env_dir.join(".syftbox");
+    for entry in fs::read_dir(&syftbox_dir)?.flatten() {
+        if entry.file_name() == "daemon.log" {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
     }
-    if !already_auto {
-        to_add.push_str(&get_auto_activation_block());
-        to_add.push('\n');
+    copy_tree(snapshot, &syftbox_dir, &[])
+}
+
+/// One recorded change to an environment's binary
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct HistoryEntry {
+    timestamp: String,
+    action: String,
+    from: String,
+    to: String,
+    /// success, rolled-back or rollback-failed
+    outcome: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+fn get_history_path() -> PathBuf {
+    let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    Path::new(&home).join(".sbenv").join("history.json")
+}
+
+/// Per-environment history, keyed like the registry
+fn load_history() -> HashMap<String, Vec<HistoryEntry>> {
+    fs::read_to_string(get_history_path())
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+fn record_history(env_key: &str, entry: HistoryEntry) -> Result<()> {
+    let mut history = load_history();
+    history.entry(env_key.to_string()).or_default().push(entry);
+    let path = get_history_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
     }
+    fs::write(&path, serde_json::to_string_pretty(&history)?)?;
+    Ok(())
+}
 
-    // Show what will be added
-    println!("The following will be added to your {} file:", shell_name);
-    println!("{}", "─".repeat(50).dimmed());
-    print!("{}", to_add.dimmed());
-    println!("{}", "─".repeat(50).dimmed());
-    println!();
+fn describe_binary(bin: &Path, version: Option<&str>) -> String {
+    match version {
+        Some(v) => format!("{} ({})", v, bin.display()),
+        None => bin.display().to_string(),
+    }
+}
 
-    // Ask for confirmation
-    let confirm = if atty::is(atty::Stream::Stdin) {
-        Confirm::with_theme(&ColorfulTheme::default())
-            .with_prompt(format!("Add SyftBox functions to {}?", rc_file.display()))
-            .default(true)
-            .interact()?
-    } else {
-        println!("Non-interactive mode detected. Run with --manual flag to see installation instructions.");
-        return Ok(());
+/// Switch an environment to a new syftbox binary. A running daemon is stopped, its state
+/// snapshotted and restarted on the new binary; if that does not come up, the previous binary
+/// and state are put back.
+fn upgrade_environment(to: Option<&str>, skip_verify: bool) -> Result<()> {
+    let current_dir = env::current_dir().context("Failed to get current directory")?;
+    let config_path = find_syftbox_config(&current_dir)
+        .ok_or_else(|| anyhow::anyhow!("No SyftBox environment found. Run 'sbenv init' first."))?;
+    let config = load_config(&config_path)?;
+    let env_dir = config_path
+        .parent()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_path_buf();
+    let env_key = generate_env_key(&env_dir, &config.email);
+    let old_info = load_registry()?
+        .environments
+        .get(&env_key)
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("Environment is not registered. Run 'sbenv init' first."))?;
+
+    // What the env runs now, so a pinned range isn't already moved to its newest release
+    let (old_bin, old_ver, source) =
+        resolve_binary_for_env_with_source(&config_path, true, IntegrityCheck::Skip, false)
+            .or_else(|_| {
+                resolve_binary_for_env_with_source(&config_path, true, IntegrityCheck::Skip, true)
+            })?;
+    // An env following the project's .syftbox-version keeps following it
+    let pin = project_pin(&env_dir).filter(|p| p.display().to_string() == source);
+    let follows_pin = to.is_none() && pin.is_some();
+    // Follow the pin file or the env's tracked range by default, otherwise move to the
+    // latest release
+    let spec = match (to, &pin) {
+        (Some(to), _) => to.to_string(),
+        (None, Some(pin)) => read_pin_file(pin)?,
+        (None, None) => old_info
+            .binary_spec
+            .clone()
+            .unwrap_or_else(|| "latest".to_string()),
     };
 
-    if !confirm {
-        println!("{}", "Installation cancelled.".yellow());
-        println!("You can manually add the functions by running:");
-        println!("  {}", "sbenv install-shell --manual".cyan());
+    println!(
+        "{}",
+        format!("⬆️  Upgrading syftbox for {}", config.email).bold()
+    );
+    let (new_bin, new_ver) = resolve_or_install_syftbox(&spec, false, skip_verify)?;
+    let from = describe_binary(&old_bin, old_ver.as_deref());
+    let to_desc = describe_binary(&new_bin, new_ver.as_deref());
+    if new_bin == old_bin {
+        println!("{}", format!("✅ Already on {}", from).green());
         return Ok(());
     }
+    println!("  From: {}", from.dimmed());
+    println!("  To  : {}", to_desc.cyan());
 
-    // Create backup
-    if rc_file.exists() {
-        let backup_path = rc_file.with_extension("bak.sbenv");
-        fs::copy(&rc_file, &backup_path)?;
-        println!(
-            "📦 Backup created: {}",
-            backup_path.display().to_string().dimmed()
-        );
+    let was_running = running_daemon_pid(&env_dir).is_some();
+    if was_running {
+        stop_daemon()?;
     }
+    let snapshot = snapshot_syftbox_state(&env_dir, &env_key)?;
+    println!("  Snapshot: {}", snapshot.display().to_string().dimmed());
 
-    // Append to rc file
-    let mut existing_content = if rc_file.exists() {
-        fs::read_to_string(&rc_file)?
+    let mut registry = load_registry()?;
+    if let Some(info) = registry.environments.get_mut(&env_key) {
+        set_env_binary(info, &spec, &new_bin, new_ver.clone());
+        if follows_pin {
+            info.binary_pinned = false;
+        }
+    }
+    save_registry(&registry)?;
+
+    let result = if was_running {
+        start_daemon(false, true, true, IntegrityCheck::Warn).and_then(|_| {
+            println!("Waiting for syftbox to become ready...");
+            wait_until_ready(
+                &env_dir,
+                env_client_url(&config, &env_dir).as_deref(),
+                READY_TIMEOUT,
+            )
+        })
+    } else if detect_binary_version(&new_bin).is_none() {
+        Err(anyhow::anyhow!(
+            "{} does not run on this machine",
+            new_bin.display()
+        ))
     } else {
-        String::new()
+        Ok(())
+    };
+
+    let entry = |outcome: &str, detail: Option<String>| HistoryEntry {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        action: "upgrade".to_string(),
+        from: from.clone(),
+        to: to_desc.clone(),
+        outcome: outcome.to_string(),
+        detail,
+    };
+    let err = match result {
+        Ok(()) => {
+            let _ = fs::remove_dir_all(&snapshot);
+            record_history(&env_key, entry("success", None))?;
+            println!(
+                "{}",
+                format!("✅ Upgraded to {}", new_ver.as_deref().unwrap_or(&spec))
+                    .green()
+                    .bold()
+            );
+            return Ok(());
+        }
+        Err(e) => e,
     };
 
-    // Ensure there's a newline before our content
-    if !existing_content.is_empty() && !existing_content.ends_with('\n') {
-        existing_content.push('\n');
+    println!("{}", format!("❌ New binary failed: {:#}", err).red());
+    println!("{}", "Rolling back...".yellow());
+    let rollback = (|| -> Result<()> {
+        if running_daemon_pid(&env_dir).is_some() {
+            let _ = stop_daemon();
+        }
+        restore_syftbox_state(&env_dir, &snapshot)?;
+        let mut registry = load_registry()?;
+        registry
+            .environments
+            .insert(env_key.clone(), old_info.clone());
+        save_registry(&registry)?;
+        if was_running {
+            start_daemon(false, true, true, IntegrityCheck::Warn)?;
+        }
+        Ok(())
+    })();
+    match rollback {
+        Ok(()) => {
+            let _ = fs::remove_dir_all(&snapshot);
+            record_history(&env_key, entry("rolled-back", Some(format!("{:#}", err))))?;
+            Err(anyhow::anyhow!(
+                "Upgrade failed and was rolled back to {}: {:#}",
+                from,
+                err
+            ))
+        }
+        Err(rollback_err) => {
+            record_history(
+                &env_key,
+                entry(
+                    "rollback-failed",
+                    Some(format!("{:#}; rollback: {:#}", err, rollback_err)),
+                ),
+            )?;
+            Err(anyhow::anyhow!(
+                "Upgrade failed ({:#}) and so did the rollback ({:#}). The previous state is kept at {}",
+                err,
+                rollback_err,
+                snapshot.display()
+            ))
+        }
     }
+}
 
-    existing_content.push_str(&to_add);
-    existing_content.push('\n');
-    existing_content.push_str(&get_auto_activation_block());
+fn show_history() -> Result<()> {
+    let current_dir = env::current_dir().context("Failed to get current directory")?;
+    let config_path = find_syftbox_config(&current_dir)
+        .ok_or_else(|| anyhow::anyhow!("No SyftBox environment found"))?;
+    let config = load_config(&config_path)?;
+    let env_dir = config_path.parent().unwrap().parent().unwrap();
+    let entries = load_history()
+        .remove(&generate_env_key(env_dir, &config.email))
+        .unwrap_or_default();
+    if entries.is_empty() {
+        println!("{}", "No history for this environment yet.".yellow());
+        return Ok(());
+    }
+    for e in entries {
+        let outcome = match e.outcome.as_str() {
+            "success" => e.outcome.green(),
+            "rolled-back" => e.outcome.yellow(),
+            _ => e.outcome.red(),
+        };
+        println!("{}  {}  {}", e.timestamp.dimmed(), e.action.bold(), outcome);
+        println!("     From: {}", e.from);
+        println!("     To  : {}", e.to);
+        if let Some(d) = e.detail {
+            println!("     Note: {}", d);
+        }
+    }
+    Ok(())
+}
 
-    fs::write(&rc_file, existing_content)?;
+fn restore_config_after_login(config_path: &Path, original_config: &SyftBoxConfig) -> Result<()> {
+    // Load the config that syftbox login modified
+    let content = fs::read_to_string(config_path)?;
+    let mut modified_config: serde_json::Value = serde_json::from_str(&content)?;
 
-    println!(
-        "{}",
-        "✅ SyftBox functions successfully installed!"
-            .green()
-            .bold()
-    );
-    println!();
-    println!("To start using the new commands, either:");
-    println!(
-        "  1. Run: {}",
-        format!("source {}", rc_file.display()).yellow()
-    );
-    println!("  2. Open a new terminal");
-    println!();
-    println!("Available commands:");
-    println!(
-        "  {} - Activate environment (no eval needed!)",
-        "sbenv activate".cyan()
-    );
-    println!("  {} - Deactivate environment", "sbenv deactivate".cyan());
-    println!("  {} - Activate (shortcut)", "sba".cyan());
-    println!("  {} - Deactivate (shortcut)", "sbd".cyan());
-    println!("  {} - Show info (shortcut)", "sbi".cyan());
-    println!();
-    println!(
-        "{}",
-        "⚠️  Important for Powerlevel10k users:".yellow().bold()
-    );
-    println!(
-        "   Add this line at the END of your {} file:",
-        rc_file.display()
-    );
-    println!();
-    println!(
-        "   {}",
-        "(( ! ${+functions[p10k]} )) || p10k finalize".cyan()
-    );
-    println!();
-    println!("   This prevents instant prompt errors with sbenv auto-activation.");
+    // Restore original values but keep refresh_token
+    if let Some(obj) = modified_config.as_object_mut() {
+        obj.insert(
+            "data_dir".to_string(),
+            serde_json::Value::String(original_config.data_dir.clone()),
+        );
+        obj.insert(
+            "email".to_string(),
+            serde_json::Value::String(original_config.email.clone()),
+        );
+        obj.insert(
+            "server_url".to_string(),
+            serde_json::Value::String(original_config.server_url.clone()),
+        );
+        if let Some(url) = &original_config.client_url {
+            obj.insert(
+                "client_url".to_string(),
+                serde_json::Value::String(url.clone()),
+            );
+        } else {
+            obj.remove("client_url");
+        }
+        // Preserve dev_mode flag
+        obj.insert(
+            "dev_mode".to_string(),
+            serde_json::Value::Bool(original_config.dev_mode),
+        );
+        // Keep the refresh_token from login
+    }
 
+    // Write back the fixed config
+    let fixed_json = serde_json::to_string_pretty(&modified_config)?;
+    fs::write(config_path, fixed_json)?;
+
+    println!("  Restored environment config settings");
     Ok(())
 }
 
-fn check_login_status(config_path: &Path) -> Result<bool> {
-    // Check if refresh_token exists in config
-    let config = load_config(config_path)?;
-
-    // If there's a refresh token, assume we're logged in
-    // The daemon will fail if the token is invalid, and we'll handle that
-    Ok(config.refresh_token.is_some())
-}
+fn login_to_syftbox(integrity: IntegrityCheck) -> Result<()> {
+    let current_dir = env::current_dir().context("Failed to get current directory")?;
+    let config_path = find_syftbox_config(&current_dir)
+        .ok_or_else(|| anyhow::anyhow!("No SyftBox environment found. Run 'sbenv init' first."))?;
 
-fn prompt_and_login(config_path: &Path) -> Result<()> {
-    // If this environment is in dev mode, do not attempt login
-    if load_config(config_path)?.dev_mode {
+    let original_config = load_config(&config_path)?;
+
+    if original_config.dev_mode {
         println!("{}", "Dev mode environment: skipping login.".yellow());
         return Ok(());
     }
 
-    println!("{}", "You are not logged in to SyftBox.".yellow());
+    println!("{}", "Logging in to SyftBox...".green());
+    println!("  Email: {}", original_config.email.cyan());
+    println!("  Server: {}", original_config.server_url.cyan());
+    println!("  Config: {}", config_path.display().to_string().cyan());
+    println!();
 
-    let confirm = if atty::is(atty::Stream::Stdin) {
-        Confirm::with_theme(&ColorfulTheme::default())
-            .with_prompt("Would you like to log in now?")
-            .default(true)
-            .interact()?
-    } else {
-        println!("Cannot prompt for login in non-interactive mode.");
-        println!(
-            "Please run: {}",
-            format!("syftbox -c {} login", config_path.display()).cyan()
-        );
-        return Err(anyhow::anyhow!("Not logged in"));
-    };
+    let status = run_syftbox_login(&config_path, original_config.dev_mode, integrity)?;
 
-    if !confirm {
-        println!("{}", "Cannot start daemon without logging in.".red());
+    if status.success() {
+        // Restore original config values but keep the new refresh_token
+        restore_config_after_login(&config_path, &original_config)?;
+
+        println!();
+        println!("{}", "✅ Login successful!".green().bold());
         println!(
-            "Run: {}",
-            format!("syftbox -c {} login", config_path.display()).cyan()
+            "You can now run {} to start the daemon",
+            "sbenv start".yellow()
         );
-        return Err(anyhow::anyhow!("Login required"));
-    }
-
-    // Save original config before login
-    let original_config = load_config(config_path)?;
-
-    println!("Logging in to SyftBox...");
-    let (bin, _) = resolve_binary_for_env(config_path, false)?;
-    let mut cmd = Command::new(bin);
-    let status = cmd
-        .args(["-c", config_path.to_str().unwrap(), "login"])
-        .env("SYFTBOX_CONFIG", config_path.to_str().unwrap())
-        .env("SYFTBOX_CLIENT_CONFIG_PATH", config_path.to_str().unwrap())
-        // Enable auth bypass only in dev mode
-        .envs(
-            if load_config(config_path)?.dev_mode {
-                Some(("SYFTBOX_AUTH_ENABLED", "0"))
-            } else {
-                None::<(&str, &str)>
-            }
-            .into_iter(),
-        )
-        .status()?;
-
-    if !status.success() {
+    } else {
         return Err(anyhow::anyhow!("Login failed"));
     }
 
-    // Restore original config values but keep the new refresh_token
-    restore_config_after_login(config_path, &original_config)?;
-
-    println!("{}", "✅ Login successful!".green());
     Ok(())
 }
 
-fn cleanup_orphaned_processes(config_path: &Path) -> Result<()> {
-    // Check for any syftbox processes using this config file
-    let config_path_str = config_path.to_str().unwrap();
-
-    // Use pgrep to find syftbox processes
-    let output = Command::new("pgrep").args(["-fl", "syftbox"]).output();
-
-    if let Ok(output) = output {
-        let processes = String::from_utf8_lossy(&output.stdout);
-        for line in processes.lines() {
-            // Check if this process is using our config file
-            if line.contains(config_path_str) {
-                // Extract PID from the line (first field)
-                if let Some(pid_str) = line.split_whitespace().next() {
-                    if let Ok(pid) = pid_str.parse::<u32>() {
-                        println!(
-                            "Found orphaned syftbox process (PID: {}) for this environment",
-                            pid
-                        );
-                        println!("Killing orphaned process...");
-
-                        // Try graceful kill first
-                        Command::new("kill").arg(pid.to_string()).output()?;
-                        thread::sleep(Duration::from_secs(2));
+fn list_environments() -> Result<()> {
+    let registry = load_registry()?;
 
-                        // Check if still running and force kill if needed
-                        let check = Command::new("ps").args(["-p", &pid.to_string()]).output()?;
-                        if check.status.success() {
-                            println!("Force killing stubborn process...");
-                            Command::new("kill")
-                                .args(["-9", &pid.to_string()])
-                                .output()?;
-                        }
-                    }
-                }
-            }
-        }
+    if registry.environments.is_empty() {
+        println!("{}", "No SyftBox environments registered yet.".yellow());
+        println!("Use {} to create one.", "sbenv init".cyan());
+        return Ok(());
     }
 
-    Ok(())
-}
-
-fn start_daemon(force: bool, skip_login_check: bool, daemon: bool) -> Result<()> {
-    let current_dir = env::current_dir().context("Failed to get current directory")?;
-    let config_path = find_syftbox_config(&current_dir)
-        .ok_or_else(|| anyhow::anyhow!("No SyftBox environment found. Run 'sbenv init' first."))?;
-
-    // Clean up any orphaned processes for this environment
-    cleanup_orphaned_processes(&config_path)?;
+    println!("{}", "📦 SyftBox Environments".bold());
+    println!();
 
-    let mut config = load_config(&config_path)?;
-    // Always use the environment directory for PID and logs, not config.data_dir
-    // because syftbox login might change data_dir
-    let env_dir = config_path.parent().unwrap().parent().unwrap();
-    let syftbox_dir = env_dir.join(".syftbox");
-    let pid_file = syftbox_dir.join("syftbox.pid");
-    let log_file = syftbox_dir.join("daemon.log");
+    // Collect and sort by email (case-insensitive)
+    let mut envs: Vec<&EnvInfo> = registry.environments.values().collect();
+    envs.sort_by_key(|a| a.email.to_lowercase());
 
-    // Check if already running (only relevant for daemon mode)
-    if daemon && !force && pid_file.exists() {
-        if let Ok(pid_str) = fs::read_to_string(&pid_file) {
-            if let Ok(pid) = pid_str.trim().parse::<u32>() {
-                // Check if process is actually running
-                let check = Command::new("ps").args(["-p", &pid.to_string()]).output()?;
+    for info in envs {
+        let path = Path::new(&info.path);
+        let exists = path.join(".syftbox").exists();
+        let status = if exists { "✅".green() } else { "❌".red() };
+        let top_name = Path::new(&info.path)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("?");
+        let dev_label = if info.dev_mode { " - DEV" } else { "" };
 
-                if check.status.success() {
-                    println!("{}", "SyftBox daemon is already running!".yellow());
-                    println!("  PID: {}", pid.to_string().cyan());
-                    println!("  Use {} to force restart", "sbenv start --force".yellow());
-                    return Ok(());
-                } else {
-                    // Stale PID file
-                    println!("Removing stale PID file...");
-                    fs::remove_file(&pid_file)?;
-                }
-            }
+        // Primary line: status + email [ - DEV ] (top folder name)
+        println!(
+            "  {} {}{} ({})",
+            status,
+            info.email.cyan(),
+            dev_label,
+            top_name
+        );
+        println!("     Path : {}", info.path);
+        println!("     Port : {}", info.port);
+        if !info.server_url.is_empty() {
+            println!("     Server: {}", info.server_url);
         }
-    }
-
-    // Check if logged in (unless skipped or dev mode)
-    // Only prompt if there's definitely no token
-    let effective_skip_login = skip_login_check || config.dev_mode;
-    if !effective_skip_login && !check_login_status(&config_path)? {
-        prompt_and_login(&config_path)?;
-        // Reload config after login
-        config = load_config(&config_path)?;
-    }
-
-    // Prepare args and optionally set http addr if client_url is present (or derivable)
-    let mut syftbox_args: Vec<String> = vec!["-c".into(), config_path.to_str().unwrap().into()];
-    if daemon {
-        syftbox_args.push("daemon".into());
-    }
-    if daemon {
-        // Prefer config client_url, otherwise try derive from registry port
-        let derived_url = if let Some(url) = &config.client_url {
-            Some(url.clone())
-        } else {
-            // derive http://127.0.0.1:<port> from registry if available
-            let registry = load_registry().unwrap_or(EnvRegistry {
-                environments: HashMap::new(),
-            });
-            let env_dir = config_path
-                .parent()
-                .unwrap()
-                .parent()
-                .unwrap()
-                .to_string_lossy()
-                .to_string();
-            let port = registry
-                .environments
-                .values()
-                .find(|info| info.path == env_dir)
-                .map(|info| info.port)
-                .unwrap_or(0);
-            if port > 0 {
-                Some(format!("http://127.0.0.1:{}", port))
-            } else {
-                None
+        if let Some(b) = &info.binary {
+            println!("     Bin  : {}", b);
+        }
+        if let Some(v) = &info.binary_version {
+            match &info.binary_spec {
+                Some(spec) => println!("     Ver  : {} ({})", v, spec),
+                None => println!("     Ver  : {}", v),
             }
-        };
-
-        if let Some(url) = derived_url {
-            let http_addr_owned = url.strip_prefix("http://").unwrap_or(&url).to_string();
-            syftbox_args.push("--http-addr".into());
-            syftbox_args.push(http_addr_owned);
         }
-
-        // Pass the client token if available
-        if let Some(token) = &config.client_token {
-            syftbox_args.push("--http-token".into());
-            syftbox_args.push(token.clone());
+        if let Some(h) = &info.binary_hash {
+            println!("     Hash : {}", h);
+        }
+        if let Some(d) = &info.release_sha256 {
+            println!("     SHA256: {} (verified)", d);
+        }
+        if info.binary_os.is_some() || info.binary_arch.is_some() {
+            println!(
+                "     Target: {}/{}",
+                info.binary_os.as_deref().unwrap_or("?"),
+                info.binary_arch.as_deref().unwrap_or("?")
+            );
         }
-    }
 
-    if daemon {
-        println!("{}", "Starting SyftBox daemon (background)...".green());
-    } else {
-        println!("{}", "Starting SyftBox (background)...".green());
-    }
-    println!("  Email: {}", config.email.cyan());
-    // Determine client URL for display
-    let client_url_display = if let Some(url) = &config.client_url {
-        url.clone()
-    } else {
-        let registry = load_registry().unwrap_or(EnvRegistry {
-            environments: HashMap::new(),
-        });
-        let env_dir = config_path
-            .parent()
-            .unwrap()
-            .parent()
-            .unwrap()
-            .to_string_lossy()
-            .to_string();
-        let port = registry
-            .environments
-            .values()
-            .find(|info| info.path == env_dir)
-            .map(|info| info.port)
-            .unwrap_or(0);
-        if port > 0 {
-            format!("http://127.0.0.1:{}", port)
+        // Show process info (last known PID and whether it's active)
+        let pid_file = path.join(".syftbox").join("syftbox.pid");
+        if pid_file.exists() {
+            match fs::read_to_string(&pid_file) {
+                Ok(pid_str) => {
+                    let pid_str = pid_str.trim().to_string();
+                    if let Ok(pid) = pid_str.parse::<u32>() {
+                        let check = Command::new("ps")
+                            .args(["-p", &pid.to_string()])
+                            .stdout(Stdio::null())
+                            .stderr(Stdio::null())
+                            .status();
+                        let running = matches!(check, Ok(s) if s.success());
+                        if running {
+                            println!("     PID  : {} (active)", pid.to_string().cyan());
+                        } else {
+                            println!("     PID  : {} (stale)", pid.to_string().yellow());
+                        }
+                    } else {
+                        println!("     PID  : {}", "invalid".red());
+                    }
+                }
+                Err(_) => {
+                    println!("     PID  : {}", "unreadable".red());
+                }
+            }
         } else {
-            "unknown".to_string()
+            println!("     PID  : {}", "-".dimmed());
         }
-    };
-    println!("  Client URL: {}", client_url_display.cyan());
-    println!("  Data dir: {}", config.data_dir.cyan());
-    println!("  Config: {}", config_path.display().to_string().cyan());
-    if config.dev_mode {
-        println!("  Mode  : {}", "dev".cyan());
+        println!();
     }
 
-    // Create log file (both modes use the same log so 'sbenv logs' works)
-    let log = fs::File::create(&log_file)?;
-
-    // WORKAROUND: Temporarily rename global config if it exists
-    // because syftbox ignores -c flag and always uses ~/.syftbox/config.json
-    let home_config = dirs::home_dir()
-        .unwrap()
-        .join(".syftbox")
-        .join("config.json");
-    let home_config_backup = dirs::home_dir()
-        .unwrap()
-        .join(".syftbox")
-        .join("config.json.sbenv_backup");
-    let local_config_backup = config_path.with_extension("json.sbenv_local_backup");
-    let mut restored_home_config = false;
+    Ok(())
+}
 
-    if home_config.exists() && home_config != config_path {
-        println!("  Temporarily moving global config aside...");
-        fs::rename(&home_config, &home_config_backup)?;
-        // Backup our local config before copying it to global location
-        fs::copy(&config_path, &local_config_backup)?;
-        // Copy our config to the global location
-        fs::copy(&config_path, &home_config)?;
-        restored_home_config = true;
+fn exec_in_environment(email: &str, command: &[String]) -> Result<()> {
+    if command.is_empty() {
+        return Err(anyhow::anyhow!("No command specified"));
     }
 
-    // Background execution using nohup for both modes; write output to log file
-    let (bin, _) = resolve_binary_for_env(&config_path, false)?;
-    let mut nohup = Command::new("nohup");
-    let child = nohup
-        .arg(bin.to_str().unwrap())
-        .args(&syftbox_args)
-        .env("SYFTBOX_CONFIG", config_path.to_str().unwrap())
-        .env("SYFTBOX_CLIENT_CONFIG_PATH", config_path.to_str().unwrap())
-        // Enable auth bypass only in dev mode
-        .envs(
-            if config.dev_mode {
-                Some(("SYFTBOX_AUTH_ENABLED", "0"))
-            } else {
-                None::<(&str, &str)>
-            }
-            .into_iter(),
-        )
-        .stdin(Stdio::null())
-        .stdout(Stdio::from(log.try_clone()?))
-        .stderr(Stdio::from(log))
-        .spawn()
-        .context("Failed to start syftbox in background. Is 'syftbox' installed?")?;
+    let env_info = resolve_env_selector(email)?;
 
-    let pid = child.id();
+    let env_path = Path::new(&env_info.path);
+    let config_path = env_path.join(".syftbox").join("config.json");
 
-    // Save PID
-    fs::write(&pid_file, pid.to_string())?;
+    if !config_path.exists() {
+        return Err(anyhow::anyhow!(
+            "Environment config not found at {:?}",
+            config_path
+        ));
+    }
 
-    // Wait a moment and check if it's still running
-    thread::sleep(Duration::from_secs(2));
+    // Load the config to get environment variables
+    let config = load_config(&config_path)?;
 
-    let check = Command::new("ps").args(["-p", &pid.to_string()]).output()?;
+    // Build the command - it inherits parent environment by default
+    let mut cmd = Command::new(&command[0]);
+    if command.len() > 1 {
+        cmd.args(&command[1..]);
+    }
 
-    // Restore the original global config if we moved it
-    if restored_home_config {
-        thread::sleep(Duration::from_millis(500)); // Give daemon time to read config
-        fs::remove_file(&home_config).ok();
-        fs::rename(&home_config_backup, &home_config)?;
-        // Restore our local config from backup to preserve dev_mode and other fields
-        if local_config_backup.exists() {
-            fs::copy(&local_config_backup, &config_path)?;
-            fs::remove_file(&local_config_backup)?;
+    // Add/override environment variables for sbenv
+    cmd.env("SYFTBOX_EMAIL", &config.email);
+    cmd.env("SYFTBOX_DATA_DIR", &config.data_dir);
+    cmd.env("SYFTBOX_SERVER_URL", &config.server_url);
+    cmd.env(
+        "SYFTBOX_CONFIG_PATH",
+        config_path.to_string_lossy().as_ref(),
+    );
+    if let Some(url) = &config.client_url {
+        cmd.env("SYFTBOX_CLIENT_URL", url);
+    }
+    cmd.env("SYFTBOX_ENV_NAME", &config.email);
+    cmd.env("SYFTBOX_ENV_ACTIVE", "1");
+    if let Ok((bin, ver)) = resolve_binary_for_env(&config_path, true) {
+        cmd.env("SYFTBOX_BINARY", &bin);
+        if let Some(v) = ver {
+            cmd.env("SYFTBOX_VERSION", v);
         }
-        println!("  Restored global config");
     }
 
-    if check.status.success() {
-        if daemon {
-            println!(
-                "{}",
-                "✅ SyftBox daemon started successfully!".green().bold()
-            );
-        } else {
-            println!("{}", "✅ SyftBox started in background".green().bold());
-        }
-        println!("  PID: {}", pid.to_string().cyan());
-        println!("  Logs: {}", "sbenv logs".yellow());
-        println!("  Status: {}", "sbenv status".yellow());
-        println!("  Stop: {}", "sbenv stop".yellow());
+    // Set the working directory to the environment directory
+    cmd.current_dir(env_path);
 
-        // Try to check HTTP API (if URL is available)
-        if let Some(url) = &config.client_url {
-            thread::sleep(Duration::from_secs(1));
-            let api_check = Command::new("curl")
-                .args([
-                    "-s",
-                    "-o",
-                    "/dev/null",
-                    "-w",
-                    "%{http_code}",
-                    &format!("{}/v1/status", url),
-                ])
-                .output();
-
-            if let Ok(output) = api_check {
-                let status_code = String::from_utf8_lossy(&output.stdout);
-                if status_code == "200" || status_code == "401" {
-                    println!("  API: {} Responding", "✓".green());
-                }
+    // Execute the command and wait for it to complete
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to execute command: {}", command[0]))?;
+
+    // Exit with the same code as the command
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+
+    Ok(())
+}
+
+/// Left over from an activation in the parent shell; they would stop the
+/// subshell from decorating its own prompt or deactivate it on `cd`
+const PARENT_ACTIVATION_STATE: &[&str] = &[
+    "SYFTBOX_OLD_VIRTUAL_ENV",
+    "SYFTBOX_OLD_PS1",
+    "SYFTBOX_OLD_PROMPT_COMMAND",
+    "SYFTBOX_OLD_P9K_CONTENT",
+    "SYFTBOX_OLD_P9K_VISUAL",
+    "SBENV_AUTO_ACTIVE_ROOT",
+];
+
+/// Command starting `program` interactively with `act` applied. Startup files that run
+/// the activation after the user's own rc file are written to `rc_dir`.
+fn env_shell_command(program: &str, act: &Activation, rc_dir: &Path) -> Result<Command> {
+    let mut cmd = Command::new(program);
+    for name in ACTIVATION_VARS.iter().chain(PARENT_ACTIVATION_STATE) {
+        cmd.env_remove(name);
+    }
+    cmd.envs(act.vars.iter().map(|(k, v)| (*k, v.as_str())))
+        .env("VIRTUAL_ENV", &act.data_dir)
+        .env("SBENV_SHELL", "1");
+
+    let Some(shell) = Shell::from_program(program) else {
+        // No way to hook an rc file into sh, dash or ksh; the variables are still set
+        eprintln!(
+            "{}",
+            format!(
+                "⚠️  {} is not a shell sbenv knows; the environment is set but the prompt is unchanged",
+                program
+            )
+            .yellow()
+        );
+        return Ok(cmd);
+    };
+    // The prompt is set up by the activation script, run after the user's own rc file
+    match shell {
+        Shell::Bash => {
+            fs::create_dir_all(rc_dir)?;
+            let rc_file = rc_dir.join("bashrc");
+            fs::write(
+                &rc_file,
+                format!(
+                    "[ -f ~/.bashrc ] && . ~/.bashrc\n{}",
+                    shell.activation_script(act)
+                ),
+            )?;
+            cmd.arg("--rcfile").arg(&rc_file);
+        }
+        Shell::Zsh => {
+            // zsh reads its startup files from $ZDOTDIR; point it at ours, which
+            // hand over to the user's files first
+            fs::create_dir_all(rc_dir)?;
+            let user_dir = env::var("ZDOTDIR")
+                .ok()
+                .or_else(|| env::var("HOME").ok())
+                .unwrap_or_default();
+            fs::write(
+                rc_dir.join(".zshenv"),
+                "[ -f \"$_SBENV_ZDOTDIR/.zshenv\" ] && . \"$_SBENV_ZDOTDIR/.zshenv\"\n",
+            )?;
+            fs::write(
+                rc_dir.join(".zshrc"),
+                format!(
+                    "ZDOTDIR=\"$_SBENV_ZDOTDIR\"\nunset _SBENV_ZDOTDIR\n[ -f \"$ZDOTDIR/.zshrc\" ] && . \"$ZDOTDIR/.zshrc\"\n{}",
+                    shell.activation_script(act)
+                ),
+            )?;
+            cmd.env("ZDOTDIR", rc_dir).env("_SBENV_ZDOTDIR", user_dir);
+        }
+        Shell::Fish => {
+            cmd.arg("--init-command").arg(shell.activation_script(act));
+        }
+        Shell::Nu => {
+            // Without the install-shell wrapper the prompt would not show the env
+            if !get_nu_conf_file().is_ok_and(|f| f.exists()) {
+                cmd.arg("--execute").arg(NU_PROMPT_WRAPPER);
             }
         }
-    } else {
-        fs::remove_file(&pid_file).ok();
-        println!("{}", "❌ Failed to start daemon".red());
-        println!("Check logs at: {}", log_file.display());
-        return Err(anyhow::anyhow!("Daemon failed to start"));
+        Shell::Pwsh => {
+            cmd.arg("-NoExit")
+                .arg("-Command")
+                .arg(shell.activation_script(act));
+        }
     }
+    Ok(cmd)
+}
+
+/// Start `$SHELL` with the environment activated and wait for it to exit
+fn spawn_env_shell(selector: Option<&str>) -> Result<()> {
+    if env::var_os("SBENV_SHELL").is_some() {
+        anyhow::bail!("Already inside an sbenv shell; type `exit` to leave it first");
+    }
+    let config_path = selected_config_path(selector)?;
+    let config = load_config(&config_path)?;
+    let act = Activation::new(&config, &config_path, false);
 
+    let program = env::var("SHELL").unwrap_or_else(|_| String::from("/bin/bash"));
+    let rc_dir = env::temp_dir().join(format!("sbenv-shell-{}", uuid::Uuid::new_v4()));
+    let mut cmd = env_shell_command(&program, &act, &rc_dir)?;
+
+    eprintln!(
+        "📦 Starting {} with {} activated. Type {} to leave.",
+        program,
+        config.email.cyan(),
+        "exit".yellow()
+    );
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to start {}", program));
+    let _ = fs::remove_dir_all(&rc_dir);
+    status?;
     Ok(())
 }
 
-fn stop_daemon() -> Result<()> {
+fn update_environment(server_url: Option<String>, dev: Option<bool>) -> Result<()> {
     let current_dir = env::current_dir().context("Failed to get current directory")?;
-    let config_path = find_syftbox_config(&current_dir)
-        .ok_or_else(|| anyhow::anyhow!("No SyftBox environment found"))?;
+    let config_path = find_syftbox_config(&current_dir).ok_or_else(|| {
+        anyhow::anyhow!("No SyftBox environment found in current directory or parents")
+    })?;
 
-    let _config = load_config(&config_path)?;
-    // Always use the environment directory for PID file
-    let env_dir = config_path.parent().unwrap().parent().unwrap();
-    let pid_file = env_dir.join(".syftbox").join("syftbox.pid");
+    let mut config = load_config(&config_path)?;
 
-    // First, check for and clean up any orphaned processes for this environment
-    cleanup_orphaned_processes(&config_path)?;
+    let mut changed = false;
+    if let Some(url) = server_url {
+        if config.server_url != url {
+            config.server_url = url;
+            changed = true;
+        }
+    }
+    if let Some(dev_mode) = dev {
+        if config.dev_mode != dev_mode {
+            config.dev_mode = dev_mode;
+            changed = true;
+        }
+    }
 
-    if !pid_file.exists() {
-        println!("{}", "No daemon PID file found".yellow());
+    if !changed {
+        println!("No changes specified. Use --server_url or --dev true/false.");
         return Ok(());
     }
 
-    let pid_str = fs::read_to_string(&pid_file)?;
-    let pid = pid_str
-        .trim()
-        .parse::<u32>()
-        .context("Invalid PID in file")?;
-
-    // Check if process is running
-    let check = Command::new("ps").args(["-p", &pid.to_string()]).output()?;
+    // Save updated config
+    let config_json =
+        serde_json::to_string_pretty(&config).context("Failed to serialize config")?;
+    fs::write(&config_path, config_json).context("Failed to write config file")?;
 
-    if !check.status.success() {
-        println!("Daemon not running (cleaning up stale PID)");
-        fs::remove_file(&pid_file)?;
-        return Ok(());
-    }
+    // Update environment registry (path is env dir of config)
+    let env_dir = config_path.parent().unwrap().parent().unwrap();
+    register_environment(env_dir, &config)?;
 
+    println!("{}", "✅ Environment updated".green().bold());
+    println!("  Email : {}", config.email.cyan());
+    println!("  Server: {}", config.server_url.cyan());
     println!(
-        "Stopping SyftBox daemon (PID: {})...",
-        pid.to_string().cyan()
+        "  Dev   : {}",
+        if config.dev_mode { "true" } else { "false" }
     );
 
-    // Send SIGTERM
-    Command::new("kill").arg(pid.to_string()).output()?;
+    Ok(())
+}
+
+// Update check structs
+#[derive(Debug, Deserialize)]
+struct CratesApiResponse {
+    #[serde(rename = "crate")]
+    crate_info: CrateInfo,
+}
+
+#[derive(Debug, Deserialize)]
+struct CrateInfo {
+    max_version: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubRelease {
+    tag_name: String,
+}
+
+enum InstallMethod {
+    Cargo,
+    Binary,
+}
+
+fn get_current_version() -> Version {
+    Version::parse(env!("CARGO_PKG_VERSION")).expect("Invalid current version")
+}
+
+fn detect_install_method() -> Result<InstallMethod> {
+    let exe_path = env::current_exe().context("Failed to get current executable path")?;
+    let exe_path_str = exe_path.to_string_lossy();
+
+    if exe_path_str.contains(".cargo")
+        || exe_path_str.contains("target/release")
+        || exe_path_str.contains("target/debug")
+    {
+        Ok(InstallMethod::Cargo)
+    } else {
+        Ok(InstallMethod::Binary)
+    }
+}
+
+async fn check_crates_io() -> Result<Option<Version>> {
+    let client = reqwest::Client::new();
+    let response = client
+        .get("https://crates.io/api/v1/crates/sbenv")
+        .header("User-Agent", "sbenv-cli")
+        .send()
+        .await
+        .context("Failed to check crates.io")?;
 
-    // Wait for graceful shutdown
-    for i in 0..10 {
-        thread::sleep(Duration::from_secs(1));
-        let check = Command::new("ps").args(["-p", &pid.to_string()]).output()?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
 
-        if !check.status.success() {
-            println!("{}", "✅ SyftBox daemon stopped".green());
-            fs::remove_file(&pid_file)?;
-            return Ok(());
-        }
+    let api_response: CratesApiResponse = response
+        .json()
+        .await
+        .context("Failed to parse crates.io response")?;
 
-        if i == 5 {
-            println!("Daemon still running, sending force kill...");
-            Command::new("kill")
-                .args(["-9", &pid.to_string()])
-                .output()?;
-        }
-    }
+    let latest_version = Version::parse(&api_response.crate_info.max_version)
+        .context("Invalid version from crates.io")?;
 
-    fs::remove_file(&pid_file)?;
-    println!("{}", "✅ SyftBox daemon force stopped".green());
-    Ok(())
+    Ok(Some(latest_version))
 }
 
-fn show_daemon_status() -> Result<()> {
-    let current_dir = env::current_dir().context("Failed to get current directory")?;
-    let config_path = find_syftbox_config(&current_dir)
-        .ok_or_else(|| anyhow::anyhow!("No SyftBox environment found"))?;
+async fn check_github() -> Result<Option<Version>> {
+    let url = "https://api.github.com/repos/openmined/sbenv/releases/latest";
+    let client = reqwest::Client::new();
+    let mut request = client.get(url).header("User-Agent", "sbenv-cli");
+    if let Some(token) = github_token_for(url) {
+        request = request.bearer_auth(token);
+    }
+    let response = request
+        .send()
+        .await
+        .context("Failed to check GitHub releases")?;
 
-    let config = load_config(&config_path)?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
 
-    // Ensure .sbenv marker exists for this environment
-    let _ = ensure_marker_exists(&config_path, &config);
-    // Always use the environment directory for PID file
-    let env_dir = config_path.parent().unwrap().parent().unwrap();
-    let pid_file = env_dir.join(".syftbox").join("syftbox.pid");
+    let release: GithubRelease = response
+        .json()
+        .await
+        .context("Failed to parse GitHub response")?;
 
-    if !pid_file.exists() {
-        println!("{} No daemon found", "✗".red());
-        println!("  Run {} to start", "sbenv start".yellow());
-        return Ok(());
-    }
+    let version_str = release.tag_name.trim_start_matches('v');
+    let latest_version = Version::parse(version_str).context("Invalid version from GitHub")?;
 
-    let pid_str = fs::read_to_string(&pid_file)?;
-    let pid = pid_str
-        .trim()
-        .parse::<u32>()
-        .context("Invalid PID in file")?;
+    Ok(Some(latest_version))
+}
 
-    // Check if process is running
-    let check = Command::new("ps").args(["-p", &pid.to_string()]).output()?;
+async fn check_for_updates() -> Result<Option<Version>> {
+    let current = get_current_version();
 
-    if !check.status.success() {
-        println!("{} Daemon not running (stale PID: {})", "✗".red(), pid);
-        fs::remove_file(&pid_file)?;
-        return Ok(());
-    }
+    let crates_version = check_crates_io().await.ok().flatten();
+    let github_version = check_github().await.ok().flatten();
 
-    println!("{} SyftBox daemon running", "✓".green());
-    println!("  PID: {}", pid.to_string().cyan());
-    println!("  Email: {}", config.email.cyan());
-    // Determine client URL for display (prefer config, fallback to registry)
-    let client_url_display = if let Some(url) = &config.client_url {
-        url.clone()
-    } else {
-        let registry = load_registry().unwrap_or(EnvRegistry {
-            environments: HashMap::new(),
-        });
-        let env_dir = config_path
-            .parent()
-            .unwrap()
-            .parent()
-            .unwrap()
-            .to_string_lossy()
-            .to_string();
-        let port = registry
-            .environments
-            .values()
-            .find(|info| info.path == env_dir)
-            .map(|info| info.port)
-            .unwrap_or(0);
-        if port > 0 {
-            format!("http://127.0.0.1:{}", port)
-        } else {
-            "unknown".to_string()
-        }
+    let latest = match (crates_version, github_version) {
+        (Some(c), Some(g)) => Some(if c > g { c } else { g }),
+        (Some(c), None) => Some(c),
+        (None, Some(g)) => Some(g),
+        (None, None) => None,
     };
-    println!("  Client URL: {}", client_url_display.cyan());
-    println!("  Data dir: {}", config.data_dir.cyan());
 
-    // Check API
-    if let Some(url) = config
-        .client_url
-        .as_ref()
-        .or(Some(&client_url_display))
-        .filter(|u| *u != "unknown")
-    {
-        let api_check = Command::new("curl")
-            .args([
-                "-s",
-                "-o",
-                "/dev/null",
-                "-w",
-                "%{http_code}",
-                &format!("{}/v1/status", url),
-            ])
-            .output();
-
-        if let Ok(output) = api_check {
-            let status_code = String::from_utf8_lossy(&output.stdout);
-            if status_code == "200" || status_code == "401" {
-                println!("  API: {} Responding", "✓".green());
-            } else {
-                println!("  API: {} Not responding (HTTP {})", "✗".red(), status_code);
-            }
-        } else {
-            println!("  API: {} Cannot connect", "✗".red());
+    if let Some(ref version) = latest {
+        if version > &current {
+            return Ok(Some(version.clone()));
         }
-    } else {
-        println!("  API: {} URL not set in config", "–".dimmed());
     }
 
-    Ok(())
+    Ok(None)
 }
 
-fn show_daemon_logs(lines: Option<usize>, follow: bool) -> Result<()> {
-    let current_dir = env::current_dir().context("Failed to get current directory")?;
-    let config_path = find_syftbox_config(&current_dir)
-        .ok_or_else(|| anyhow::anyhow!("No SyftBox environment found"))?;
+async fn update_via_cargo() -> Result<()> {
+    println!("Updating via cargo install...");
 
-    let _config = load_config(&config_path)?;
-    // Always use the environment directory for log file
-    let env_dir = config_path.parent().unwrap().parent().unwrap();
-    let log_file = env_dir.join(".syftbox").join("daemon.log");
+    let output = Command::new("cargo")
+        .args(["install", "sbenv", "--force"])
+        .output()
+        .context("Failed to run cargo install")?;
 
-    if !log_file.exists() {
-        println!("{}", "No log file found".yellow());
-        println!("Start the daemon first with: {}", "sbenv start".cyan());
-        return Ok(());
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("cargo install failed: {}", stderr);
     }
 
-    let mut args = vec![];
-    let lines_str;
-
-    if let Some(n) = lines {
-        lines_str = format!("-{}", n);
-        args.push(lines_str.as_str());
-    } else if follow {
-        args.push("-f");
-    } else {
-        args.push("-50");
-    }
+    Ok(())
+}
 
-    args.push(log_file.to_str().unwrap());
+async fn update_via_self_update(new_version: &Version) -> Result<()> {
+    println!("Updating via direct binary download...");
 
-    let status = Command::new("tail").args(&args).status()?;
+    let status = self_update::backends::github::Update::configure()
+        .repo_owner("OpenMined")
+        .repo_name("sbenv")
+        .bin_name("sbenv")
+        .target_version_tag(&format!("v{}", new_version))
+        .show_download_progress(true)
+        .current_version(env!("CARGO_PKG_VERSION"))
+        .build()
+        .context("Failed to build self-updater")?
+        .update()
+        .context("Failed to perform self-update")?;
 
-    if !status.success() {
-        return Err(anyhow::anyhow!("Failed to read log file"));
-    }
+    if let self_update::Status::Updated(_) = status {}
 
     Ok(())
 }
 
-fn restart_daemon() -> Result<()> {
-    println!("{}", "Restarting SyftBox daemon...".yellow());
+async fn perform_update(new_version: &Version) -> Result<()> {
+    println!("\n{} Updating sbenv...", "🔄".cyan());
 
-    // Stop if running
-    let _ = stop_daemon();
+    let install_method = detect_install_method()?;
 
-    thread::sleep(Duration::from_secs(1));
+    match install_method {
+        InstallMethod::Cargo => update_via_cargo().await?,
+        InstallMethod::Binary => update_via_self_update(new_version).await?,
+    }
 
-    // Start again
-    start_daemon(false, false, true)
+    println!(
+        "\n{} {} {} {}!",
+        "✨".green(),
+        "Successfully updated to version".green().bold(),
+        new_version.to_string().green().bold(),
+        "".green().bold()
+    );
+
+    Ok(())
 }
 
-fn restore_config_after_login(config_path: &Path, original_config: &SyftBoxConfig) -> Result<()> {
-    // Load the config that syftbox login modified
-    let content = fs::read_to_string(config_path)?;
-    let mut modified_config: serde_json::Value = serde_json::from_str(&content)?;
+async fn self_update_sbenv_async(force: bool) -> Result<()> {
+    println!("{}", "Checking for updates...".cyan());
 
-    // Restore original values but keep refresh_token
-    if let Some(obj) = modified_config.as_object_mut() {
-        obj.insert(
-            "data_dir".to_string(),
-            serde_json::Value::String(original_config.data_dir.clone()),
-        );
-        obj.insert(
-            "email".to_string(),
-            serde_json::Value::String(original_config.email.clone()),
-        );
-        obj.insert(
-            "server_url".to_string(),
-            serde_json::Value::String(original_config.server_url.clone()),
-        );
-        if let Some(url) = &original_config.client_url {
-            obj.insert(
-                "client_url".to_string(),
-                serde_json::Value::String(url.clone()),
+    let current = get_current_version();
+    println!("Current version: {}", current.to_string().cyan());
+
+    match check_for_updates().await? {
+        Some(new_version) => {
+            println!(
+                "\n{} {} {}",
+                "✨".green(),
+                "New version available:".green().bold(),
+                new_version.to_string().green().bold()
+            );
+
+            let confirm = if force {
+                true
+            } else {
+                Confirm::new()
+                    .with_prompt(format!("Upgrade from {} to {}?", current, new_version))
+                    .default(true)
+                    .interact()
+                    .context("Failed to get user confirmation")?
+            };
+
+            if confirm {
+                perform_update(&new_version).await?;
+            } else {
+                println!("Update cancelled.");
+            }
+        }
+        None => {
+            println!(
+                "{} {}",
+                "✓".green(),
+                "You're already on the latest version!".green()
             );
-        } else {
-            obj.remove("client_url");
         }
-        // Preserve dev_mode flag
-        obj.insert(
-            "dev_mode".to_string(),
-            serde_json::Value::Bool(original_config.dev_mode),
-        );
-        // Keep the refresh_token from login
     }
 
-    // Write back the fixed config
-    let fixed_json = serde_json::to_string_pretty(&modified_config)?;
-    fs::write(config_path, fixed_json)?;
-
-    println!("  Restored environment config settings");
     Ok(())
 }
 
-fn login_to_syftbox() -> Result<()> {
-    let current_dir = env::current_dir().context("Failed to get current directory")?;
-    let config_path = find_syftbox_config(&current_dir)
-        .ok_or_else(|| anyhow::anyhow!("No SyftBox environment found. Run 'sbenv init' first."))?;
+fn self_update_sbenv(force: bool) -> Result<()> {
+    // Create a runtime for the async operations
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(self_update_sbenv_async(force))
+}
 
-    let original_config = load_config(&config_path)?;
+/// Keep the candidates that start with what has been typed so far
+fn matching_candidates(
+    current: &OsStr,
+    values: impl IntoIterator<Item = (String, String)>,
+) -> Vec<CompletionCandidate> {
+    let typed = current.to_string_lossy();
+    let mut candidates: Vec<(String, String)> = values
+        .into_iter()
+        .filter(|(value, _)| value.starts_with(typed.as_ref()))
+        .collect();
+    candidates.sort();
+    candidates.dedup_by(|a, b| a.0 == b.0);
+    candidates
+        .into_iter()
+        .map(|(value, help)| CompletionCandidate::new(value).help(Some(help.into())))
+        .collect()
+}
 
-    if original_config.dev_mode {
-        println!("{}", "Dev mode environment: skipping login.".yellow());
-        return Ok(());
+/// Registered environments; completion must never fail, so errors give none
+fn registered_environments() -> Vec<EnvInfo> {
+    load_registry()
+        .map(|r| r.environments.into_values().collect())
+        .unwrap_or_default()
+}
+
+/// Emails and directory names of registered environments, with their path as
+/// help, plus their paths once a path is being typed
+fn complete_env_selector(current: &OsStr) -> Vec<CompletionCandidate> {
+    if current
+        .to_string_lossy()
+        .contains(std::path::MAIN_SEPARATOR)
+    {
+        return complete_env_paths(current);
     }
+    matching_candidates(
+        current,
+        registered_environments()
+            .into_iter()
+            .flat_map(|info| [(info.email, info.path.clone()), (info.name, info.path)]),
+    )
+}
 
-    println!("{}", "Logging in to SyftBox...".green());
-    println!("  Email: {}", original_config.email.cyan());
-    println!("  Server: {}", original_config.server_url.cyan());
-    println!("  Config: {}", config_path.display().to_string().cyan());
-    println!();
+/// Paths of registered environments, with their email as help
+fn complete_env_paths(current: &OsStr) -> Vec<CompletionCandidate> {
+    matching_candidates(
+        current,
+        registered_environments()
+            .into_iter()
+            .map(|info| (info.path, info.email)),
+    )
+}
 
-    let (bin, _) = resolve_binary_for_env(&config_path, false)?;
-    let mut cmd = Command::new(bin);
-    let status = cmd
-        .args(["-c", config_path.to_str().unwrap(), "login"])
-        .env("SYFTBOX_CONFIG", config_path.to_str().unwrap())
-        .env("SYFTBOX_CLIENT_CONFIG_PATH", config_path.to_str().unwrap())
-        // Enable auth bypass only in dev mode
-        .envs(
-            if original_config.dev_mode {
-                Some(("SYFTBOX_AUTH_ENABLED", "0"))
-            } else {
-                None::<(&str, &str)>
-            }
-            .into_iter(),
-        )
-        .status()
-        .context("Failed to run syftbox login. Is 'syftbox' installed?")?;
+/// Versions in the binaries cache
+fn complete_cached_versions(current: &OsStr) -> Vec<CompletionCandidate> {
+    matching_candidates(
+        current,
+        get_cached_syftbox_versions()
+            .into_iter()
+            .map(|v| (v, "cached".to_string())),
+    )
+}
 
-    if status.success() {
-        // Restore original config values but keep the new refresh_token
-        restore_config_after_login(&config_path, &original_config)?;
+/// Cached versions and `latest`, the specs a .syftbox-version may hold
+fn complete_pin_spec(current: &OsStr) -> Vec<CompletionCandidate> {
+    let mut candidates = complete_cached_versions(current);
+    candidates.extend(matching_candidates(
+        current,
+        [("latest".to_string(), "newest release".to_string())],
+    ));
+    candidates
+}
 
-        println!();
-        println!("{}", "✅ Login successful!".green().bold());
-        println!(
-            "You can now run {} to start the daemon",
-            "sbenv start".yellow()
-        );
-    } else {
-        return Err(anyhow::anyhow!("Login failed"));
-    }
+/// Cached versions, `latest` and local paths for arguments that take a binary spec
+fn complete_binary_spec(current: &OsStr) -> Vec<CompletionCandidate> {
+    let mut candidates = complete_pin_spec(current);
+    candidates.extend(PathCompleter::any().complete(current));
+    candidates
+}
 
+/// Shells clap_complete can register completions for (no nushell yet)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    Pwsh,
+}
+
+fn print_completions(shell: CompletionShell) -> Result<()> {
+    let completer: &dyn EnvCompleter = match shell {
+        CompletionShell::Bash => &clap_complete::env::Bash,
+        CompletionShell::Zsh => &clap_complete::env::Zsh,
+        CompletionShell::Fish => &clap_complete::env::Fish,
+        CompletionShell::Pwsh => &clap_complete::env::Powershell,
+    };
+    // The script calls back into this binary with COMPLETE=<shell> to get candidates
+    let exe = env::current_exe().context("Failed to locate the sbenv executable")?;
+    let mut out = std::io::stdout();
+    completer
+        .write_registration(
+            "COMPLETE",
+            "sbenv",
+            "sbenv",
+            &exe.to_string_lossy(),
+            &mut out,
+        )
+        .context("Failed to write completion script")?;
     Ok(())
 }
 
-fn list_environments() -> Result<()> {
-    let registry = load_registry()?;
+fn main() -> Result<()> {
+    // Answer completion requests from the script printed by `sbenv completions`
+    CompleteEnv::with_factory(Cli::command).complete();
+    let cli = Cli::parse();
 
-    if registry.environments.is_empty() {
-        println!("{}", "No SyftBox environments registered yet.".yellow());
-        println!("Use {} to create one.", "sbenv init".cyan());
-        return Ok(());
+    // Activation output is eval'd by the shell hooks, so keep those commands silent
+    if !matches!(
+        cli.command,
+        Some(Commands::Activate { .. }) | Some(Commands::Deactivate { .. })
+    ) {
+        if let Err(e) = recover_interrupted_config_swap() {
+            eprintln!("{} {}", "⚠️  Could not restore configs:".yellow(), e);
+        }
     }
 
-    println!("{}", "📦 SyftBox Environments".bold());
-    println!();
-
-    // Collect and sort by email (case-insensitive)
-    let mut envs: Vec<&EnvInfo> = registry.environments.values().collect();
-    envs.sort_by(|a, b| a.email.to_lowercase().cmp(&b.email.to_lowercase()));
-
-    for info in envs {
-        let path = Path::new(&info.path);
-        let exists = path.join(".syftbox").exists();
-        let status = if exists { "✅".green() } else { "❌".red() };
-        let top_name = Path::new(&info.path)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("?");
-        let dev_label = if info.dev_mode { " - DEV" } else { "" };
-
-        // Primary line: status + email [ - DEV ] (top folder name)
-        println!(
-            "  {} {}{} ({})",
-            status,
-            info.email.cyan(),
-            dev_label,
-            top_name
-        );
-        println!("     Path : {}", info.path);
-        println!("     Port : {}", info.port);
-        if !info.server_url.is_empty() {
-            println!("     Server: {}", info.server_url);
+    match &cli.command {
+        Some(Commands::Init {
+            email,
+            server_url,
+            dev,
+            binary,
+            quiet,
+            insecure_skip_verify,
+            make_default,
+            target,
+        }) => {
+            init_environment_with_binary(
+                email.clone(),
+                server_url.clone(),
+                *dev,
+                binary.clone(),
+                *quiet,
+                *insecure_skip_verify,
+                *make_default,
+                target.as_ref(),
+            )?;
         }
-        if let Some(b) = &info.binary {
-            println!("     Bin  : {}", b);
+        Some(Commands::Info) => {
+            show_info()?;
         }
-        if let Some(v) = &info.binary_version {
-            println!("     Ver  : {}", v);
+        Some(Commands::Activate {
+            env,
+            cd,
+            write_to,
+            quiet,
+            shell,
+        }) => {
+            let shell = shell.unwrap_or_else(Shell::detect);
+            if let Some(path) = write_to {
+                activate_environment_to_file(path, shell, env.as_deref(), *cd)?;
+            } else {
+                activate_environment(*quiet, shell, env.as_deref(), *cd)?;
+            }
         }
-        if let Some(h) = &info.binary_hash {
-            println!("     Hash : {}", h);
+        Some(Commands::Deactivate { quiet, shell }) => {
+            deactivate_environment(*quiet, shell.unwrap_or_else(Shell::detect))?;
         }
-        if info.binary_os.is_some() || info.binary_arch.is_some() {
-            println!(
-                "     Target: {}/{}",
-                info.binary_os.as_deref().unwrap_or("?"),
-                info.binary_arch.as_deref().unwrap_or("?")
-            );
+        Some(Commands::Remove { path }) => {
+            remove_environment(path.clone())?;
         }
-
-        // Show process info (last known PID and whether it's active)
-        let pid_file = path.join(".syftbox").join("syftbox.pid");
-        if pid_file.exists() {
-            match fs::read_to_string(&pid_file) {
-                Ok(pid_str) => {
-                    let pid_str = pid_str.trim().to_string();
-                    if let Ok(pid) = pid_str.parse::<u32>() {
-                        let check = Command::new("ps")
-                            .args(["-p", &pid.to_string()])
-                            .stdout(Stdio::null())
-                            .stderr(Stdio::null())
-                            .status();
-                        let running = matches!(check, Ok(s) if s.success());
-                        if running {
-                            println!("     PID  : {} (active)", pid.to_string().cyan());
-                        } else {
-                            println!("     PID  : {} (stale)", pid.to_string().yellow());
-                        }
-                    } else {
-                        println!("     PID  : {}", "invalid".red());
-                    }
+        Some(Commands::Edit {
+            server_url,
+            dev,
+            binary,
+            insecure_skip_verify,
+            make_default,
+        }) => {
+            update_environment(server_url.clone(), *dev)?;
+            if let Some(bin_spec) = binary.clone() {
+                // Update binary for the current env only; the global default changes on request
+                let current_dir = env::current_dir().context("Failed to get current directory")?;
+                let config_path = find_syftbox_config(&current_dir).ok_or_else(|| {
+                    anyhow::anyhow!("No SyftBox environment found in current directory or parents")
+                })?;
+                let config = load_config(&config_path)?;
+                let env_dir = config_path.parent().unwrap().parent().unwrap();
+                let env_key = generate_env_key(env_dir, &config.email);
+                let (p, v) = resolve_or_install_syftbox(&bin_spec, false, *insecure_skip_verify)?;
+                let mut registry = load_registry()?;
+                if let Some(info) = registry.environments.get_mut(&env_key) {
+                    set_env_binary(info, &bin_spec, &p, v.clone());
                 }
-                Err(_) => {
-                    println!("     PID  : {}", "unreadable".red());
+                save_registry(&registry)?;
+                println!(
+                    "{}",
+                    "✅ Updated syftbox binary for this environment".green()
+                );
+                if *make_default {
+                    save_default_binary(&bin_spec, v.as_deref())?;
+                    println!("✅ Also set as the global default for new environments");
                 }
             }
-        } else {
-            println!("     PID  : {}", "-".dimmed());
         }
-        println!();
+        Some(Commands::InstallShell { manual }) => {
+            if *manual && Shell::detect() == Shell::Fish {
+                println!("# Save this as {}:", get_fish_conf_file()?.display());
+                print!("{}", get_fish_shell_functions());
+            } else if *manual && Shell::detect() == Shell::Nu {
+                println!("# Save this as {}:", get_nu_conf_file()?.display());
+                print!("{}", get_nu_shell_functions());
+            } else if *manual && Shell::detect() == Shell::Pwsh {
+                println!("# Add these functions to your PowerShell profile ($PROFILE):");
+                print!("{}", get_pwsh_shell_functions());
+            } else if *manual {
+                println!("# Add these functions to your shell configuration:");
+                println!("# For ZSH: add to ~/.zshrc");
+                println!("# For Bash: add to ~/.bashrc");
+                print!("{}", get_shell_block());
+                println!();
+                println!("After adding these functions, restart your shell or run:");
+                println!("  source ~/.zshrc  # for ZSH");
+                println!("  source ~/.bashrc # for Bash");
+            } else {
+                install_shell_functions()?;
+            }
+        }
+        Some(Commands::UninstallShell { restore_backup }) => {
+            uninstall_shell_functions(*restore_backup)?;
+        }
+        Some(Commands::Start {
+            force,
+            skip_login_check,
+            daemon,
+            strict,
+        }) => {
+            start_daemon(*force, *skip_login_check, *daemon, integrity_check(*strict))?;
+        }
+        Some(Commands::Stop) => {
+            stop_daemon()?;
+        }
+        Some(Commands::Status) => {
+            show_daemon_status()?;
+        }
+        Some(Commands::Restart) => {
+            restart_daemon()?;
+        }
+        Some(Commands::Logs { lines, follow }) => {
+            show_daemon_logs(*lines, *follow)?;
+        }
+        Some(Commands::Login { strict }) => {
+            login_to_syftbox(integrity_check(*strict))?;
+        }
+        Some(Commands::List) => {
+            list_environments()?;
+        }
+        Some(Commands::Update { force }) => {
+            self_update_sbenv(*force)?;
+        }
+        Some(Commands::Exec { email, command }) => {
+            exec_in_environment(email, command)?;
+        }
+        Some(Commands::Shell { env }) => spawn_env_shell(env.as_deref())?,
+        Some(Commands::Completions { shell }) => print_completions(*shell)?,
+        Some(Commands::Upgrade {
+            to,
+            insecure_skip_verify,
+        }) => upgrade_environment(to.as_deref(), *insecure_skip_verify)?,
+        Some(Commands::History) => show_history()?,
+        Some(Commands::Gc { days, dry_run }) => gc_binaries(*days, *dry_run)?,
+        Some(Commands::Pin { spec, unset }) => pin_binary(spec.as_deref(), *unset)?,
+        Some(Commands::Binary { command }) => match command {
+            BinaryCommands::List => binary_list()?,
+            BinaryCommands::Install {
+                version,
+                insecure_skip_verify,
+                target,
+            } => binary_install(version, *insecure_skip_verify, target.as_ref())?,
+            BinaryCommands::Remove { version, force } => binary_remove(version, *force)?,
+            BinaryCommands::Prune { force } => binary_prune(*force)?,
+            BinaryCommands::Outdated => binary_outdated()?,
+            BinaryCommands::Which => binary_which()?,
+            BinaryCommands::Default {
+                spec,
+                insecure_skip_verify,
+            } => default_binary_set(spec, *insecure_skip_verify)?,
+        },
+        Some(Commands::DefaultBinary { command }) => match command {
+            DefaultBinaryCommands::Set {
+                spec,
+                insecure_skip_verify,
+            } => default_binary_set(spec, *insecure_skip_verify)?,
+            DefaultBinaryCommands::Unset => default_binary_unset()?,
+            DefaultBinaryCommands::Show => default_binary_show()?,
+        },
+        None => {
+            if env::var("SYFTBOX_ENV_ACTIVE").is_ok() {
+                show_info()?;
+            } else {
+                println!("{}", "SyftBox Env (sbenv) - virtualenv for SyftBox".bold());
+                println!();
+                println!("No active environment. Commands available:");
+                println!(
+                    "  {} - Initialize a new environment in current directory",
+                    "sbenv init".yellow()
+                );
+                println!(
+                    "  {} - Show current environment info",
+                    "sbenv info".yellow()
+                );
+                println!("  {} - Activate environment", "sbenv activate".yellow());
+                println!("  {} - Deactivate environment", "sbenv deactivate".yellow());
+                println!("  {} - Remove an environment", "sbenv remove".yellow());
+                println!(
+                    "  {} - Install shell functions for easier use",
+                    "sbenv install-shell".yellow()
+                );
+                println!();
+                println!("Daemon commands:");
+                println!("  {} - Start SyftBox daemon", "sbenv start".yellow());
+                println!("  {} - Stop daemon", "sbenv stop".yellow());
+                println!("  {} - Check daemon status", "sbenv status".yellow());
+                println!("  {} - View daemon logs", "sbenv logs".yellow());
+                println!("  {} - Restart daemon", "sbenv restart".yellow());
+                println!("  {} - Login to SyftBox", "sbenv login".yellow());
+                println!();
+                println!("Use {} for more information", "sbenv --help".cyan());
+            }
+        }
     }
 
     Ok(())
 }
 
-fn exec_in_environment(email: &str, command: &[String]) -> Result<()> {
-    if command.is_empty() {
-        return Err(anyhow::anyhow!("No command specified"));
-    }
-
-    // Load registry to find the environment
-    let registry = load_registry()?;
-    let env_info = registry.environments.get(email).ok_or_else(|| {
-        anyhow::anyhow!(
-            "Environment for '{}' not found. Run 'sbenv list' to see available environments.",
-            email
-        )
-    })?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::Mutex;
+    use tempfile::TempDir;
 
-    let env_path = Path::new(&env_info.path);
-    let config_path = env_path.join(".syftbox").join("config.json");
+    // Use a mutex to ensure tests that modify HOME don't run concurrently
+    static HOME_MUTEX: Mutex<()> = Mutex::new(());
 
-    if !config_path.exists() {
-        return Err(anyhow::anyhow!(
-            "Environment config not found at {:?}",
-            config_path
-        ));
+    /// Release-style tar.gz holding a single executable `syftbox`
+    fn write_syftbox_tar_gz(path: &Path, script: &[u8]) {
+        let enc = flate2::write::GzEncoder::new(
+            fs::File::create(path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(enc);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(script.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder.append_data(&mut header, "syftbox", script).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
     }
 
-    // Load the config to get environment variables
-    let config = load_config(&config_path)?;
+    #[test]
+    fn test_find_available_port_with_empty_registry() {
+        let _guard = HOME_MUTEX.lock().unwrap();
+        // Create a temporary directory for testing
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        env::set_var("HOME", temp_dir.path());
 
-    // Build the command - it inherits parent environment by default
-    let mut cmd = Command::new(&command[0]);
-    if command.len() > 1 {
-        cmd.args(&command[1..]);
-    }
+        // Test finding a port when registry is empty
+        let port = find_available_port().unwrap();
+        assert!((7939..=7999).contains(&port));
 
-    // Add/override environment variables for sbenv
-    cmd.env("SYFTBOX_EMAIL", &config.email);
-    cmd.env("SYFTBOX_DATA_DIR", &config.data_dir);
-    cmd.env("SYFTBOX_SERVER_URL", &config.server_url);
-    cmd.env(
-        "SYFTBOX_CONFIG_PATH",
-        config_path.to_string_lossy().as_ref(),
-    );
-    if let Some(url) = &config.client_url {
-        cmd.env("SYFTBOX_CLIENT_URL", url);
+        // Restore original HOME
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
+        }
     }
-    cmd.env("SYFTBOX_ENV_NAME", email);
-    cmd.env("SYFTBOX_ENV_ACTIVE", "1");
 
-    // Set the working directory to the environment directory
-    cmd.current_dir(env_path);
+    #[test]
+    fn test_find_available_port_with_used_ports() {
+        let _guard = HOME_MUTEX.lock().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        env::set_var("HOME", temp_dir.path());
 
-    // Execute the command and wait for it to complete
-    let status = cmd
-        .status()
-        .with_context(|| format!("Failed to execute command: {}", command[0]))?;
+        // Create a registry with some used ports
+        let mut registry = EnvRegistry {
+            environments: HashMap::new(),
+        };
 
-    // Exit with the same code as the command
-    if !status.success() {
-        std::process::exit(status.code().unwrap_or(1));
-    }
+        // Add environments with specific ports
+        for i in 0..5 {
+            let env_info = EnvInfo {
+                path: format!("/test/path{}", i),
+                email: format!("test{}@example.com", i),
+                port: 7940 + i as u16,
+                name: format!("test{}", i),
+                server_url: "https://test.server".to_string(),
+                dev_mode: false,
+                binary: None,
+                binary_version: None,
+                binary_hash: None,
+                binary_os: None,
+                binary_arch: None,
+                release_sha256: None,
+                binary_spec: None,
+                binary_pinned: false,
+                binary_sha256: None,
+            };
+            registry.environments.insert(format!("test{}", i), env_info);
+        }
 
-    Ok(())
-}
+        save_registry(&registry).unwrap();
 
-fn update_environment(server_url: Option<String>, dev: Option<bool>) -> Result<()> {
-    let current_dir = env::current_dir().context("Failed to get current directory")?;
-    let config_path = find_syftbox_config(&current_dir).ok_or_else(|| {
-        anyhow::anyhow!("No SyftBox environment found in current directory or parents")
-    })?;
+        // Find an available port
+        let port = find_available_port().unwrap();
 
-    let mut config = load_config(&config_path)?;
+        // Verify the port is in range and not in the used ports
+        assert!((7939..=7999).contains(&port));
+        let used_ports: Vec<u16> = (7940..7945).collect();
+        assert!(!used_ports.contains(&port));
 
-    let mut changed = false;
-    if let Some(url) = server_url {
-        if config.server_url != url {
-            config.server_url = url;
-            changed = true;
-        }
-    }
-    if let Some(dev_mode) = dev {
-        if config.dev_mode != dev_mode {
-            config.dev_mode = dev_mode;
-            changed = true;
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
         }
     }
 
-    if !changed {
-        println!("No changes specified. Use --server_url or --dev true/false.");
-        return Ok(());
-    }
+    #[test]
+    fn test_register_and_unregister_environment() {
+        let _guard = HOME_MUTEX.lock().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        env::set_var("HOME", temp_dir.path());
 
-    // Save updated config
-    let config_json =
-        serde_json::to_string_pretty(&config).context("Failed to serialize config")?;
-    fs::write(&config_path, config_json).context("Failed to write config file")?;
+        let test_path = temp_dir.path().join("test_env");
+        fs::create_dir(&test_path).unwrap();
 
-    // Update environment registry (path is env dir of config)
-    let env_dir = config_path.parent().unwrap().parent().unwrap();
-    register_environment(env_dir, &config)?;
+        let config = SyftBoxConfig {
+            data_dir: test_path.to_string_lossy().to_string(),
+            email: "test@example.com".to_string(),
+            server_url: "https://test.server".to_string(),
+            client_url: Some("http://127.0.0.1:7950".to_string()),
+            client_token: None,
+            refresh_token: None,
+            dev_mode: false,
+        };
 
-    println!("{}", "✅ Environment updated".green().bold());
-    println!("  Email : {}", config.email.cyan());
-    println!("  Server: {}", config.server_url.cyan());
-    println!(
-        "  Dev   : {}",
-        if config.dev_mode { "true" } else { "false" }
-    );
+        // Register environment
+        register_environment(&test_path, &config).unwrap();
 
-    Ok(())
-}
+        // Verify it was registered using the correct key
+        let registry = load_registry().unwrap();
+        let env_key = generate_env_key(&test_path, "test@example.com");
+        assert!(registry.environments.contains_key(&env_key));
+        let env_info = registry.environments.get(&env_key).unwrap();
+        assert_eq!(env_info.email, "test@example.com");
+        assert_eq!(env_info.port, 7950);
 
-// Update check structs
-#[derive(Debug, Deserialize)]
-struct CratesApiResponse {
-    #[serde(rename = "crate")]
-    crate_info: CrateInfo,
-}
+        // Unregister environment
+        unregister_environment(&test_path).unwrap();
 
-#[derive(Debug, Deserialize)]
-struct CrateInfo {
-    max_version: String,
-}
+        // Verify it was removed
+        let registry = load_registry().unwrap();
+        assert!(!registry.environments.contains_key(&env_key));
 
-#[derive(Debug, Deserialize)]
-struct GithubRelease {
-    tag_name: String,
-}
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
+        }
+    }
 
-enum InstallMethod {
-    Cargo,
-    Binary,
-}
+    #[test]
+    fn test_load_registry_creates_empty_if_not_exists() {
+        let _guard = HOME_MUTEX.lock().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        env::set_var("HOME", temp_dir.path());
+
+        // Load registry when it doesn't exist
+        let registry = load_registry().unwrap();
+        assert!(registry.environments.is_empty());
+
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
+        }
+    }
+
+    #[test]
+    fn test_get_used_ports() {
+        let _guard = HOME_MUTEX.lock().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        env::set_var("HOME", temp_dir.path());
+
+        let mut registry = EnvRegistry {
+            environments: HashMap::new(),
+        };
+
+        // Add test environments
+        registry.environments.insert(
+            "env1".to_string(),
+            EnvInfo {
+                path: "/path1".to_string(),
+                email: "test1@example.com".to_string(),
+                port: 7940,
+                name: "env1".to_string(),
+                server_url: "https://test.server".to_string(),
+                dev_mode: false,
+                binary: None,
+                binary_version: None,
+                binary_hash: None,
+                binary_os: None,
+                binary_arch: None,
+                release_sha256: None,
+                binary_spec: None,
+                binary_pinned: false,
+                binary_sha256: None,
+            },
+        );
+        registry.environments.insert(
+            "env2".to_string(),
+            EnvInfo {
+                path: "/path2".to_string(),
+                email: "test2@example.com".to_string(),
+                port: 7945,
+                name: "env2".to_string(),
+                server_url: "https://test.server".to_string(),
+                dev_mode: false,
+                binary: None,
+                binary_version: None,
+                binary_hash: None,
+                binary_os: None,
+                binary_arch: None,
+                release_sha256: None,
+                binary_spec: None,
+                binary_pinned: false,
+                binary_sha256: None,
+            },
+        );
 
-fn get_current_version() -> Version {
-    Version::parse(env!("CARGO_PKG_VERSION")).expect("Invalid current version")
-}
+        save_registry(&registry).unwrap();
 
-fn detect_install_method() -> Result<InstallMethod> {
-    let exe_path = env::current_exe().context("Failed to get current executable path")?;
-    let exe_path_str = exe_path.to_string_lossy();
+        // Get used ports
+        let used_ports = get_used_ports().unwrap();
+        assert_eq!(used_ports.len(), 2);
+        assert!(used_ports.contains(&7940));
+        assert!(used_ports.contains(&7945));
 
-    if exe_path_str.contains(".cargo")
-        || exe_path_str.contains("target/release")
-        || exe_path_str.contains("target/debug")
-    {
-        Ok(InstallMethod::Cargo)
-    } else {
-        Ok(InstallMethod::Binary)
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
+        }
     }
-}
-
-async fn check_crates_io() -> Result<Option<Version>> {
-    let client = reqwest::Client::new();
-    let response = client
-        .get("https://crates.io/api/v1/crates/sbenv")
-        .header("User-Agent", "sbenv-cli")
-        .send()
-        .await
-        .context("Failed to check crates.io")?;
 
-    if !response.status().is_success() {
-        return Ok(None);
-    }
+    #[test]
+    fn test_parse_port_from_client_url() {
+        let config = SyftBoxConfig {
+            data_dir: "/test".to_string(),
+            email: "test@example.com".to_string(),
+            server_url: "https://test.server".to_string(),
+            client_url: Some("http://127.0.0.1:7950".to_string()),
+            client_token: None,
+            refresh_token: None,
+            dev_mode: false,
+        };
 
-    let api_response: CratesApiResponse = response
-        .json()
-        .await
-        .context("Failed to parse crates.io response")?;
+        let port = config
+            .client_url
+            .as_deref()
+            .and_then(|u| u.rsplit(':').next())
+            .and_then(|p| p.parse::<u16>().ok())
+            .unwrap();
 
-    let latest_version = Version::parse(&api_response.crate_info.max_version)
-        .context("Invalid version from crates.io")?;
+        assert_eq!(port, 7950);
+    }
 
-    Ok(Some(latest_version))
-}
+    #[test]
+    fn test_parse_port_from_localhost_url() {
+        let config = SyftBoxConfig {
+            data_dir: "/test".to_string(),
+            email: "test@example.com".to_string(),
+            server_url: "https://test.server".to_string(),
+            client_url: Some("http://localhost:8080".to_string()),
+            client_token: None,
+            refresh_token: None,
+            dev_mode: false,
+        };
 
-async fn check_github() -> Result<Option<Version>> {
-    let client = reqwest::Client::new();
-    let response = client
-        .get("https://api.github.com/repos/openmined/sbenv/releases/latest")
-        .header("User-Agent", "sbenv-cli")
-        .send()
-        .await
-        .context("Failed to check GitHub releases")?;
+        let port = config
+            .client_url
+            .as_deref()
+            .and_then(|u| u.rsplit(':').next())
+            .and_then(|p| p.parse::<u16>().ok())
+            .unwrap();
 
-    if !response.status().is_success() {
-        return Ok(None);
+        assert_eq!(port, 8080);
     }
 
-    let release: GithubRelease = response
-        .json()
-        .await
-        .context("Failed to parse GitHub response")?;
+    #[test]
+    fn test_isolated_home_does_not_touch_global_config() {
+        let _guard = HOME_MUTEX.lock().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        env::set_var("HOME", temp_dir.path());
 
-    let version_str = release.tag_name.trim_start_matches('v');
-    let latest_version = Version::parse(version_str).context("Invalid version from GitHub")?;
+        let global_dir = temp_dir.path().join(".syftbox");
+        fs::create_dir_all(&global_dir).unwrap();
+        fs::write(global_dir.join("config.json"), "{\"email\":\"me@global\"}").unwrap();
+
+        let env_dir = temp_dir.path().join("env");
+        let config_path = env_dir.join(".syftbox").join("config.json");
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+        fs::write(&config_path, "{\"email\":\"me@env\"}").unwrap();
+
+        let home = prepare_isolated_home(&env_dir, &config_path).unwrap();
+        assert_eq!(home, env_dir.join(".syftbox").join("home"));
+        let isolated_config = home.join(".syftbox").join("config.json");
+        assert_eq!(
+            fs::read_to_string(&isolated_config).unwrap(),
+            "{\"email\":\"me@env\"}"
+        );
 
-    Ok(Some(latest_version))
-}
+        // Simulate syftbox login writing a token into its (isolated) HOME
+        fs::write(
+            &isolated_config,
+            "{\"email\":\"me@env\",\"refresh_token\":\"t\"}",
+        )
+        .unwrap();
+        sync_config_from_isolated_home(&home, &config_path).unwrap();
+        assert!(fs::read_to_string(&config_path)
+            .unwrap()
+            .contains("refresh_token"));
 
-async fn check_for_updates() -> Result<Option<Version>> {
-    let current = get_current_version();
+        // A token the daemon refreshed later survives the next start
+        let later = std::time::SystemTime::now() + Duration::from_secs(60);
+        fs::write(
+            &isolated_config,
+            "{\"email\":\"me@env\",\"refresh_token\":\"t2\"}",
+        )
+        .unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&isolated_config)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        prepare_isolated_home(&env_dir, &config_path).unwrap();
+        assert!(fs::read_to_string(&config_path).unwrap().contains("\"t2\""));
+        assert!(fs::read_to_string(&isolated_config)
+            .unwrap()
+            .contains("\"t2\""));
 
-    let crates_version = check_crates_io().await.ok().flatten();
-    let github_version = check_github().await.ok().flatten();
+        // Edits to the env config made after that are copied in again
+        fs::write(&config_path, "{\"email\":\"me@edited\"}").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&config_path)
+            .unwrap()
+            .set_modified(later + Duration::from_secs(60))
+            .unwrap();
+        prepare_isolated_home(&env_dir, &config_path).unwrap();
+        assert!(fs::read_to_string(&isolated_config)
+            .unwrap()
+            .contains("me@edited"));
 
-    let latest = match (crates_version, github_version) {
-        (Some(c), Some(g)) => Some(if c > g { c } else { g }),
-        (Some(c), None) => Some(c),
-        (None, Some(g)) => Some(g),
-        (None, None) => None,
-    };
+        assert_eq!(
+            fs::read_to_string(global_dir.join("config.json")).unwrap(),
+            "{\"email\":\"me@global\"}"
+        );
 
-    if let Some(ref version) = latest {
-        if version > &current {
-            return Ok(Some(version.clone()));
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
         }
     }
 
-    Ok(None)
-}
-
-async fn update_via_cargo() -> Result<()> {
-    println!("Updating via cargo install...");
-
-    let output = Command::new("cargo")
-        .args(["install", "sbenv", "--force"])
-        .output()
-        .context("Failed to run cargo install")?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("cargo install failed: {}", stderr);
-    }
+    #[test]
+    fn test_restore_interrupted_config_swap() {
+        let _guard = HOME_MUTEX.lock().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        env::set_var("HOME", temp_dir.path());
 
-    Ok(())
-}
+        let env_dir = temp_dir.path().join("env");
+        let config_path = env_dir.join(".syftbox").join("config.json");
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+        fs::write(&config_path, "{\"email\":\"changed\"}").unwrap();
+        fs::write(
+            config_path.with_extension("json.sbenv_local_backup"),
+            "{\"email\":\"env\"}",
+        )
+        .unwrap();
+        let config = SyftBoxConfig {
+            data_dir: env_dir.to_string_lossy().to_string(),
+            email: "env@example.com".to_string(),
+            server_url: "https://test.server".to_string(),
+            client_url: None,
+            client_token: None,
+            refresh_token: None,
+            dev_mode: false,
+        };
+        register_environment(&env_dir, &config).unwrap();
+
+        // Global config replaced by the env copy, real one moved aside
+        let home_config = get_global_syftbox_config_path();
+        fs::create_dir_all(home_config.parent().unwrap()).unwrap();
+        fs::write(&home_config, "{\"email\":\"env\"}").unwrap();
+        fs::write(
+            home_config.with_extension("json.sbenv_backup"),
+            "{\"email\":\"global\"}",
+        )
+        .unwrap();
 
-async fn update_via_self_update(new_version: &Version) -> Result<()> {
-    println!("Updating via direct binary download...");
+        let artifacts = find_interrupted_config_swap();
+        assert!(artifacts.home_backup.is_some());
+        assert_eq!(artifacts.local_backups.len(), 1);
 
-    let status = self_update::backends::github::Update::configure()
-        .repo_owner("OpenMined")
-        .repo_name("sbenv")
-        .bin_name("sbenv")
-        .target_version_tag(&format!("v{}", new_version))
-        .show_download_progress(true)
-        .current_version(env!("CARGO_PKG_VERSION"))
-        .build()
-        .context("Failed to build self-updater")?
-        .update()
-        .context("Failed to perform self-update")?;
+        restore_interrupted_config_swap(&artifacts).unwrap();
+        assert_eq!(
+            fs::read_to_string(&home_config).unwrap(),
+            "{\"email\":\"global\"}"
+        );
+        assert_eq!(
+            fs::read_to_string(&config_path).unwrap(),
+            "{\"email\":\"env\"}"
+        );
+        assert!(find_interrupted_config_swap().is_empty());
 
-    if let self_update::Status::Updated(_) = status {}
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
+        }
+    }
 
-    Ok(())
-}
+    #[test]
+    fn test_cached_version_references() {
+        let _guard = HOME_MUTEX.lock().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        env::set_var("HOME", temp_dir.path());
 
-async fn perform_update(new_version: &Version) -> Result<()> {
-    println!("\n{} Updating sbenv...", "🔄".cyan());
+        let cached = get_binaries_dir().join("0.8.5").join("syftbox");
+        let mut registry = EnvRegistry {
+            environments: HashMap::new(),
+        };
+        for (key, binary, version) in [
+            ("a", Some(cached.to_string_lossy().to_string()), None),
+            ("b", None, Some("0.8.6".to_string())),
+            ("c", Some("/usr/bin/syftbox".to_string()), None),
+        ] {
+            registry.environments.insert(
+                key.to_string(),
+                EnvInfo {
+                    path: format!("/path/{}", key),
+                    email: format!("{}@example.com", key),
+                    port: 7940,
+                    name: key.to_string(),
+                    server_url: "https://test.server".to_string(),
+                    dev_mode: false,
+                    binary,
+                    binary_version: version,
+                    binary_hash: None,
+                    binary_os: None,
+                    binary_arch: None,
+                    release_sha256: None,
+                    binary_spec: None,
+                    binary_pinned: false,
+                    binary_sha256: None,
+                },
+            );
+        }
 
-    let install_method = detect_install_method()?;
+        let refs = cached_version_references(&registry);
+        assert_eq!(refs.len(), 2);
+        assert_eq!(refs["0.8.5"], vec!["a@example.com (/path/a)".to_string()]);
+        assert!(refs.contains_key("0.8.6"));
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MB");
 
-    match install_method {
-        InstallMethod::Cargo => update_via_cargo().await?,
-        InstallMethod::Binary => update_via_self_update(new_version).await?,
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
+        }
     }
 
-    println!(
-        "\n{} {} {} {}!",
-        "✨".green(),
-        "Successfully updated to version".green().bold(),
-        new_version.to_string().green().bold(),
-        "".green().bold()
-    );
+    #[test]
+    fn test_binary_remove_stays_in_cache() {
+        let _guard = HOME_MUTEX.lock().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        env::set_var("HOME", temp_dir.path());
 
-    Ok(())
-}
+        // Looks like a cache entry, but lives outside the cache
+        let outside = temp_dir.path().join("outside");
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("syftbox"), "").unwrap();
+        fs::create_dir_all(get_binaries_dir()).unwrap();
+        for name in [
+            outside.to_string_lossy().to_string(),
+            "../../outside".to_string(),
+            "..".to_string(),
+            "".to_string(),
+        ] {
+            assert!(binary_remove(&name, true).is_err(), "{:?} accepted", name);
+        }
+        assert!(outside.join("syftbox").exists());
+
+        // A version named in a .syftbox-version pin is in use
+        let cached = get_binaries_dir().join("0.8.5");
+        fs::create_dir_all(&cached).unwrap();
+        fs::write(cached.join("syftbox"), "").unwrap();
+        let project = temp_dir.path().join("project");
+        fs::create_dir_all(&project).unwrap();
+        fs::write(project.join(PIN_FILE), "0.8.5\n").unwrap();
+        let config = SyftBoxConfig {
+            data_dir: project.to_string_lossy().to_string(),
+            email: "me@example.com".to_string(),
+            server_url: "https://test.server".to_string(),
+            client_url: None,
+            client_token: None,
+            refresh_token: None,
+            dev_mode: false,
+        };
+        register_environment(&project, &config).unwrap();
+        assert!(binary_remove("0.8.5", false).is_err());
+        assert!(cached.join("syftbox").exists());
+        binary_remove("0.8.5", true).unwrap();
+        assert!(!cached.exists());
 
-async fn self_update_sbenv_async(force: bool) -> Result<()> {
-    println!("{}", "Checking for updates...".cyan());
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
+        }
+    }
 
-    let current = get_current_version();
-    println!("Current version: {}", current.to_string().cyan());
+    #[test]
+    fn test_verify_download_against_checksums() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("asset.tar.gz");
+        fs::write(&file, b"hello").unwrap();
+        let digest = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        assert_eq!(sha256_file(&file).unwrap(), digest);
+
+        let sums = parse_checksums(&format!(
+            "{}  syftbox_0.8.5_linux_amd64.tar.gz\nnot a checksum line\n{} *other.zip\n",
+            digest,
+            "0".repeat(64)
+        ));
+        assert_eq!(sums.len(), 2);
+        assert_eq!(sums["other.zip"], "0".repeat(64));
+
+        let verified = verify_download(
+            &file,
+            "syftbox_0.8.5_linux_amd64.tar.gz",
+            Some(&sums),
+            false,
+        )
+        .unwrap();
+        assert_eq!(verified.as_deref(), Some(digest));
+        assert!(verify_download(&file, "other.zip", Some(&sums), false).is_err());
+        assert!(verify_download(&file, "missing.tar.gz", Some(&sums), false).is_err());
+        assert!(verify_download(&file, "asset.tar.gz", None, false).is_err());
+        assert_eq!(
+            verify_download(&file, "asset.tar.gz", None, true).unwrap(),
+            None
+        );
 
-    match check_for_updates().await? {
-        Some(new_version) => {
-            println!(
-                "\n{} {} {}",
-                "✨".green(),
-                "New version available:".green().bold(),
-                new_version.to_string().green().bold()
+        // Only a missing checksums file means "no checksums"; other failures are errors
+        let url = |p: &Path| format!("file://{}", p.display());
+        fs::write(
+            temp_dir.path().join("checksums.txt"),
+            format!("{}  asset.tar.gz\n", digest),
+        )
+        .unwrap();
+        let fetched = fetch_checksums(&url(&temp_dir.path().join("checksums.txt"))).unwrap();
+        assert_eq!(fetched.unwrap()["asset.tar.gz"], digest);
+        assert!(fetch_checksums(&url(&temp_dir.path().join("missing.txt")))
+            .unwrap()
+            .is_none());
+        assert!(fetch_checksums(&url(temp_dir.path())).is_err());
+    }
+
+    #[test]
+    fn test_install_syftbox_from_archives() {
+        use std::io::Write;
+        let temp_dir = TempDir::new().unwrap();
+        let payload = b"#!/bin/sh\necho syftbox\n";
+
+        // tar.gz with the binary in a nested directory
+        let tgz = temp_dir.path().join("asset.tar.gz");
+        {
+            let enc = flate2::write::GzEncoder::new(
+                fs::File::create(&tgz).unwrap(),
+                flate2::Compression::default(),
             );
+            let mut builder = tar::Builder::new(enc);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(payload.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(
+                    &mut header,
+                    "syftbox_0.8.5_linux_amd64/syftbox",
+                    &payload[..],
+                )
+                .unwrap();
+            builder.into_inner().unwrap().finish().unwrap();
+        }
+        let tmp = temp_dir.path().join("tmp_tgz");
+        fs::create_dir_all(&tmp).unwrap();
+        let bin = temp_dir.path().join("from_tgz");
+        install_syftbox_from_download(&tgz, "syftbox_0.8.5_linux_amd64.tar.gz", &tmp, &bin)
+            .unwrap();
+        assert_eq!(fs::read(&bin).unwrap(), payload);
+
+        // zip
+        let zip_path = temp_dir.path().join("asset.zip");
+        {
+            let mut zw = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+            zw.start_file("syftbox", zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zw.write_all(payload).unwrap();
+            zw.finish().unwrap();
+        }
+        let tmp = temp_dir.path().join("tmp_zip");
+        fs::create_dir_all(&tmp).unwrap();
+        let bin = temp_dir.path().join("from_zip");
+        install_syftbox_from_download(&zip_path, "syftbox_0.8.5_darwin_arm64.zip", &tmp, &bin)
+            .unwrap();
+        assert_eq!(fs::read(&bin).unwrap(), payload);
 
-            let confirm = if force {
-                true
-            } else {
-                Confirm::new()
-                    .with_prompt(format!("Upgrade from {} to {}?", current, new_version))
-                    .default(true)
-                    .interact()
-                    .context("Failed to get user confirmation")?
-            };
+        // archive without a syftbox binary
+        let empty = temp_dir.path().join("empty.zip");
+        zip::ZipWriter::new(fs::File::create(&empty).unwrap())
+            .finish()
+            .unwrap();
+        let tmp = temp_dir.path().join("tmp_empty");
+        fs::create_dir_all(&tmp).unwrap();
+        let err =
+            install_syftbox_from_download(&empty, "empty.zip", &tmp, &temp_dir.path().join("none"))
+                .unwrap_err();
+        assert!(err.to_string().contains("not found"));
+    }
 
-            if confirm {
-                perform_update(&new_version).await?;
-            } else {
-                println!("Update cancelled.");
+    #[test]
+    fn test_release_source_parse_and_local_dir() {
+        assert_eq!(
+            ReleaseSource::parse("github").unwrap(),
+            ReleaseSource::GitHub {
+                api: DEFAULT_RELEASE_API.to_string()
             }
+        );
+        assert_eq!(
+            ReleaseSource::parse("github:https://ghe.example.com/api/v3/repos/o/syftbox/").unwrap(),
+            ReleaseSource::GitHub {
+                api: "https://ghe.example.com/api/v3/repos/o/syftbox".to_string()
+            }
+        );
+        assert_eq!(
+            ReleaseSource::parse("http://mirror.lab/syftbox/").unwrap(),
+            ReleaseSource::Mirror {
+                base: "http://mirror.lab/syftbox".to_string()
+            }
+        );
+        assert!(ReleaseSource::parse("ftp://nope").is_err());
+
+        let temp_dir = TempDir::new().unwrap();
+        for ver in ["v0.8.5", "0.9.0", "not-a-version"] {
+            let dir = temp_dir.path().join(ver);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("checksums.txt"), "").unwrap();
         }
-        None => {
-            println!(
-                "{} {}",
-                "✓".green(),
-                "You're already on the latest version!".green()
-            );
-        }
+        let source =
+            ReleaseSource::parse(&format!("file://{}", temp_dir.path().display())).unwrap();
+        assert_eq!(source.latest_version().unwrap(), "0.9.0");
+        let assets = source.release_assets("0.8.5").unwrap().unwrap();
+        assert_eq!(assets.len(), 1);
+        assert_eq!(assets[0].1, "checksums.txt");
+        assert!(assets[0].0.starts_with("file://"));
+        assert!(source.release_assets("1.0.0").unwrap().is_none());
+
+        // An index takes precedence over directory scanning
+        fs::write(
+            temp_dir.path().join("index.json"),
+            r#"{"latest":"0.8.5","releases":[{"version":"v0.8.5","assets":["a.tar.gz",{"name":"b.zip","url":"https://cdn/b.zip"}]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(source.latest_version().unwrap(), "0.8.5");
+        let assets = source.release_assets("0.8.5").unwrap().unwrap();
+        assert_eq!(
+            assets[0].0,
+            format!("file://{}/v0.8.5/a.tar.gz", temp_dir.path().display())
+        );
+        assert_eq!(assets[1].0, "https://cdn/b.zip");
     }
 
-    Ok(())
-}
+    #[cfg(unix)]
+    #[test]
+    fn test_install_syftbox_from_archive_spec() {
+        let _guard = HOME_MUTEX.lock().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        env::set_var("HOME", temp_dir.path());
 
-fn self_update_sbenv(force: bool) -> Result<()> {
-    // Create a runtime for the async operations
-    let runtime = tokio::runtime::Runtime::new()?;
-    runtime.block_on(self_update_sbenv_async(force))
-}
+        let script =
+            b"#!/bin/sh\necho 'syftbox version 0.9.0-rc.1 (abc1234; go1.24.3; linux/amd64; now)'\n";
+        let archive = temp_dir
+            .path()
+            .join("syftbox_0.9.0-rc.1_linux_amd64.tar.gz");
+        write_syftbox_tar_gz(&archive, script);
+        let spec = archive.to_string_lossy().to_string();
+        assert!(is_archive_spec(&spec));
+        assert!(is_archive_spec("https://example.com/a/syftbox.tgz?token=1"));
+        assert!(!is_archive_spec("/does/not/exist.tar.gz"));
+        assert!(!is_archive_spec("0.8.5"));
+
+        let (bin, ver) = install_syftbox_from_archive_spec(&spec, true, false).unwrap();
+        assert_eq!(ver.as_deref(), Some("0.9.0-rc.1"));
+        assert_eq!(bin, get_binaries_dir().join("0.9.0-rc.1").join("syftbox"));
+        assert_eq!(
+            get_cached_syftbox_versions(),
+            vec!["0.9.0-rc.1".to_string()]
+        );
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+        // Installing the same archive again reuses the cached binary
+        let (again, _) = install_syftbox_from_archive_spec(&spec, true, false).unwrap();
+        assert_eq!(again, bin);
+
+        // URL archives must come with a checksum
+        let mirror = temp_dir.path().join("mirror");
+        fs::create_dir_all(&mirror).unwrap();
+        let remote_script =
+            b"#!/bin/sh\necho 'syftbox version 0.9.1 (abc1234; go1.24.3; linux/amd64; now)'\n";
+        let remote = mirror.join("syftbox_0.9.1_linux_amd64.tar.gz");
+        write_syftbox_tar_gz(&remote, remote_script);
+        let url = format!("file://{}", remote.display());
+        let err = install_syftbox_from_archive_spec(&url, true, false).unwrap_err();
+        assert!(err.to_string().contains("No checksum found"), "{}", err);
+        let wrong = format!("{}#sha256={}", url, "0".repeat(64));
+        let err = install_syftbox_from_archive_spec(&wrong, true, false).unwrap_err();
+        assert!(err.to_string().contains("Checksum mismatch"), "{}", err);
+        assert!(!get_binaries_dir().join("0.9.1").exists());
+
+        let digest = sha256_file(&remote).unwrap();
+        let pinned = format!("{}#sha256={}", url, digest);
+        let (remote_bin, ver) = install_syftbox_from_archive_spec(&pinned, true, false).unwrap();
+        assert_eq!(ver.as_deref(), Some("0.9.1"));
+        assert_eq!(read_verified_digest(&remote_bin), Some(digest));
 
-    match &cli.command {
-        Some(Commands::Init {
-            email,
-            server_url,
-            dev,
-            binary,
-            quiet,
-        }) => {
-            init_environment_with_binary(
-                email.clone(),
-                server_url.clone(),
-                *dev,
-                binary.clone(),
-                *quiet,
-            )?;
-        }
-        Some(Commands::Info) => {
-            show_info()?;
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
         }
-        Some(Commands::Activate { write_to, quiet }) => {
-            if let Some(path) = write_to {
-                activate_environment_to_file(path)?;
-            } else {
-                activate_environment(*quiet)?;
+    }
+
+    #[test]
+    fn test_api_responding() {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            // Replies first, so zip stops without waiting for a third connection
+            let replies = [Some("401 Unauthorized"), None];
+            for (reply, stream) in replies.into_iter().zip(listener.incoming()) {
+                let mut stream = stream.unwrap();
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf);
+                match reply {
+                    Some(status) => {
+                        let head = format!(
+                            "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                            status
+                        );
+                        stream.write_all(head.as_bytes()).unwrap();
+                    }
+                    // A hung daemon must not stall the caller
+                    None => thread::sleep(API_PROBE_TIMEOUT + Duration::from_secs(1)),
+                }
             }
-        }
-        Some(Commands::Deactivate { quiet }) => {
-            deactivate_environment(*quiet)?;
-        }
-        Some(Commands::Remove { path }) => {
-            remove_environment(path.clone())?;
-        }
-        Some(Commands::Edit {
-            server_url,
-            dev,
-            binary,
-        }) => {
-            update_environment(server_url.clone(), *dev)?;
-            if let Some(bin_spec) = binary.clone() {
-                // Update binary for current env and save to registry; also update global default
-                let current_dir = env::current_dir().context("Failed to get current directory")?;
-                let config_path = find_syftbox_config(&current_dir).ok_or_else(|| {
-                    anyhow::anyhow!("No SyftBox environment found in current directory or parents")
-                })?;
-                let config = load_config(&config_path)?;
-                let env_dir = config_path.parent().unwrap().parent().unwrap();
-                let env_key = generate_env_key(env_dir, &config.email);
-                let (p, v) = resolve_or_install_syftbox(&bin_spec, false)?;
-                let mut registry = load_registry()?;
-                if let Some(info) = registry.environments.get_mut(&env_key) {
-                    info.binary = Some(p.to_string_lossy().to_string());
-                    info.binary_version = if is_semver_spec(&bin_spec) {
-                        v.clone()
-                    } else {
-                        None
-                    };
+        });
+        assert!(api_responding(&url));
+        let started = std::time::Instant::now();
+        assert!(!api_responding(&url));
+        assert!(started.elapsed() < API_PROBE_TIMEOUT + Duration::from_secs(1));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_download_resumes_after_dropped_connection() {
+        use std::io::{Read, Write};
+        let _guard = HOME_MUTEX.lock().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        env::set_var("HOME", temp_dir.path());
+
+        // The first response drops after 50k bytes; later ones honour Range while the
+        // If-Range validator still matches the file's ETag
+        let body: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let served = body.clone();
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for stream in listener.incoming().take(3) {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                let mut byte = [0u8; 1];
+                while !request.ends_with(b"\r\n\r\n") {
+                    stream.read_exact(&mut byte).unwrap();
+                    request.push(byte[0]);
                 }
-                save_registry(&registry)?;
-                let mut gc = load_global_config();
-                gc.default_binary = Some(bin_spec);
-                let _ = save_global_config(&gc);
-                println!(
-                    "{}",
-                    "✅ Updated syftbox binary for this environment".green()
-                );
+                let request = String::from_utf8_lossy(&request).to_lowercase();
+                let range: Option<usize> = request
+                    .lines()
+                    .find_map(|l| l.strip_prefix("range: bytes="))
+                    .and_then(|r| r.trim_end_matches('-').parse().ok());
+                let if_range = request
+                    .lines()
+                    .find_map(|l| l.strip_prefix("if-range: "))
+                    .map(|v| v.to_string());
+                match range.filter(|_| if_range.as_deref() == Some("\"v1\"")) {
+                    Some(start) => {
+                        let head = format!(
+                            "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\nConnection: close\r\n\r\n",
+                            served.len() - start,
+                            start,
+                            served.len() - 1,
+                            served.len()
+                        );
+                        stream.write_all(head.as_bytes()).unwrap();
+                        stream.write_all(&served[start..]).unwrap();
+                    }
+                    None => {
+                        let head = format!(
+                            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            served.len()
+                        );
+                        stream.write_all(head.as_bytes()).unwrap();
+                        let len = if requests.is_empty() {
+                            50_000
+                        } else {
+                            served.len()
+                        };
+                        stream.write_all(&served[..len]).unwrap();
+                    }
+                }
+                requests.push((range, if_range));
             }
+            requests
+        });
+
+        let url = format!("http://{}/v0.8.5/syftbox_0.8.5_linux_amd64.tar.gz", addr);
+        let dest = temp_dir.path().join("download.bin");
+        download_to_file(&url, &dest).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), body);
+        assert!(!partial_download_path(&url).exists());
+        assert!(!part_validator_path(&partial_download_path(&url)).exists());
+
+        // A partial file left from an older upload of the asset is replaced, not extended
+        let url2 = format!("http://{}/v0.8.6/syftbox_0.8.6_linux_amd64.tar.gz", addr);
+        let part = partial_download_path(&url2);
+        fs::write(&part, vec![0u8; 10_000]).unwrap();
+        fs::write(part_validator_path(&part), "\"v0\"").unwrap();
+        download_to_file(&url2, &dest).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), body);
+
+        let v1 = Some("\"v1\"".to_string());
+        let v0 = Some("\"v0\"".to_string());
+        assert_eq!(
+            server.join().unwrap(),
+            vec![(None, None), (Some(50_000), v1), (Some(10_000), v0)]
+        );
+
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
+        } else {
+            env::remove_var("HOME");
         }
-        Some(Commands::InstallShell { manual }) => {
-            if *manual {
-                println!("# Add these functions to your shell configuration:");
-                println!("# For ZSH: add to ~/.zshrc");
-                println!("# For Bash: add to ~/.bashrc");
-                print!("{}", get_shell_functions());
-                println!();
-                print!("{}", get_auto_activation_block());
-                println!();
-                println!("After adding these functions, restart your shell or run:");
-                println!("  source ~/.zshrc  # for ZSH");
-                println!("  source ~/.bashrc # for Bash");
-            } else {
-                install_shell_functions()?;
+    }
+
+    #[test]
+    fn test_github_api_cache_and_rate_limit_fallback() {
+        use std::io::{Read, Write};
+        let _guard = HOME_MUTEX.lock().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        let original_token = env::var("GITHUB_TOKEN").ok();
+        let original_hosts = env::var("SBENV_GITHUB_API_HOSTS").ok();
+        env::remove_var("SBENV_GITHUB_API_HOSTS");
+        env::set_var("HOME", temp_dir.path());
+        env::set_var("GITHUB_TOKEN", "test-token");
+
+        // Every request is refused with GitHub's rate limit response
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut auth = Vec::new();
+            for stream in listener.incoming().take(3) {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                let mut byte = [0u8; 1];
+                while !request.ends_with(b"\r\n\r\n") {
+                    stream.read_exact(&mut byte).unwrap();
+                    request.push(byte[0]);
+                }
+                let request = String::from_utf8_lossy(&request).to_lowercase();
+                auth.push(
+                    request
+                        .lines()
+                        .find_map(|l| l.strip_prefix("authorization: "))
+                        .map(|a| a.to_string()),
+                );
+                let body = r#"{"message":"API rate limit exceeded"}"#;
+                let head = format!(
+                    "HTTP/1.1 403 Forbidden\r\nx-ratelimit-remaining: 0\r\nx-ratelimit-reset: 1700000000\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                stream.write_all(head.as_bytes()).unwrap();
+                stream.write_all(body.as_bytes()).unwrap();
             }
+            auth
+        });
+
+        let api = format!("http://{}/repos/OpenMined/syftbox", addr);
+        let latest = format!("{}/releases/latest", api);
+        let tag = format!("{}/releases/tags/v0.8.5", api);
+
+        // A stale entry is refreshed, and used anyway when GitHub refuses
+        write_cached_response(&latest, r#"{"tag_name":"v0.8.4"}"#).unwrap();
+        let path = cached_response_path(&latest);
+        let mut entry: CachedResponse =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        entry.fetched_at -= 2 * DEFAULT_RELEASE_CACHE_TTL as i64;
+        fs::write(&path, serde_json::to_string(&entry).unwrap()).unwrap();
+        let source = ReleaseSource::GitHub { api: api.clone() };
+        assert_eq!(source.latest_version().unwrap(), "0.8.4");
+
+        // Without a cached copy the rate limit is reported, not parsed as a release
+        // or mistaken for a release without assets. The token only goes to opted-in hosts.
+        env::set_var(
+            "SBENV_GITHUB_API_HOSTS",
+            format!("ghe.example.com, {}", addr),
+        );
+        assert!(matches!(
+            github_api_json(&tag),
+            Err(DownloadError::RateLimited {
+                reset: Some(1_700_000_000),
+                ..
+            })
+        ));
+        let err = source.release_assets("0.8.6").unwrap_err();
+        assert!(format!("{:#}", err).contains("rate limit"), "{:#}", err);
+        let token = Some("bearer test-token".to_string());
+        assert_eq!(server.join().unwrap(), vec![None, token.clone(), token]);
+        assert_eq!(github_token_for("http://api.github.com/repos/a/b"), None);
+        assert!(github_token_for("https://api.github.com/repos/a/b").is_some());
+        assert!(github_token_for("https://ghe.example.com/api/v3").is_some());
+        assert_eq!(github_token_for("https://evil.example.com/api/v3"), None);
+
+        // Fresh entries are answered without asking GitHub at all
+        write_cached_response(&tag, r#"{"assets":[]}"#).unwrap();
+        assert_eq!(source.release_assets("0.8.5").unwrap(), Some(Vec::new()));
+
+        match original_token {
+            Some(token) => env::set_var("GITHUB_TOKEN", token),
+            None => env::remove_var("GITHUB_TOKEN"),
         }
-        Some(Commands::Start {
-            force,
-            skip_login_check,
-            daemon,
-        }) => {
-            start_daemon(*force, *skip_login_check, *daemon)?;
-        }
-        Some(Commands::Stop) => {
-            stop_daemon()?;
-        }
-        Some(Commands::Status) => {
-            show_daemon_status()?;
-        }
-        Some(Commands::Restart) => {
-            restart_daemon()?;
-        }
-        Some(Commands::Logs { lines, follow }) => {
-            show_daemon_logs(*lines, *follow)?;
-        }
-        Some(Commands::Login) => {
-            login_to_syftbox()?;
-        }
-        Some(Commands::List) => {
-            list_environments()?;
-        }
-        Some(Commands::Update { force }) => {
-            self_update_sbenv(*force)?;
+        match original_hosts {
+            Some(hosts) => env::set_var("SBENV_GITHUB_API_HOSTS", hosts),
+            None => env::remove_var("SBENV_GITHUB_API_HOSTS"),
         }
-        Some(Commands::Exec { email, command }) => {
-            exec_in_environment(email, command)?;
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
+        } else {
+            env::remove_var("HOME");
         }
-        None => {
-            if env::var("SYFTBOX_ENV_ACTIVE").is_ok() {
-                show_info()?;
-            } else {
-                println!("{}", "SyftBox Env (sbenv) - virtualenv for SyftBox".bold());
-                println!();
-                println!("No active environment. Commands available:");
-                println!(
-                    "  {} - Initialize a new environment in current directory",
-                    "sbenv init".yellow()
-                );
-                println!(
-                    "  {} - Show current environment info",
-                    "sbenv info".yellow()
-                );
-                println!("  {} - Activate environment", "sbenv activate".yellow());
-                println!("  {} - Deactivate environment", "sbenv deactivate".yellow());
-                println!("  {} - Remove an environment", "sbenv remove".yellow());
-                println!(
-                    "  {} - Install shell functions for easier use",
-                    "sbenv install-shell".yellow()
-                );
-                println!();
-                println!("Daemon commands:");
-                println!("  {} - Start SyftBox daemon", "sbenv start".yellow());
-                println!("  {} - Stop daemon", "sbenv stop".yellow());
-                println!("  {} - Check daemon status", "sbenv status".yellow());
-                println!("  {} - View daemon logs", "sbenv logs".yellow());
-                println!("  {} - Restart daemon", "sbenv restart".yellow());
-                println!("  {} - Login to SyftBox", "sbenv login".yellow());
-                println!();
-                println!("Use {} for more information", "sbenv --help".cyan());
-            }
+    }
+
+    #[test]
+    fn test_parse_git_spec() {
+        assert_eq!(
+            parse_git_spec("git:https://github.com/OpenMined/syftbox.git@fix/sync").unwrap(),
+            (
+                "https://github.com/OpenMined/syftbox.git".to_string(),
+                "fix/sync".to_string()
+            )
+        );
+        assert_eq!(
+            parse_git_spec("git:git@github.com:OpenMined/syftbox.git").unwrap(),
+            (
+                "git@github.com:OpenMined/syftbox.git".to_string(),
+                "HEAD".to_string()
+            )
+        );
+        assert_eq!(
+            parse_git_spec("git:@v0.9.0").unwrap(),
+            (DEFAULT_GIT_REPO.to_string(), "v0.9.0".to_string())
+        );
+        assert!(parse_git_spec("git:--upload-pack=touch /tmp/x").is_err());
+        assert!(parse_git_spec("git:@--output=/tmp/x").is_err());
+        assert!(is_git_spec("git:@main"));
+        assert!(!is_git_spec("0.8.5"));
+    }
+
+    #[test]
+    fn test_newest_matching_range_specs() {
+        let versions: Vec<String> = ["0.8.4", "0.8.7", "0.9.0-rc.1", "0.9.1", "1.0.0-beta.2"]
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert!(is_range_spec("latest"));
+        assert!(is_range_spec("^0.8"));
+        assert!(is_range_spec(">=0.8.5,<0.9"));
+        assert!(!is_range_spec("0.8.5"));
+        assert!(!is_range_spec("/usr/local/bin/syftbox"));
+        assert!(!is_range_spec("git:@main"));
+
+        assert_eq!(
+            newest_matching("latest", &versions).as_deref(),
+            Some("0.9.1")
+        );
+        assert_eq!(newest_matching("^0.8", &versions).as_deref(), Some("0.8.7"));
+        assert_eq!(
+            newest_matching("~0.8.4", &versions).as_deref(),
+            Some("0.8.7")
+        );
+        assert_eq!(
+            newest_matching(">=0.8.5,<0.9", &versions).as_deref(),
+            Some("0.8.7")
+        );
+        assert_eq!(newest_matching("^2", &versions), None);
+    }
+
+    #[test]
+    fn test_pin_file_lookup() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path().join("project");
+        let nested = project.join("envs").join("alice");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(project.join("bin")).unwrap();
+        fs::write(project.join("bin").join("syftbox"), "").unwrap();
+
+        assert_eq!(find_pin_file(&nested), None);
+        fs::write(project.join(PIN_FILE), "# team syftbox\n\n^0.8\n").unwrap();
+        let pin = find_pin_file(&nested).unwrap();
+        assert_eq!(pin, project.join(PIN_FILE));
+        assert_eq!(read_pin_file(&pin).unwrap(), "^0.8");
+        fs::write(&pin, "latest\n").unwrap();
+        assert_eq!(read_pin_file(&pin).unwrap(), "latest");
+
+        // A checked-out project must not be able to point sbenv at code to run
+        for spec in [
+            "bin/syftbox",
+            "/usr/bin/syftbox",
+            "git:https://example.com/evil.git@main",
+            "https://example.com/syftbox_linux_amd64.tar.gz",
+        ] {
+            fs::write(&pin, format!("{}\n", spec)).unwrap();
+            assert!(read_pin_file(&pin).is_err(), "{} was accepted", spec);
         }
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_pin_file_resolves_from_cache_without_install() {
+        let _guard = HOME_MUTEX.lock().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        env::set_var("HOME", temp_dir.path());
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use std::sync::Mutex;
-    use tempfile::TempDir;
+        for v in ["0.8.5", "0.9.0"] {
+            let dir = get_binaries_dir().join(v);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("syftbox"), "").unwrap();
+        }
+        // Any attempt to list releases would fail against this source
+        save_global_config(&GlobalConfig {
+            release_source: Some(format!("file://{}", temp_dir.path().join("none").display())),
+            ..Default::default()
+        })
+        .unwrap();
+        let env_dir = temp_dir.path().join("project").join("env");
+        let config_path = env_dir.join(".syftbox").join("config.json");
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+        let config = SyftBoxConfig {
+            data_dir: env_dir.to_string_lossy().to_string(),
+            email: "a@example.com".to_string(),
+            server_url: "https://test.server".to_string(),
+            client_url: None,
+            client_token: None,
+            refresh_token: None,
+            dev_mode: false,
+        };
+        fs::write(&config_path, serde_json::to_string(&config).unwrap()).unwrap();
+        let pin = temp_dir.path().join("project").join(PIN_FILE);
 
-    // Use a mutex to ensure tests that modify HOME don't run concurrently
-    static HOME_MUTEX: Mutex<()> = Mutex::new(());
+        fs::write(&pin, "latest\n").unwrap();
+        let (bin, ver) = resolve_binary_for_env(&config_path, true).unwrap();
+        assert_eq!(bin, get_binaries_dir().join("0.9.0").join("syftbox"));
+        assert_eq!(ver.as_deref(), Some("0.9.0"));
+
+        fs::write(&pin, "^1\n").unwrap();
+        let err = resolve_binary_for_env(&config_path, true).unwrap_err();
+        assert!(err.to_string().contains("not installed yet"), "{}", err);
+
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
+        }
+    }
 
     #[test]
-    fn test_find_available_port_with_empty_registry() {
+    fn test_legacy_registry_entries_keep_explicit_binary() {
         let _guard = HOME_MUTEX.lock().unwrap();
-        // Create a temporary directory for testing
         let temp_dir = TempDir::new().unwrap();
         let original_home = env::var("HOME").ok();
         env::set_var("HOME", temp_dir.path());
 
-        // Test finding a port when registry is empty
-        let port = find_available_port().unwrap();
-        assert!((7939..=7999).contains(&port));
+        let bins = get_binaries_dir();
+        for v in ["0.8.5", "0.9.0"] {
+            fs::create_dir_all(bins.join(v)).unwrap();
+            fs::write(bins.join(v).join("syftbox"), "").unwrap();
+        }
+        save_global_config(&GlobalConfig {
+            default_binary: Some("0.9.0".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        // Written by sbenv before binary_pinned existed
+        let entry = |bin: &str| {
+            serde_json::json!({
+                "path": "/env", "email": "a@example.com", "port": 7938, "name": "env",
+                "binary": bins.join(bin).join("syftbox"),
+            })
+        };
+        let registry = serde_json::json!({"environments": {
+            "explicit": entry("0.8.5"),
+            "inherited": entry("0.9.0"),
+        }});
+        fs::create_dir_all(get_registry_path().parent().unwrap()).unwrap();
+        fs::write(get_registry_path(), registry.to_string()).unwrap();
+
+        let loaded = load_registry().unwrap();
+        assert!(loaded.environments["explicit"].binary_pinned);
+        assert!(!loaded.environments["inherited"].binary_pinned);
+
+        // The result is saved on first load, so a later default change doesn't flip it
+        let saved: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(get_registry_path()).unwrap()).unwrap();
+        assert_eq!(saved["environments"]["explicit"]["binary_pinned"], true);
+        assert_eq!(saved["environments"]["inherited"]["binary_pinned"], false);
+        save_global_config(&GlobalConfig {
+            default_binary: Some("0.8.5".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        let reloaded = load_registry().unwrap();
+        assert!(reloaded.environments["explicit"].binary_pinned);
+        assert!(!reloaded.environments["inherited"].binary_pinned);
 
-        // Restore original HOME
         if let Some(home) = original_home {
             env::set_var("HOME", home);
         }
     }
 
     #[test]
-    fn test_find_available_port_with_used_ports() {
+    fn test_snapshot_and_restore_syftbox_state() {
         let _guard = HOME_MUTEX.lock().unwrap();
         let temp_dir = TempDir::new().unwrap();
         let original_home = env::var("HOME").ok();
         env::set_var("HOME", temp_dir.path());
 
-        // Create a registry with some used ports
-        let mut registry = EnvRegistry {
-            environments: HashMap::new(),
-        };
+        let env_dir = temp_dir.path().join("env");
+        let syftbox_dir = env_dir.join(".syftbox");
+        fs::create_dir_all(syftbox_dir.join("home").join(".cache")).unwrap();
+        fs::create_dir_all(syftbox_dir.join("home").join(".syftbox")).unwrap();
+        fs::write(syftbox_dir.join("config.json"), "old").unwrap();
+        fs::write(syftbox_dir.join("home/.syftbox/state.db"), "v1").unwrap();
+        fs::write(syftbox_dir.join("home/.cache/blob"), "cache").unwrap();
+        fs::write(syftbox_dir.join("daemon.log"), "old log").unwrap();
+        fs::write(syftbox_dir.join("syftbox.pid"), "123").unwrap();
+
+        let snapshot = snapshot_syftbox_state(&env_dir, "a@example.com@/env").unwrap();
+        assert!(snapshot.join("home/.syftbox/state.db").exists());
+        assert!(!snapshot.join("daemon.log").exists());
+        assert!(!snapshot.join("syftbox.pid").exists());
+        assert!(!snapshot.join("home/.cache").exists());
+
+        // The new binary migrates state and writes new files
+        fs::write(syftbox_dir.join("home/.syftbox/state.db"), "v2").unwrap();
+        fs::write(syftbox_dir.join("home/.syftbox/new.db"), "v2").unwrap();
+        fs::write(syftbox_dir.join("daemon.log"), "panic: boom").unwrap();
+
+        restore_syftbox_state(&env_dir, &snapshot).unwrap();
+        assert_eq!(
+            fs::read_to_string(syftbox_dir.join("home/.syftbox/state.db")).unwrap(),
+            "v1"
+        );
+        assert!(!syftbox_dir.join("home/.syftbox/new.db").exists());
+        assert_eq!(
+            fs::read_to_string(syftbox_dir.join("config.json")).unwrap(),
+            "old"
+        );
+        // Logs of the failed start are kept for inspection
+        assert_eq!(
+            fs::read_to_string(syftbox_dir.join("daemon.log")).unwrap(),
+            "panic: boom"
+        );
 
-        // Add environments with specific ports
-        for i in 0..5 {
-            let env_info = EnvInfo {
-                path: format!("/test/path{}", i),
-                email: format!("test{}@example.com", i),
-                port: 7940 + i as u16,
-                name: format!("test{}", i),
-                server_url: "https://test.server".to_string(),
-                dev_mode: false,
-                binary: None,
-                binary_version: None,
-                binary_hash: None,
-                binary_os: None,
-                binary_arch: None,
-            };
-            registry.environments.insert(format!("test{}", i), env_info);
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
         }
+    }
 
-        save_registry(&registry).unwrap();
+    #[test]
+    fn test_verify_binary_integrity() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin = temp_dir.path().join("syftbox");
+        fs::write(&bin, "original build").unwrap();
+        let mut info = EnvInfo {
+            path: "/env".to_string(),
+            email: "a@example.com".to_string(),
+            port: 7938,
+            name: "env".to_string(),
+            server_url: String::new(),
+            dev_mode: false,
+            binary: Some(bin.to_string_lossy().to_string()),
+            binary_version: None,
+            binary_hash: None,
+            binary_os: None,
+            binary_arch: None,
+            release_sha256: None,
+            binary_spec: None,
+            binary_pinned: true,
+            binary_sha256: None,
+        };
+        // Without a recorded hash, --strict refuses and points at re-pinning
+        let err = verify_binary_integrity(&info, &bin, IntegrityCheck::Strict).unwrap_err();
+        assert!(err.to_string().contains("sbenv edit --binary"));
+        assert!(verify_binary_integrity(&info, &bin, IntegrityCheck::Warn).is_ok());
+
+        apply_binary_details(&mut info, &bin);
+        assert!(verify_binary_integrity(&info, &bin, IntegrityCheck::Strict).is_ok());
+
+        fs::write(&bin, "replaced build").unwrap();
+        assert!(verify_binary_integrity(&info, &bin, IntegrityCheck::Warn).is_ok());
+        let err = verify_binary_integrity(&info, &bin, IntegrityCheck::Strict).unwrap_err();
+        assert!(err.to_string().contains("changed since it was pinned"));
+    }
 
-        // Find an available port
-        let port = find_available_port().unwrap();
+    #[test]
+    fn test_concurrent_installs_share_one_download() {
+        let _guard = HOME_MUTEX.lock().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        env::set_var("HOME", temp_dir.path());
 
-        // Verify the port is in range and not in the used ports
-        assert!((7939..=7999).contains(&port));
-        let used_ports: Vec<u16> = (7940..7945).collect();
-        assert!(!used_ports.contains(&port));
+        let (os, arch) = current_os_arch();
+        let asset = format!("syftbox_0.8.5_{}_{}.tar.gz", os, arch);
+        let release_dir = temp_dir.path().join("mirror").join("v0.8.5");
+        fs::create_dir_all(&release_dir).unwrap();
+        let script = b"#!/bin/sh\necho 'syftbox version 0.8.5'\n";
+        write_syftbox_tar_gz(&release_dir.join(&asset), script);
+        let digest = sha256_file(&release_dir.join(&asset)).unwrap();
+        fs::write(
+            release_dir.join("checksums.txt"),
+            format!("{}  {}\n", digest, asset),
+        )
+        .unwrap();
+        save_global_config(&GlobalConfig {
+            default_binary: None,
+            release_source: Some(format!(
+                "file://{}",
+                temp_dir.path().join("mirror").display()
+            )),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| thread::spawn(|| ensure_syftbox_version("0.8.5", true, false)))
+            .collect();
+        let bins: Vec<PathBuf> = handles
+            .into_iter()
+            .map(|h| h.join().unwrap().unwrap())
+            .collect();
+        let expected = get_binaries_dir().join("0.8.5").join("syftbox");
+        assert!(bins.iter().all(|b| *b == expected));
+        assert_eq!(read_verified_digest(&expected), Some(digest));
+        // No staging dirs are left behind
+        let entries: Vec<String> = fs::read_dir(get_binaries_dir())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(entries, vec!["0.8.5".to_string()]);
 
         if let Some(home) = original_home {
             env::set_var("HOME", home);
+        } else {
+            env::remove_var("HOME");
         }
     }
 
     #[test]
-    fn test_register_and_unregister_environment() {
+    fn test_install_for_other_target() {
         let _guard = HOME_MUTEX.lock().unwrap();
         let temp_dir = TempDir::new().unwrap();
         let original_home = env::var("HOME").ok();
         env::set_var("HOME", temp_dir.path());
 
-        let test_path = temp_dir.path().join("test_env");
-        fs::create_dir(&test_path).unwrap();
+        assert_eq!(
+            Target::parse("macos/aarch64").unwrap(),
+            Target::parse("darwin/arm64").unwrap()
+        );
+        assert!(Target::parse("linux/amd64")
+            .unwrap()
+            .matches("linux", "x86_64"));
+        assert!(Target::parse("linux").is_err());
+        assert!(Target::parse("plan9/amd64").is_err());
+
+        // A host and a foreign build of the same release
+        let host = Target::host();
+        let other = if host.os == "darwin" {
+            Target::parse("linux/arm64").unwrap()
+        } else {
+            Target::parse("darwin/arm64").unwrap()
+        };
+        let release_dir = temp_dir.path().join("mirror").join("v0.8.5");
+        fs::create_dir_all(&release_dir).unwrap();
+        let mut checksums = String::new();
+        for (target, script) in [
+            (&host, &b"#!/bin/sh\necho 'syftbox version 0.8.5'\n"[..]),
+            (&other, &b"foreign"[..]),
+        ] {
+            let asset = format!("syftbox_0.8.5_{}_{}.tar.gz", target.os, target.arch);
+            write_syftbox_tar_gz(&release_dir.join(&asset), script);
+            let digest = sha256_file(&release_dir.join(&asset)).unwrap();
+            checksums.push_str(&format!("{}  {}\n", digest, asset));
+        }
+        fs::write(release_dir.join("checksums.txt"), checksums).unwrap();
+        env::set_var(
+            "SBENV_RELEASE_SOURCE",
+            format!("file://{}", temp_dir.path().join("mirror").display()),
+        );
 
-        let config = SyftBoxConfig {
-            data_dir: test_path.to_string_lossy().to_string(),
-            email: "test@example.com".to_string(),
-            server_url: "https://test.server".to_string(),
-            client_url: Some("http://127.0.0.1:7950".to_string()),
-            client_token: None,
-            refresh_token: None,
-            dev_mode: false,
+        let foreign = ensure_syftbox_target("0.8.5", &other, true, false).unwrap();
+        assert_eq!(
+            foreign,
+            get_binaries_dir()
+                .join("0.8.5")
+                .join(other.dir_name())
+                .join("syftbox")
+        );
+        assert_eq!(fs::read(&foreign).unwrap(), b"foreign");
+        // Foreign builds don't make the version usable here, but aren't leftovers either
+        assert!(get_cached_syftbox_versions().is_empty());
+        assert_eq!(cached_target_builds("0.8.5"), vec![other.clone()]);
+        let later = std::time::SystemTime::now() + STALE_TMP_AGE * 2;
+        let mut refs = HashMap::new();
+        refs.insert("0.8.5".to_string(), vec!["test".to_string()]);
+        assert!(gc_candidates(&refs, 30, later).is_empty());
+
+        // Installing the host build keeps the foreign one
+        let native = ensure_syftbox_version("0.8.5", true, false).unwrap();
+        assert_eq!(native, get_binaries_dir().join("0.8.5").join("syftbox"));
+        assert!(read_verified_digest(&native).is_some());
+        assert!(foreign.exists());
+        assert_eq!(get_cached_syftbox_versions(), vec!["0.8.5".to_string()]);
+
+        env::remove_var("SBENV_RELEASE_SOURCE");
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
+        } else {
+            env::remove_var("HOME");
+        }
+    }
+
+    #[test]
+    fn test_fish_activation_script() {
+        let vars = vec![
+            ("SYFTBOX_EMAIL", "a@b.c".to_string()),
+            ("SYFTBOX_DATA_DIR", "/srv/it's here".to_string()),
+            ("SYFTBOX_ENV_ACTIVE", "1".to_string()),
+        ];
+        let script = fish_activation_script(&vars, "/srv/it's here");
+        assert!(script.contains("set -gx SYFTBOX_EMAIL 'a@b.c'\n"));
+        assert!(script.contains("set -gx SYFTBOX_DATA_DIR '/srv/it\\'s here'\n"));
+        assert!(script.contains("set -gx VIRTUAL_ENV '/srv/it\\'s here'\n"));
+        assert!(!script.contains("export "));
+        assert_eq!(fish_quote(r"a\b"), r"'a\\b'");
+
+        let deactivate = fish_deactivation_script();
+        for name in ACTIVATION_VARS {
+            assert!(deactivate.contains(&format!("set -e {}\n", name)));
+        }
+        let functions = get_fish_shell_functions();
+        assert!(functions.contains("function _sbenv_auto_hook --on-variable PWD"));
+        assert!(functions.contains("command sbenv activate --shell fish --quiet"));
+    }
+
+    #[test]
+    fn test_nu_and_pwsh_activation_scripts() {
+        let act = Activation {
+            vars: vec![
+                ("SYFTBOX_EMAIL", "a@b.c".to_string()),
+                ("SYFTBOX_DATA_DIR", "/srv/it's here".to_string()),
+                ("SYFTBOX_ENV_NAME", "a@b.c".to_string()),
+            ],
+            data_dir: "/srv/it's here".to_string(),
+            cd_to: None,
         };
 
-        // Register environment
-        register_environment(&test_path, &config).unwrap();
+        let record: serde_json::Value =
+            serde_json::from_str(&nu_activation_script(&act, Some("/venv".into()))).unwrap();
+        assert_eq!(record["SYFTBOX_DATA_DIR"], "/srv/it's here");
+        assert_eq!(record["VIRTUAL_ENV"], "/srv/it's here");
+        assert_eq!(record["SYFTBOX_OLD_VIRTUAL_ENV"], "/venv");
+        let changes: serde_json::Value =
+            serde_json::from_str(&nu_deactivation_script(Some("/venv".into()))).unwrap();
+        let hidden = changes["hide"].as_array().unwrap();
+        for name in ACTIVATION_VARS
+            .iter()
+            .chain(["SYFTBOX_OLD_VIRTUAL_ENV"].iter())
+        {
+            assert!(hidden.iter().any(|h| h == name), "{} not hidden", name);
+        }
+        assert_eq!(changes["set"]["VIRTUAL_ENV"], "/venv");
+        let changes: serde_json::Value =
+            serde_json::from_str(&nu_deactivation_script(None)).unwrap();
+        assert!(changes["hide"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|h| h == "VIRTUAL_ENV"));
+
+        let script = pwsh_activation_script(&act);
+        assert!(script.contains("$env:SYFTBOX_DATA_DIR = '/srv/it''s here'\n"));
+        assert!(script.contains("function global:prompt"));
+        let deactivate = pwsh_deactivation_script();
+        for name in ACTIVATION_VARS {
+            assert!(deactivate.contains(&format!("Remove-Item Env:{} ", name)));
+        }
 
-        // Verify it was registered using the correct key
-        let registry = load_registry().unwrap();
-        let env_key = generate_env_key(&test_path, "test@example.com");
-        assert!(registry.environments.contains_key(&env_key));
-        let env_info = registry.environments.get(&env_key).unwrap();
-        assert_eq!(env_info.email, "test@example.com");
-        assert_eq!(env_info.port, 7950);
+        // bash and zsh share one script that checks $ZSH_VERSION at runtime
+        assert_eq!(
+            Shell::Zsh.activation_script(&act),
+            Shell::Bash.activation_script(&act)
+        );
+        assert!(Shell::Bash
+            .deactivation_script()
+            .contains("unset SYFTBOX_ENV_ACTIVE\n"));
+    }
 
-        // Unregister environment
-        unregister_environment(&test_path).unwrap();
+    #[test]
+    fn test_dynamic_completions() {
+        let _guard = HOME_MUTEX.lock().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        env::set_var("HOME", temp_dir.path());
 
-        // Verify it was removed
-        let registry = load_registry().unwrap();
-        assert!(!registry.environments.contains_key(&env_key));
+        for (dir, email) in [("alice", "alice@example.com"), ("bob", "bob@example.com")] {
+            let path = temp_dir.path().join(dir);
+            fs::create_dir(&path).unwrap();
+            let config = SyftBoxConfig {
+                data_dir: path.to_string_lossy().to_string(),
+                email: email.to_string(),
+                server_url: "https://test.server".to_string(),
+                client_url: None,
+                client_token: None,
+                refresh_token: None,
+                dev_mode: false,
+            };
+            register_environment(&path, &config).unwrap();
+        }
+        for ver in ["0.8.5", "0.9.0"] {
+            let dir = get_binaries_dir().join(ver);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("syftbox"), "").unwrap();
+        }
+
+        let values = |c: Vec<CompletionCandidate>| {
+            c.iter()
+                .map(|c| c.get_value().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            values(complete_env_selector(OsStr::new(""))),
+            ["alice", "alice@example.com", "bob", "bob@example.com"]
+        );
+        assert_eq!(
+            values(complete_env_selector(OsStr::new("bob@"))),
+            ["bob@example.com"]
+        );
+        let alice = temp_dir.path().join("alice").to_string_lossy().to_string();
+        assert_eq!(values(complete_env_paths(OsStr::new(&alice))), [alice]);
+        assert_eq!(
+            values(complete_cached_versions(OsStr::new("0.8"))),
+            ["0.8.5"]
+        );
+        assert!(values(complete_binary_spec(OsStr::new(""))).contains(&"latest".to_string()));
 
         if let Some(home) = original_home {
             env::set_var("HOME", home);
@@ -3402,73 +8971,210 @@ mod tests {
     }
 
     #[test]
-    fn test_load_registry_creates_empty_if_not_exists() {
+    fn test_shell_block_replace_and_remove() {
+        let user = "export PATH=\"$HOME/bin:$PATH\"\n";
+        let after = "alias ll='ls -l'\n";
+
+        // Unmarked install from older versions, which appended the hook twice
+        let legacy = format!(
+            "{}{}\n{}\n{}{}",
+            user,
+            LEGACY_SHELL_FUNCTIONS,
+            LEGACY_AUTO_ACTIVATION_BLOCK,
+            LEGACY_AUTO_ACTIVATION_BLOCK,
+            after
+        );
+        let upgraded = with_shell_block(&legacy).unwrap();
+        assert_eq!(upgraded.matches(SHELL_BLOCK_BEGIN).count(), 1);
+        assert_eq!(upgraded.matches("_sbenv_auto_hook()").count(), 1);
+        assert!(!upgraded.contains("📦 $SYFTBOX_EMAIL"));
+        assert!(upgraded.starts_with(user));
+        assert!(upgraded.ends_with(after));
+        assert_eq!(with_shell_block(&upgraded).unwrap(), upgraded);
+
+        // A block from another version is replaced where it is
+        let old_block = format!(
+            "{} (v0.0.1) >>>\nsbenv() {{ :; }}\n{}\n",
+            SHELL_BLOCK_BEGIN, SHELL_BLOCK_END
+        );
+        let replaced = with_shell_block(&format!("{}{}{}", user, old_block, after)).unwrap();
+        assert_eq!(replaced, format!("{}{}{}", user, get_shell_block(), after));
+
+        let (rest, first) = remove_shell_blocks(&replaced).unwrap();
+        assert_eq!(rest, format!("{}{}", user, after));
+        assert_eq!(first, Some(user.len()));
+        assert_eq!(with_shell_block("").unwrap(), get_shell_block());
+
+        // Never guess where a block without its end marker stops
+        let broken = format!("{}{} (v1) >>>\n{}", user, SHELL_BLOCK_BEGIN, after);
+        assert!(remove_shell_blocks(&broken).is_err());
+    }
+
+    #[test]
+    fn test_activation_quotes_hostile_values() {
+        let temp_dir = TempDir::new().unwrap();
+        let email = "a'b\"$(touch pwned)`touch pwned`%n@x.y";
+        let data_dir = "/srv/$HOME/`touch pwned`/\"q\" 'a' \\n\nnew line";
+        let act = Activation {
+            vars: vec![
+                ("SYFTBOX_EMAIL", email.to_string()),
+                ("SYFTBOX_DATA_DIR", data_dir.to_string()),
+                ("SYFTBOX_ENV_NAME", email.to_string()),
+            ],
+            data_dir: data_dir.to_string(),
+            cd_to: None,
+        };
+
+        // bash expands PS1 again for ${PS1@P}, just like when drawing the prompt
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(r#"PS1='> '; eval "$SB_SCRIPT"; printf '%s\0' "$SYFTBOX_EMAIL" "$VIRTUAL_ENV" "${PS1@P}""#)
+            .env("SB_SCRIPT", posix_activation_script(&act))
+            .env_remove("PROMPT_COMMAND")
+            .current_dir(temp_dir.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let values: Vec<&str> = stdout.split('\0').collect();
+        assert_eq!(values[0], email);
+        assert_eq!(values[1], data_dir);
+        assert!(values[2].contains(&format!("📦 ({}) > ", email)));
+        assert!(!temp_dir.path().join("pwned").exists());
+
+        assert_eq!(fish_quote(r"it's \'"), r"'it\'s \\\''");
+        assert_eq!(pwsh_quote("it's ‘x’ $env:HOME"), "'it''s ‘‘x’’ $env:HOME'");
+        let record: serde_json::Value =
+            serde_json::from_str(&nu_activation_script(&act, None)).unwrap();
+        assert_eq!(record["SYFTBOX_EMAIL"], email);
+        assert_eq!(record["VIRTUAL_ENV"], data_dir);
+    }
+
+    #[test]
+    fn test_env_shell_startup_files() {
+        use std::io::Write;
         let _guard = HOME_MUTEX.lock().unwrap();
         let temp_dir = TempDir::new().unwrap();
         let original_home = env::var("HOME").ok();
+        let original_zdotdir = env::var("ZDOTDIR").ok();
         env::set_var("HOME", temp_dir.path());
+        env::remove_var("ZDOTDIR");
+        let home = temp_dir.path().to_string_lossy().to_string();
+        fs::write(temp_dir.path().join(".bashrc"), "export SB_USER_RC=bash\n").unwrap();
+        fs::write(temp_dir.path().join(".zshrc"), "export SB_USER_RC=zsh\n").unwrap();
+        let act = Activation {
+            vars: vec![
+                ("SYFTBOX_EMAIL", "a@x.y".to_string()),
+                ("SYFTBOX_ENV_NAME", "a@x.y".to_string()),
+            ],
+            data_dir: "/srv/a".to_string(),
+            cd_to: None,
+        };
+        let probe = r#"printf '%s|%s|%s|%s|%s\n' "$SB_USER_RC" "$SYFTBOX_EMAIL" "$SBENV_SHELL" "$ZDOTDIR" "${PS1@P}""#;
+        let run = |mut cmd: Command, input: &str| {
+            let mut child = cmd
+                .env_remove("PROMPT_COMMAND")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap();
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(input.as_bytes())
+                .unwrap();
+            let output = child.wait_with_output().unwrap();
+            assert!(output.status.success(), "{:?}", output);
+            String::from_utf8(output.stdout).unwrap()
+        };
 
-        // Load registry when it doesn't exist
-        let registry = load_registry().unwrap();
-        assert!(registry.environments.is_empty());
-
+        // bash runs the generated rc file: the user's .bashrc, then the activation
+        let rc_dir = temp_dir.path().join("bash-rc");
+        let mut cmd = env_shell_command("bash", &act, &rc_dir).unwrap();
+        cmd.arg("-i");
+        let out = run(cmd, &format!("{}\nexit\n", probe));
+        assert!(out.starts_with("bash|a@x.y|1||"), "{}", out);
+        assert!(out.contains("📦 (a@x.y) "), "{}", out);
+
+        // zsh goes through the ZDOTDIR trampoline back to the user's files. zsh sources
+        // .zshenv then .zshrc from $ZDOTDIR; replay that with the same environment.
+        let rc_dir = temp_dir.path().join("zsh-rc");
+        let zsh = env_shell_command("/usr/bin/zsh", &act, &rc_dir).unwrap();
+        let mut cmd = Command::new("bash");
+        for (name, value) in zsh.get_envs() {
+            match value {
+                Some(value) => cmd.env(name, value),
+                None => cmd.env_remove(name),
+            };
+        }
+        let replay = format!(
+            ". \"$ZDOTDIR/.zshenv\"; . \"$ZDOTDIR/.zshrc\"; {}; echo \"${{_SBENV_ZDOTDIR-unset}}\"\n",
+            probe
+        );
+        let out = run(cmd, &replay);
+        assert!(
+            out.starts_with(&format!("zsh|a@x.y|1|{}|", home)),
+            "{}",
+            out
+        );
+        assert!(out.ends_with("\nunset\n"), "{}", out);
+
+        // Shells without an emitter (sh, dash) get the variables but no startup files
+        let rc_dir = temp_dir.path().join("sh-rc");
+        let cmd = env_shell_command("/bin/sh", &act, &rc_dir).unwrap();
+        assert_eq!(cmd.get_args().count(), 0);
+        assert!(!rc_dir.exists());
+        assert!(cmd
+            .get_envs()
+            .any(|(k, v)| k == "SYFTBOX_EMAIL" && v == Some(OsStr::new("a@x.y"))));
+
+        match original_zdotdir {
+            Some(dir) => env::set_var("ZDOTDIR", dir),
+            None => env::remove_var("ZDOTDIR"),
+        }
         if let Some(home) = original_home {
             env::set_var("HOME", home);
         }
     }
 
     #[test]
-    fn test_get_used_ports() {
+    fn test_resolve_env_selector() {
         let _guard = HOME_MUTEX.lock().unwrap();
         let temp_dir = TempDir::new().unwrap();
         let original_home = env::var("HOME").ok();
         env::set_var("HOME", temp_dir.path());
 
-        let mut registry = EnvRegistry {
-            environments: HashMap::new(),
-        };
-
-        // Add test environments
-        registry.environments.insert(
-            "env1".to_string(),
-            EnvInfo {
-                path: "/path1".to_string(),
-                email: "test1@example.com".to_string(),
-                port: 7940,
-                name: "env1".to_string(),
-                server_url: "https://test.server".to_string(),
-                dev_mode: false,
-                binary: None,
-                binary_version: None,
-                binary_hash: None,
-                binary_os: None,
-                binary_arch: None,
-            },
-        );
-        registry.environments.insert(
-            "env2".to_string(),
-            EnvInfo {
-                path: "/path2".to_string(),
-                email: "test2@example.com".to_string(),
-                port: 7945,
-                name: "env2".to_string(),
+        // Two environments for the same datasite, in different directories
+        for dir in ["work", "lab"] {
+            let path = temp_dir.path().join(dir);
+            fs::create_dir(&path).unwrap();
+            let config = SyftBoxConfig {
+                data_dir: path.to_string_lossy().to_string(),
+                email: "me@example.com".to_string(),
                 server_url: "https://test.server".to_string(),
+                client_url: None,
+                client_token: None,
+                refresh_token: None,
                 dev_mode: false,
-                binary: None,
-                binary_version: None,
-                binary_hash: None,
-                binary_os: None,
-                binary_arch: None,
-            },
-        );
-
-        save_registry(&registry).unwrap();
+            };
+            register_environment(&path, &config).unwrap();
+        }
+        let lab = temp_dir.path().join("lab");
 
-        // Get used ports
-        let used_ports = get_used_ports().unwrap();
-        assert_eq!(used_ports.len(), 2);
-        assert!(used_ports.contains(&7940));
-        assert!(used_ports.contains(&7945));
+        assert_eq!(
+            resolve_env_selector("lab").unwrap().path,
+            lab.to_string_lossy()
+        );
+        let by_path = resolve_env_selector(&lab.join(".").to_string_lossy()).unwrap();
+        assert_eq!(by_path.path, lab.to_string_lossy());
+        let err = resolve_env_selector("me@example.com")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("matches several environments"), "{}", err);
+        assert!(err.contains(&*lab.to_string_lossy()));
+        assert!(resolve_env_selector("nobody@example.com").is_err());
 
         if let Some(home) = original_home {
             env::set_var("HOME", home);
@@ -3476,47 +9182,99 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_port_from_client_url() {
+    fn test_activate_by_name_with_cd() {
+        let _guard = HOME_MUTEX.lock().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        env::set_var("HOME", temp_dir.path());
+
+        let root = temp_dir.path().join("lab 'b' $(x)");
+        fs::create_dir_all(root.join(".syftbox")).unwrap();
         let config = SyftBoxConfig {
-            data_dir: "/test".to_string(),
-            email: "test@example.com".to_string(),
+            data_dir: root.to_string_lossy().to_string(),
+            email: "me@example.com".to_string(),
             server_url: "https://test.server".to_string(),
-            client_url: Some("http://127.0.0.1:7950".to_string()),
+            client_url: None,
             client_token: None,
             refresh_token: None,
             dev_mode: false,
         };
-
-        let port = config
-            .client_url
-            .as_deref()
-            .and_then(|u| u.rsplit(':').next())
-            .and_then(|p| p.parse::<u16>().ok())
+        fs::write(
+            root.join(".syftbox").join("config.json"),
+            serde_json::to_string(&config).unwrap(),
+        )
+        .unwrap();
+        register_environment(&root, &config).unwrap();
+
+        let act = selected_activation(Some("lab 'b' $(x)"), true, true).unwrap();
+        assert!(act
+            .vars
+            .contains(&("SYFTBOX_EMAIL", "me@example.com".to_string())));
+        assert_eq!(act.cd_to.as_deref(), Some(&*root.to_string_lossy()));
+
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(r#"eval "$SB_SCRIPT"; printf '%s' "$PWD""#)
+            .env("SB_SCRIPT", Shell::Bash.activation_script(&act))
+            .output()
             .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            root.to_string_lossy()
+        );
+        let record: serde_json::Value =
+            serde_json::from_str(&Shell::Nu.activation_script(&act)).unwrap();
+        assert_eq!(record["SBENV_CD"], *root.to_string_lossy());
 
-        assert_eq!(port, 7950);
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
+        }
     }
 
     #[test]
-    fn test_parse_port_from_localhost_url() {
-        let config = SyftBoxConfig {
-            data_dir: "/test".to_string(),
-            email: "test@example.com".to_string(),
-            server_url: "https://test.server".to_string(),
-            client_url: Some("http://localhost:8080".to_string()),
-            client_token: None,
-            refresh_token: None,
-            dev_mode: false,
+    fn test_gc_candidates() {
+        let _guard = HOME_MUTEX.lock().unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let original_home = env::var("HOME").ok();
+        env::set_var("HOME", temp_dir.path());
+
+        let bin_dir = get_binaries_dir();
+        for ver in ["0.8.4", "0.8.5", "git-0123456789ab"] {
+            fs::create_dir_all(bin_dir.join(ver)).unwrap();
+            fs::write(bin_dir.join(ver).join("syftbox"), "").unwrap();
+        }
+        fs::create_dir_all(bin_dir.join("_tmp-1234")).unwrap();
+        fs::create_dir_all(bin_dir.join("0.9.0")).unwrap();
+        let mut refs: HashMap<String, Vec<String>> = HashMap::new();
+        refs.insert("0.8.5".to_string(), vec!["global default".to_string()]);
+
+        let names = |c: Vec<(PathBuf, String)>| {
+            c.into_iter()
+                .map(|(p, _)| p.file_name().unwrap().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
         };
+        // Fresh temp dirs may belong to an install that is still running
+        let now = std::time::SystemTime::now();
+        assert_eq!(
+            names(gc_candidates(&refs, 0, now)),
+            vec!["0.8.4", "git-0123456789ab"]
+        );
+        assert!(gc_candidates(&refs, 30, now).is_empty());
 
-        let port = config
-            .client_url
-            .as_deref()
-            .and_then(|u| u.rsplit(':').next())
-            .and_then(|p| p.parse::<u16>().ok())
-            .unwrap();
+        let later = now + Duration::from_secs(2 * 24 * 60 * 60);
+        assert_eq!(
+            names(gc_candidates(&refs, 1, later)),
+            vec!["0.8.4", "0.9.0", "_tmp-1234", "git-0123456789ab"]
+        );
+        // Unused versions younger than --days are kept; stale leftovers still go
+        assert_eq!(
+            names(gc_candidates(&refs, 30, later)),
+            vec!["0.9.0", "_tmp-1234"]
+        );
 
-        assert_eq!(port, 8080);
+        if let Some(home) = original_home {
+            env::set_var("HOME", home);
+        }
     }
 
     #[test]
@@ -3544,6 +9302,10 @@ mod tests {
                 binary_hash: None,
                 binary_os: None,
                 binary_arch: None,
+                release_sha256: None,
+                binary_spec: None,
+                binary_pinned: false,
+                binary_sha256: None,
             },
         );
         save_registry(&registry).unwrap();